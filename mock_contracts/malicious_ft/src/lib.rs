@@ -0,0 +1,202 @@
+/*!
+Malicious Fungible Token used to exercise the vault's reentrancy protection.
+NOTES:
+  - Behaves like a regular NEP-141 token until `attack` is called.
+  - `attack` makes this contract redeem its own vault shares. When the vault pays out through
+    `ft_transfer`, the token performs the transfer and then immediately calls back into the
+    vault's `redeem` with the same amount of shares, trying to get paid twice.
+  - The contract account itself must hold the vault shares used for the attack.
+*/
+use near_contract_standards::fungible_token::metadata::{
+    FungibleTokenMetadata, FungibleTokenMetadataProvider,
+};
+use near_contract_standards::fungible_token::{
+    FungibleToken, FungibleTokenCore, FungibleTokenResolver,
+};
+use near_contract_standards::storage_management::{
+    StorageBalance, StorageBalanceBounds, StorageManagement,
+};
+use near_sdk::borsh::BorshSerialize;
+use near_sdk::collections::LazyOption;
+use near_sdk::json_types::U128;
+use near_sdk::{
+    env, ext_contract, log, near, require, AccountId, BorshStorageKey, Gas, NearToken,
+    PanicOnDefault, Promise, PromiseOrValue,
+};
+
+const GAS_FOR_REDEEM: Gas = Gas::from_tgas(150);
+const GAS_FOR_REENTRANT_REDEEM: Gas = Gas::from_tgas(15);
+
+#[ext_contract(ext_vault)]
+pub trait Vault {
+    fn redeem(&mut self, shares: U128, receiver_id: Option<AccountId>, memo: Option<String>);
+}
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone)]
+pub struct Reentry {
+    vault_id: AccountId,
+    shares: U128,
+}
+
+#[derive(PanicOnDefault)]
+#[near(contract_state)]
+pub struct Contract {
+    token: FungibleToken,
+    metadata: LazyOption<FungibleTokenMetadata>,
+    reentry: Option<Reentry>,
+}
+
+#[derive(BorshSerialize, BorshStorageKey)]
+#[borsh(crate = "near_sdk::borsh")]
+enum StorageKey {
+    FungibleToken,
+    Metadata,
+}
+
+#[near]
+impl Contract {
+    #[init]
+    pub fn new(owner_id: AccountId, total_supply: U128, metadata: FungibleTokenMetadata) -> Self {
+        require!(!env::state_exists(), "Already initialized");
+        metadata.assert_valid();
+        let mut this = Self {
+            token: FungibleToken::new(StorageKey::FungibleToken),
+            metadata: LazyOption::new(StorageKey::Metadata, Some(&metadata)),
+            reentry: None,
+        };
+        this.token.internal_register_account(&owner_id);
+        this.token.internal_deposit(&owner_id, total_supply.into());
+
+        near_contract_standards::fungible_token::events::FtMint {
+            owner_id: &owner_id,
+            amount: total_supply,
+            memo: Some("new tokens are minted"),
+        }
+        .emit();
+
+        this
+    }
+
+    /// Redeems `shares` held by this contract on `vault_id`, arming the re-entrant redeem that
+    /// fires when the vault pays out through `ft_transfer`.
+    pub fn attack(&mut self, vault_id: AccountId, shares: U128) -> Promise {
+        self.reentry = Some(Reentry {
+            vault_id: vault_id.clone(),
+            shares,
+        });
+
+        ext_vault::ext(vault_id)
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .with_static_gas(GAS_FOR_REDEEM)
+            .redeem(shares, None, None)
+    }
+
+    pub fn reentry(&self) -> Option<Reentry> {
+        self.reentry.clone()
+    }
+}
+
+#[near]
+impl FungibleTokenCore for Contract {
+    #[payable]
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
+        self.token.ft_transfer(receiver_id, amount, memo);
+
+        let armed = self
+            .reentry
+            .as_ref()
+            .is_some_and(|reentry| reentry.vault_id == env::predecessor_account_id());
+        if armed {
+            let reentry = self.reentry.take().unwrap();
+            log!("Re-entering {} to redeem {}", reentry.vault_id, reentry.shares.0);
+
+            // Not returned on purpose: the vault's callback must not depend on this call
+            ext_vault::ext(reentry.vault_id)
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .with_static_gas(GAS_FOR_REENTRANT_REDEEM)
+                .redeem(reentry.shares, None, None);
+        }
+    }
+
+    #[payable]
+    fn ft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        self.token.ft_transfer_call(receiver_id, amount, memo, msg)
+    }
+
+    fn ft_total_supply(&self) -> U128 {
+        self.token.ft_total_supply()
+    }
+
+    fn ft_balance_of(&self, account_id: AccountId) -> U128 {
+        self.token.ft_balance_of(account_id)
+    }
+}
+
+#[near]
+impl FungibleTokenResolver for Contract {
+    #[private]
+    fn ft_resolve_transfer(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> U128 {
+        let (used_amount, burned_amount) =
+            self.token
+                .internal_ft_resolve_transfer(&sender_id, receiver_id, amount);
+        if burned_amount > 0 {
+            log!("Account @{} burned {}", sender_id, burned_amount);
+        }
+        used_amount.into()
+    }
+}
+
+#[near]
+impl StorageManagement for Contract {
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        self.token.storage_deposit(account_id, registration_only)
+    }
+
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<NearToken>) -> StorageBalance {
+        self.token.storage_withdraw(amount)
+    }
+
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        #[allow(unused_variables)]
+        if let Some((account_id, balance)) = self.token.internal_storage_unregister(force) {
+            log!("Closed @{} with {}", account_id, balance);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        self.token.storage_balance_bounds()
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.token.storage_balance_of(account_id)
+    }
+}
+
+#[near]
+impl FungibleTokenMetadataProvider for Contract {
+    fn ft_metadata(&self) -> FungibleTokenMetadata {
+        self.metadata.get().unwrap()
+    }
+}
@@ -0,0 +1,58 @@
+/*!
+Bridge the vault forwards redeemed assets to, used to check the assets and message arrive.
+NOTES:
+  - Records every `ft_on_transfer` call so tests can read them back through `transfers`.
+  - Refunds the whole amount when the message is `"reject"`, like a bridge refusing a transfer.
+  - Refunds `N` of the amount when the message is `"refund:N"`, like a bridge filling part of it.
+*/
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::json_types::U128;
+use near_sdk::{env, near, AccountId, PromiseOrValue};
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug)]
+pub struct BridgeTransfer {
+    pub token_id: AccountId,
+    pub sender_id: AccountId,
+    pub amount: U128,
+    pub msg: String,
+}
+
+#[derive(Default)]
+#[near(contract_state)]
+pub struct Contract {
+    transfers: Vec<BridgeTransfer>,
+}
+
+#[near]
+impl FungibleTokenReceiver for Contract {
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        if msg == "reject" {
+            return PromiseOrValue::Value(amount);
+        }
+        let refund = msg
+            .strip_prefix("refund:")
+            .and_then(|refund| refund.parse::<u128>().ok())
+            .map_or(0, |refund| refund.min(amount.0));
+
+        self.transfers.push(BridgeTransfer {
+            token_id: env::predecessor_account_id(),
+            sender_id,
+            amount,
+            msg,
+        });
+        PromiseOrValue::Value(U128(refund))
+    }
+}
+
+#[near]
+impl Contract {
+    pub fn transfers(&self) -> Vec<BridgeTransfer> {
+        self.transfers.clone()
+    }
+}
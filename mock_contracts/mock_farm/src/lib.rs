@@ -0,0 +1,41 @@
+/*!
+Farm vault shares are staked into, used to check deposits with `stake_to` land there.
+NOTES:
+  - Credits every `ft_on_transfer` to the sender's stake, readable through `staked_of`.
+  - Refunds the whole amount when the message is `"reject"`, like a farm refusing a stake.
+*/
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::json_types::U128;
+use near_sdk::{near, AccountId, PromiseOrValue};
+use std::collections::HashMap;
+
+#[derive(Default)]
+#[near(contract_state)]
+pub struct Contract {
+    staked: HashMap<AccountId, U128>,
+}
+
+#[near]
+impl FungibleTokenReceiver for Contract {
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        if msg == "reject" {
+            return PromiseOrValue::Value(amount);
+        }
+
+        let staked = self.staked.get(&sender_id).map_or(0, |staked| staked.0);
+        self.staked.insert(sender_id, U128(staked + amount.0));
+        PromiseOrValue::Value(U128(0))
+    }
+}
+
+#[near]
+impl Contract {
+    pub fn staked_of(&self, account_id: AccountId) -> U128 {
+        self.staked.get(&account_id).copied().unwrap_or(U128(0))
+    }
+}
@@ -0,0 +1,47 @@
+/*!
+Withdraw hook the vault notifies once a withdrawal settles, used to check the notified args.
+NOTES:
+  - Records every `on_vault_withdraw` call so tests can read them back through `calls`.
+*/
+use near_sdk::json_types::U128;
+use near_sdk::{near, AccountId};
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug)]
+pub struct HookCall {
+    pub owner: AccountId,
+    pub receiver: AccountId,
+    pub shares: U128,
+    pub assets: U128,
+    pub success: bool,
+}
+
+#[derive(Default)]
+#[near(contract_state)]
+pub struct Contract {
+    calls: Vec<HookCall>,
+}
+
+#[near]
+impl Contract {
+    pub fn on_vault_withdraw(
+        &mut self,
+        owner: AccountId,
+        receiver: AccountId,
+        shares: U128,
+        assets: U128,
+        success: bool,
+    ) {
+        self.calls.push(HookCall {
+            owner,
+            receiver,
+            shares,
+            assets,
+            success,
+        });
+    }
+
+    pub fn calls(&self) -> Vec<HookCall> {
+        self.calls.clone()
+    }
+}
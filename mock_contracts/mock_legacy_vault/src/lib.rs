@@ -0,0 +1,152 @@
+/*!
+Vault of the first release, before `redeem` resolved to a `WithdrawOutcome`, used as the source
+of a migration.
+NOTES:
+  - Shares are a plain ledger credited with `mint` and moved with `ft_transfer_call`.
+  - `redeem` pays out the asset one to one with a plain `ft_transfer`, resolving to the assets
+    sent as a bare `U128`, zero when the withdrawal rolled back.
+  - `set_roll_back` makes `redeem` roll back without paying out, like a failed transfer.
+*/
+use std::collections::HashMap;
+
+use near_contract_standards::fungible_token::core::ext_ft_core;
+use near_contract_standards::fungible_token::receiver::ext_ft_receiver;
+use near_sdk::json_types::U128;
+use near_sdk::{
+    env, near, AccountId, Gas, NearToken, PanicOnDefault, Promise, PromiseError, PromiseOrValue,
+};
+
+const GAS_FOR_FT_TRANSFER: Gas = Gas::from_tgas(20);
+const GAS_FOR_ON_REDEEMED: Gas = Gas::from_tgas(5);
+const GAS_FOR_FT_ON_TRANSFER: Gas = Gas::from_tgas(200);
+const GAS_FOR_FT_RESOLVE_TRANSFER: Gas = Gas::from_tgas(10);
+
+#[derive(PanicOnDefault)]
+#[near(contract_state)]
+pub struct Contract {
+    asset: AccountId,
+    balances: HashMap<AccountId, u128>,
+    roll_back: bool,
+}
+
+#[near]
+impl Contract {
+    #[init]
+    pub fn new(asset: AccountId) -> Self {
+        Self {
+            asset,
+            balances: HashMap::new(),
+            roll_back: false,
+        }
+    }
+
+    pub fn asset(&self) -> AccountId {
+        self.asset.clone()
+    }
+
+    /// Credits `amount` shares to `account_id`. The assets backing them have to be sent to the
+    /// vault separately.
+    pub fn mint(&mut self, account_id: AccountId, amount: U128) {
+        self.internal_deposit(&account_id, amount.0);
+    }
+
+    pub fn set_roll_back(&mut self, roll_back: bool) {
+        self.roll_back = roll_back;
+    }
+
+    pub fn ft_balance_of(&self, account_id: AccountId) -> U128 {
+        U128(self.balances.get(&account_id).copied().unwrap_or(0))
+    }
+
+    #[payable]
+    pub fn ft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        #[allow(unused_variables)] memo: Option<String>,
+        msg: String,
+    ) -> Promise {
+        let sender_id = env::predecessor_account_id();
+        self.internal_withdraw(&sender_id, amount.0);
+        self.internal_deposit(&receiver_id, amount.0);
+
+        ext_ft_receiver::ext(receiver_id.clone())
+            .with_static_gas(GAS_FOR_FT_ON_TRANSFER)
+            .ft_on_transfer(sender_id.clone(), amount, msg)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_FT_RESOLVE_TRANSFER)
+                    .ft_resolve_transfer(sender_id, receiver_id, amount),
+            )
+    }
+
+    /// Moves the shares the receiver didn't use back to the sender, resolving to those used.
+    #[private]
+    pub fn ft_resolve_transfer(
+        &mut self,
+        #[callback_result] unused: Result<U128, PromiseError>,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> U128 {
+        let unused = unused.map_or(amount.0, |unused| unused.0.min(amount.0));
+        let refund = unused.min(self.ft_balance_of(receiver_id.clone()).0);
+        self.internal_withdraw(&receiver_id, refund);
+        self.internal_deposit(&sender_id, refund);
+        U128(amount.0 - refund)
+    }
+
+    /// Burns `shares` of the caller and sends as many assets to `receiver_id`, resolving to the
+    /// assets sent.
+    #[payable]
+    pub fn redeem(
+        &mut self,
+        shares: U128,
+        receiver_id: Option<AccountId>,
+        memo: Option<String>,
+    ) -> PromiseOrValue<U128> {
+        let owner = env::predecessor_account_id();
+        if self.roll_back {
+            return PromiseOrValue::Value(U128(0));
+        }
+        self.internal_withdraw(&owner, shares.0);
+
+        PromiseOrValue::Promise(
+            ext_ft_core::ext(self.asset.clone())
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .ft_transfer(receiver_id.unwrap_or(owner.clone()), shares, memo)
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_ON_REDEEMED)
+                        .on_redeemed(owner, shares),
+                ),
+        )
+    }
+
+    #[private]
+    pub fn on_redeemed(
+        &mut self,
+        #[callback_result] transfer: Result<(), PromiseError>,
+        owner: AccountId,
+        shares: U128,
+    ) -> U128 {
+        if transfer.is_ok() {
+            shares
+        } else {
+            self.internal_deposit(&owner, shares.0);
+            U128(0)
+        }
+    }
+}
+
+impl Contract {
+    fn internal_deposit(&mut self, account_id: &AccountId, amount: u128) {
+        *self.balances.entry(account_id.clone()).or_insert(0) += amount;
+    }
+
+    fn internal_withdraw(&mut self, account_id: &AccountId, amount: u128) {
+        let balance = self.balances.entry(account_id.clone()).or_insert(0);
+        *balance = balance.checked_sub(amount).expect("Insufficient shares");
+    }
+}
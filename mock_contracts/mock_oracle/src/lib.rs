@@ -0,0 +1,28 @@
+/*!
+Price oracle the vault reads its USD value from, used to check `total_assets_usd`.
+NOTES:
+  - Anyone can set the price of an asset with `set_price`, assets without one panic.
+*/
+use near_sdk::json_types::U128;
+use near_sdk::{env, near, AccountId};
+use std::collections::HashMap;
+
+#[derive(Default)]
+#[near(contract_state)]
+pub struct Contract {
+    prices: HashMap<AccountId, U128>,
+}
+
+#[near]
+impl Contract {
+    pub fn set_price(&mut self, asset: AccountId, price: U128) {
+        self.prices.insert(asset, price);
+    }
+
+    pub fn get_price(&self, asset: AccountId) -> U128 {
+        *self
+            .prices
+            .get(&asset)
+            .unwrap_or_else(|| env::panic_str("No price for asset"))
+    }
+}
@@ -0,0 +1,161 @@
+/*!
+Strategy the vault invests its idle assets into, used to exercise liquidations on redeem.
+NOTES:
+  - Receives the asset from the vault through plain `ft_transfer`s, followed by `deposit`.
+  - `balance` resolves to the strategy's own balance of the asset, so anything sent to it
+    directly shows up as yield, less the rewards set aside with `accrue_rewards`.
+  - `claim_rewards` sends those rewards to the vault, resolving to the amount sent.
+  - `withdraw` sends the asset back to the vault minus `slippage_bps`, resolving to the amount
+    actually sent, like a strategy that has to unwind a position at a loss.
+*/
+use near_contract_standards::fungible_token::core::ext_ft_core;
+use near_sdk::json_types::U128;
+use near_sdk::{
+    env, near, require, AccountId, Gas, NearToken, PanicOnDefault, Promise, PromiseError,
+};
+
+const GAS_FOR_FT_TRANSFER: Gas = Gas::from_tgas(20);
+const GAS_FOR_ON_WITHDRAWN: Gas = Gas::from_tgas(5);
+const GAS_FOR_FT_BALANCE_OF: Gas = Gas::from_tgas(5);
+const GAS_FOR_ON_BALANCE: Gas = Gas::from_tgas(5);
+const MAX_BPS: u16 = 10_000;
+
+#[derive(PanicOnDefault)]
+#[near(contract_state)]
+pub struct Contract {
+    asset: AccountId,
+    vault: AccountId,
+    slippage_bps: u16,
+    deposited: U128,
+    rewards: U128,
+}
+
+#[near]
+impl Contract {
+    #[init]
+    pub fn new(asset: AccountId, vault: AccountId, slippage_bps: u16) -> Self {
+        require!(slippage_bps <= MAX_BPS, "Slippage too high");
+        Self {
+            asset,
+            vault,
+            slippage_bps,
+            deposited: U128(0),
+            rewards: U128(0),
+        }
+    }
+
+    pub fn set_slippage_bps(&mut self, slippage_bps: u16) {
+        require!(slippage_bps <= MAX_BPS, "Slippage too high");
+        self.slippage_bps = slippage_bps;
+    }
+
+    pub fn slippage_bps(&self) -> u16 {
+        self.slippage_bps
+    }
+
+    /// Records `amount` as put to work, the vault calls it after each invest.
+    pub fn deposit(&mut self, amount: U128) {
+        require!(
+            env::predecessor_account_id() == self.vault,
+            "Only the vault can deposit"
+        );
+        self.deposited = U128(self.deposited.0 + amount.0);
+    }
+
+    pub fn deposited(&self) -> U128 {
+        self.deposited
+    }
+
+    /// Resolves to the strategy's balance of the asset, rewards left out.
+    pub fn balance(&self) -> Promise {
+        ext_ft_core::ext(self.asset.clone())
+            .with_static_gas(GAS_FOR_FT_BALANCE_OF)
+            .ft_balance_of(env::current_account_id())
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_BALANCE)
+                    .on_balance(),
+            )
+    }
+
+    #[private]
+    pub fn on_balance(&self, #[callback_result] balance: Result<U128, PromiseError>) -> U128 {
+        let balance = balance.expect("Failed to read the balance");
+        U128(balance.0.saturating_sub(self.rewards.0))
+    }
+
+    /// Sets `amount` of the asset the strategy holds aside as rewards. The tokens have to be
+    /// sent to the strategy separately.
+    pub fn accrue_rewards(&mut self, amount: U128) {
+        self.rewards = U128(self.rewards.0 + amount.0);
+    }
+
+    pub fn rewards(&self) -> U128 {
+        self.rewards
+    }
+
+    /// Sends the rewards to the vault, resolving to the amount sent.
+    pub fn claim_rewards(&mut self) -> Promise {
+        require!(
+            env::predecessor_account_id() == self.vault,
+            "Only the vault can claim"
+        );
+        let rewards = std::mem::replace(&mut self.rewards, U128(0));
+
+        ext_ft_core::ext(self.asset.clone())
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(self.vault.clone(), rewards, Some("Rewards".to_string()))
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_WITHDRAWN)
+                    .on_rewards_claimed(rewards),
+            )
+    }
+
+    #[private]
+    pub fn on_rewards_claimed(
+        &mut self,
+        #[callback_result] transfer: Result<(), PromiseError>,
+        rewards: U128,
+    ) -> U128 {
+        if transfer.is_ok() {
+            rewards
+        } else {
+            self.rewards = U128(self.rewards.0 + rewards.0);
+            U128(0)
+        }
+    }
+
+    /// Returns `amount` minus slippage to the vault, resolving to the amount sent.
+    pub fn withdraw(&mut self, amount: U128) -> Promise {
+        require!(
+            env::predecessor_account_id() == self.vault,
+            "Only the vault can withdraw"
+        );
+        let sent = amount.0 * (MAX_BPS - self.slippage_bps) as u128 / MAX_BPS as u128;
+
+        ext_ft_core::ext(self.asset.clone())
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(self.vault.clone(), U128(sent), None)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_WITHDRAWN)
+                    .on_withdrawn(U128(sent)),
+            )
+    }
+
+    #[private]
+    pub fn on_withdrawn(
+        &mut self,
+        #[callback_result] transfer: Result<(), PromiseError>,
+        sent: U128,
+    ) -> U128 {
+        if transfer.is_ok() {
+            sent
+        } else {
+            U128(0)
+        }
+    }
+}
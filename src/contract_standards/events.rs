@@ -1,7 +1,15 @@
-use near_sdk::json_types::U128;
-use near_sdk::serde::Serialize;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{env, AccountIdRef};
 
+/// Lets events borrow account ids from the log they are parsed from.
+fn deserialize_account_ref<'de: 'a, 'a, D>(deserializer: D) -> Result<&'a AccountIdRef, D::Error>
+where
+    D: near_sdk::serde::Deserializer<'de>,
+{
+    <&'de AccountIdRef>::deserialize(deserializer)
+}
+
 #[derive(Serialize, Debug)]
 #[serde(crate = "near_sdk::serde")]
 #[serde(tag = "standard")]
@@ -10,10 +18,11 @@ use near_sdk::{env, AccountIdRef};
 #[allow(unused)]
 pub(crate) enum NearEvent<'a> {
     Nep000(Nep000Event<'a>),
+    VaultAdmin(VaultAdminEvent<'a>),
 }
 
 #[allow(unused)]
-impl<'a> NearEvent<'a> {
+impl NearEvent<'_> {
     fn to_json_string(&self) -> String {
         // Events cannot fail to serialize so fine to panic on error
         #[allow(clippy::redundant_closure)]
@@ -35,14 +44,16 @@ impl<'a> NearEvent<'a> {
 
 /// Data to log for an FT mint event. To log this event, call [`.emit()`](FtMint::emit).
 #[must_use]
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct VaultDeposit<'a> {
+    #[serde(borrow, deserialize_with = "deserialize_account_ref")]
     pub sender_id: &'a AccountIdRef,
+    #[serde(borrow, deserialize_with = "deserialize_account_ref")]
     pub owner_id: &'a AccountIdRef,
     pub assets: U128,
     pub shares: U128,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, borrow, skip_serializing_if = "Option::is_none")]
     pub memo: Option<&'a str>,
 }
 
@@ -64,14 +75,16 @@ impl VaultDeposit<'_> {
 /// Data to log for an FT transfer event. To log this event,
 /// call [`.emit()`](FtTransfer::emit).
 #[must_use]
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct VaultWithdraw<'a> {
+    #[serde(borrow, deserialize_with = "deserialize_account_ref")]
     pub owner_id: &'a AccountIdRef,
+    #[serde(borrow, deserialize_with = "deserialize_account_ref")]
     pub receiver_id: &'a AccountIdRef,
     pub shares: U128,
     pub assets: U128,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, borrow, skip_serializing_if = "Option::is_none")]
     pub memo: Option<&'a str>,
 }
 
@@ -90,6 +103,248 @@ impl VaultWithdraw<'_> {
     }
 }
 
+/// Data to log for a deposit attributed to a referrer. To log this event,
+/// call [`.emit()`](VaultReferral::emit).
+#[must_use]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultReferral<'a> {
+    #[serde(borrow, deserialize_with = "deserialize_account_ref")]
+    pub referrer: &'a AccountIdRef,
+    #[serde(borrow, deserialize_with = "deserialize_account_ref")]
+    pub depositor: &'a AccountIdRef,
+    pub assets: U128,
+    pub shares: U128,
+}
+
+#[allow(unused)]
+impl VaultReferral<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits a referral event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`VaultReferral`] represents the data of each referred deposit.
+    pub fn emit_many(data: &[VaultReferral<'_>]) {
+        new_000_v1(Nep000EventKind::VaultReferral(data)).emit()
+    }
+}
+
+/// Data to log for a withdrawal attributed to a referrer. To log this event,
+/// call [`.emit()`](VaultWithdrawReferral::emit).
+#[must_use]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultWithdrawReferral<'a> {
+    #[serde(borrow, deserialize_with = "deserialize_account_ref")]
+    pub referrer: &'a AccountIdRef,
+    #[serde(borrow, deserialize_with = "deserialize_account_ref")]
+    pub owner_id: &'a AccountIdRef,
+    pub assets: U128,
+    pub shares: U128,
+}
+
+#[allow(unused)]
+impl VaultWithdrawReferral<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits a withdraw referral event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`VaultWithdrawReferral`] represents the data of each referred withdrawal.
+    pub fn emit_many(data: &[VaultWithdrawReferral<'_>]) {
+        new_000_v1(Nep000EventKind::VaultWithdrawReferral(data)).emit()
+    }
+}
+
+/// Data to log for shares voluntarily burned by their holder. To log this event,
+/// call [`.emit()`](VaultSharesBurned::emit).
+#[must_use]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultSharesBurned<'a> {
+    #[serde(borrow, deserialize_with = "deserialize_account_ref")]
+    pub owner_id: &'a AccountIdRef,
+    pub shares: U128,
+}
+
+#[allow(unused)]
+impl VaultSharesBurned<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits a shares burned event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`VaultSharesBurned`] represents the data of each burn.
+    pub fn emit_many(data: &[VaultSharesBurned<'_>]) {
+        new_000_v1(Nep000EventKind::VaultSharesBurned(data)).emit()
+    }
+}
+
+/// Data to log with the vault's state after every operation changing its assets or supply, so
+/// that TVL and price can be charted without polling. To log this event,
+/// call [`.emit()`](VaultTvlUpdated::emit).
+#[must_use]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultTvlUpdated {
+    pub total_assets: U128,
+    pub total_supply: U128,
+    pub price_per_share: U128,
+    pub timestamp: U64,
+}
+
+#[allow(unused)]
+impl VaultTvlUpdated {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits a TVL update event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`VaultTvlUpdated`] represents the state after an operation.
+    pub fn emit_many(data: &[VaultTvlUpdated]) {
+        new_000_v1(Nep000EventKind::VaultTvlUpdated(data)).emit()
+    }
+}
+
+/// Data to log when the owner repoints the vault to a new asset contract. To log this event,
+/// call [`.emit()`](VaultAssetMigrated::emit).
+#[must_use]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultAssetMigrated<'a> {
+    #[serde(borrow, deserialize_with = "deserialize_account_ref")]
+    pub old_asset: &'a AccountIdRef,
+    #[serde(borrow, deserialize_with = "deserialize_account_ref")]
+    pub new_asset: &'a AccountIdRef,
+}
+
+#[allow(unused)]
+impl VaultAssetMigrated<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits an asset migration event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`VaultAssetMigrated`] represents the data of each migration.
+    pub fn emit_many(data: &[VaultAssetMigrated<'_>]) {
+        new_000_v1(Nep000EventKind::VaultAssetMigrated(data)).emit()
+    }
+}
+
+/// Data to log when the share decimals aren't the asset's plus the vault's `extra_decimals`, so
+/// conversions are off by a power of ten or lose precision.
+/// To log this event, call [`.emit()`](VaultDecimalsMismatch::emit).
+#[must_use]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultDecimalsMismatch {
+    pub share_decimals: u8,
+    pub asset_decimals: u8,
+}
+
+#[allow(unused)]
+impl VaultDecimalsMismatch {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits a decimals mismatch event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`VaultDecimalsMismatch`] represents the data of each mismatch.
+    pub fn emit_many(data: &[VaultDecimalsMismatch]) {
+        new_000_v1(Nep000EventKind::VaultDecimalsMismatch(data)).emit()
+    }
+}
+
+/// Data to log when a deposit is refunded in full instead of minting shares, like one whose
+/// message doesn't parse. To log this event, call [`.emit()`](VaultDepositRejected::emit).
+#[must_use]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultDepositRejected<'a> {
+    #[serde(borrow, deserialize_with = "deserialize_account_ref")]
+    pub sender_id: &'a AccountIdRef,
+    pub amount: U128,
+    pub reason: &'a str,
+}
+
+#[allow(unused)]
+impl VaultDepositRejected<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits a rejected deposit event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`VaultDepositRejected`] represents the data of each refunded deposit.
+    pub fn emit_many(data: &[VaultDepositRejected<'_>]) {
+        new_000_v1(Nep000EventKind::VaultDepositRejected(data)).emit()
+    }
+}
+
+/// Data to log for an owner-only action, under the `vault_admin` standard so that audit
+/// tooling can follow it apart from the vault's activity. To log this event,
+/// call [`.emit()`](AdminAction::emit).
+#[must_use]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AdminAction<'a> {
+    pub action: &'a str,
+    /// The method's arguments, serialized as JSON
+    pub args_json: String,
+    #[serde(borrow, deserialize_with = "deserialize_account_ref")]
+    pub caller: &'a AccountIdRef,
+    pub timestamp: U64,
+}
+
+#[allow(unused)]
+impl AdminAction<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits an admin action event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`AdminAction`] represents the data of each action.
+    pub fn emit_many(data: &[AdminAction<'_>]) {
+        NearEvent::VaultAdmin(VaultAdminEvent {
+            version: "1.0.0",
+            event_kind: VaultAdminEventKind::AdminAction(data),
+        })
+        .emit()
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub(crate) struct VaultAdminEvent<'a> {
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: VaultAdminEventKind<'a>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+enum VaultAdminEventKind<'a> {
+    AdminAction(&'a [AdminAction<'a>]),
+}
+
 #[derive(Serialize, Debug)]
 #[serde(crate = "near_sdk::serde")]
 pub(crate) struct Nep000Event<'a> {
@@ -106,6 +361,13 @@ pub(crate) struct Nep000Event<'a> {
 enum Nep000EventKind<'a> {
     VaultDeposit(&'a [VaultDeposit<'a>]),
     VaultWithdraw(&'a [VaultWithdraw<'a>]),
+    VaultReferral(&'a [VaultReferral<'a>]),
+    VaultWithdrawReferral(&'a [VaultWithdrawReferral<'a>]),
+    VaultSharesBurned(&'a [VaultSharesBurned<'a>]),
+    VaultDecimalsMismatch(&'a [VaultDecimalsMismatch]),
+    VaultAssetMigrated(&'a [VaultAssetMigrated<'a>]),
+    VaultDepositRejected(&'a [VaultDepositRejected<'a>]),
+    VaultTvlUpdated(&'a [VaultTvlUpdated]),
 }
 
 fn new_000<'a>(version: &'static str, event_kind: Nep000EventKind<'a>) -> NearEvent<'a> {
@@ -1,55 +1,60 @@
 use near_contract_standards::fungible_token::{receiver::FungibleTokenReceiver, FungibleTokenCore};
+use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{json_types::U128, AccountId, PromiseOrValue};
 use uint::construct_uint;
 
 pub mod events;
 
-construct_uint! {
-    pub struct U256(4);
+#[allow(clippy::manual_div_ceil)]
+mod uint256 {
+    use super::construct_uint;
+
+    construct_uint! {
+        pub struct U256(4);
+    }
+}
+
+pub use uint256::U256;
+
+/// Final result of a `withdraw` or `redeem` once the asset transfer has settled.
+///
+/// `assets` and `shares` are what actually left the vault and got burned; both are zero when
+/// the transfer failed and the withdrawal was rolled back.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WithdrawOutcome {
+    pub assets: U128,
+    pub shares: U128,
+    pub rolled_back: bool,
 }
 
 #[allow(unused)]
 pub trait VaultCore: FungibleTokenCore + FungibleTokenReceiver {
     fn asset(&self) -> AccountId;
     fn total_assets(&self) -> U128;
+    #[allow(clippy::too_many_arguments)]
     fn redeem(
         &mut self,
         shares: U128,
         receiver_id: Option<AccountId>,
         memo: Option<String>,
-    ) -> PromiseOrValue<U128>;
+        unwrap: Option<bool>,
+        max_loss_bps: Option<u16>,
+        msg: Option<String>,
+        max_fee_bps: Option<u16>,
+    ) -> PromiseOrValue<WithdrawOutcome>;
     fn withdraw(
         &mut self,
         assets: U128,
         receiver_id: Option<AccountId>,
         memo: Option<String>,
-    ) -> PromiseOrValue<U128>;
-
-    fn convert_to_shares(&self, assets: U128) -> U128 {
-        if (self.total_assets().0 == 0u128) {
-            return assets;
-        }
-
-        U256::from(self.ft_total_supply().0)
-            .checked_mul(U256::from(assets.0))
-            .expect("Too much assets")
-            .checked_div(U256::from(self.total_assets().0))
-            .unwrap()
-            .as_u128()
-            .into()
-    }
+        msg: Option<String>,
+        max_fee_bps: Option<u16>,
+    ) -> PromiseOrValue<WithdrawOutcome>;
 
-    fn convert_to_assets(&self, shares: U128) -> U128 {
-        assert!(self.ft_total_supply().0 > 0, "No shares issued yet");
-
-        U256::from(shares.0)
-            .checked_mul(U256::from(self.total_assets().0))
-            .expect("Too many shares")
-            .checked_div(U256::from(self.ft_total_supply().0))
-            .unwrap()
-            .as_u128()
-            .into()
-    }
+    // Both round down so a round trip never yields more than it started with
+    fn convert_to_shares(&self, assets: U128) -> U128;
+    fn convert_to_assets(&self, shares: U128) -> U128;
 
     fn max_deposit(&self, receiver_id: AccountId) -> U128 {
         let max_assets = u128::MAX - self.total_assets().0;
@@ -0,0 +1,105 @@
+//! The vault's initializer, kept apart so the lint allowance below covers its generated
+//! cross-contract wrapper: `near_bindgen` doesn't carry method attributes over to it.
+#![allow(clippy::too_many_arguments)]
+
+use super::*;
+
+#[near_bindgen]
+impl TokenizedVault {
+    /// Initializes the vault once, the caller becomes its owner.
+    #[init]
+    pub fn new(
+        asset: AccountId,
+        metadata: FungibleTokenMetadata,
+        extra_decimals: u8,
+        verify_received: Option<bool>,
+        is_wrap_near: Option<bool>,
+        dead_shares: Option<U128>,
+        total_assets_mode: Option<TotalAssetsMode>,
+        emit_vault_events: Option<bool>,
+        asset_decimals: Option<u8>,
+    ) -> Self {
+        metadata.assert_valid();
+        assert!(metadata.decimals <= 24, "Invalid decimals");
+        // Conversions scale by `extra_decimals`, it has to be the gap between the two
+        if let Some(asset_decimals) = asset_decimals {
+            assert!(
+                metadata.decimals.checked_sub(asset_decimals) == Some(extra_decimals),
+                "extra_decimals must be the share decimals minus the asset decimals"
+            );
+        }
+        assert!(
+            !metadata.name.is_empty() && !metadata.symbol.is_empty(),
+            "Name and symbol are required"
+        );
+
+        let mut this = Self {
+            token: FungibleToken::new(StorageKey::FungibleToken),
+            metadata,
+            asset,
+            total_assets: 0,
+            owner: env::predecessor_account_id(),
+            extra_decimals,
+            accounts: LookupMap::new(StorageKey::AccountState),
+            verify_received: verify_received.unwrap_or(false),
+            is_wrap_near: is_wrap_near.unwrap_or(false),
+            deposit_verification_pending: false,
+            withdrawals_in_flight: 0,
+            untracked_balance: 0,
+            inbound_assets: 0,
+            last_inbound_height: 0,
+            pending_migrations: 0,
+            reserved_assets: 0,
+            strategy: None,
+            deployed_assets: 0,
+            queued_assets: 0,
+            withdrawal_queue: Vector::new(StorageKey::WithdrawalQueue),
+            withdrawal_queue_head: 0,
+            performance_fee_bps: 0,
+            fee_recipient: None,
+            high_water_mark: 0,
+            max_early_exit_bps: 0,
+            exit_fee_decay_ns: 0,
+            price_observations: Vector::new(StorageKey::PriceObservations),
+            price_observations_next: 0,
+            basket: Vec::new(),
+            paused: false,
+            withdraw_hook: None,
+            asset_decimals,
+            max_deposit_per_tx: None,
+            dead_shares: dead_shares.map_or(0, |dead_shares| dead_shares.0),
+            registered_accounts: UnorderedSet::new(StorageKey::RegisteredAccounts),
+            min_price_per_share: None,
+            bridge_account: None,
+            total_assets_mode: total_assets_mode.unwrap_or(TotalAssetsMode::Tracked),
+            total_assets_refreshed_at: 0,
+            guardian: None,
+            reject_zero_share_deposits: true,
+            farm: None,
+            share_lock_period_ns: 0,
+            emit_vault_events: emit_vault_events.unwrap_or(true),
+            price_oracle: None,
+            price_oracle_decimals: 0,
+            total_assets_cap: None,
+            migration_source: None,
+            max_withdraw_per_window: None,
+            withdraw_window_ns: 0,
+            withdraw_window_start_ns: 0,
+            withdrawn_in_window: 0,
+            withdrawn_in_previous_window: 0,
+            min_shares_per_receiver: 0,
+            check_receiver_storage: false,
+            max_deposit_slippage_bps: None,
+            fee_exempt: UnorderedSet::new(StorageKey::FeeExempt),
+        };
+
+        // Registration pays for the account's vault bookkeeping and holder entry on top of its
+        // share balance
+        this.token.account_storage_usage += this.internal_measure_account_state_storage_usage();
+        // The vault escrows exit fee shares of in-flight withdrawals
+        this.token
+            .internal_register_account(&env::current_account_id());
+
+        this
+    }
+}
@@ -1,45 +1,1315 @@
 use near_contract_standards::fungible_token::{
-    core::ext_ft_core, events::FtBurn, FungibleTokenCore,
+    core::ext_ft_core,
+    events::{FtBurn, FtMint},
+    receiver::ext_ft_receiver,
+    FungibleTokenCore,
+};
+use near_sdk::{
+    env, ext_contract,
+    json_types::{U128, U64},
+    AccountId, Gas, NearToken, Promise, StorageUsage,
 };
-use near_sdk::{env, ext_contract, json_types::U128, AccountId, Gas, NearToken, Promise};
 
 use crate::{
-    mul_div::{mul_div, Rounding},
-    TokenizedVault, GAS_FOR_FT_TRANSFER,
+    assert_memo_len,
+    contract_standards::{
+        events::{AdminAction, VaultDeposit, VaultDepositRejected, VaultReferral, VaultTvlUpdated},
+        VaultCore, U256,
+    },
+    math::{assets_for_shares, shares_for_assets},
+    mul_div::{mul_div, pow10, Rounding},
+    AccountState, DepositMessage, DepositQuote, InvariantReport, PendingWithdrawal,
+    PriceObservation, RedeemRequest, StrategyWithdrawal, TokenizedVault, WithdrawMessage,
+    WithdrawalTransfer, BASKET_PRICE_SCALE, GAS_FOR_FARM_STAKE, GAS_FOR_FT_BALANCE_OF,
+    GAS_FOR_FT_TRANSFER, GAS_FOR_FT_TRANSFER_CALL, GAS_FOR_MIGRATION_REDEEM,
+    GAS_FOR_RESOLVE_BASKET_TRANSFER, GAS_FOR_RESOLVE_FARM_STAKE, GAS_FOR_RESOLVE_INVEST,
+    GAS_FOR_RESOLVE_MIGRATION, GAS_FOR_RESOLVE_STRATEGY_WITHDRAWAL,
+    GAS_FOR_RESOLVE_UNWRAPPED_TRANSFER, GAS_FOR_RESOLVE_VERIFIED_DEPOSIT, GAS_FOR_RESOLVE_WITHDRAW,
+    GAS_FOR_STRATEGY_WITHDRAW, GAS_FOR_WITHDRAW_HOOK, GAS_FOR_WITHDRAW_OVERHEAD,
+    PRICE_OBSERVATIONS_CAP, YEAR_NS,
 };
 
+#[ext_contract(ext_wrap_near)]
+pub trait _ExtWrapNear {
+    fn near_withdraw(&mut self, amount: U128);
+}
+
 #[ext_contract(ext_self)]
 pub trait _ExtSelf {
-    fn resolve_withdraw(
+    fn resolve_withdraw(&mut self, transfer: WithdrawalTransfer);
+    fn resolve_unwrapped_transfer(&mut self, owner: AccountId, amount: U128);
+    fn resolve_farm_stake(&mut self, owner: AccountId, farm: AccountId, shares: U128);
+    fn resolve_verified_deposit(&mut self, sender_id: AccountId, amount: U128, msg: String);
+    fn credit_verified_deposit(&mut self, sender_id: AccountId, received: U128, msg: String);
+    fn settle_verified_deposit(&mut self, received: U128);
+    fn resolve_invest(&mut self, amount: U128);
+    fn resolve_divest(&mut self, amount: U128);
+    fn compound_from_strategy(&mut self, reinvest: bool);
+    fn resolve_harvest(&mut self, deployed: U128);
+    fn on_asset_balance(&self);
+    fn on_check_invariants(&self, idle: U128);
+    fn on_is_healthy(&self, idle: U128);
+    fn on_total_assets_usd(&self, total_assets: U128, decimals: u8);
+    fn resolve_asset_decimals(&mut self);
+    fn resolve_refresh_total_assets(&mut self, tracked: U128, inbound: U128);
+    fn resolve_absorb_untracked_assets(&mut self, tracked: U128, inbound: U128);
+    fn resolve_queued_withdrawal(&mut self, pending: PendingWithdrawal);
+    fn resolve_strategy_withdrawal(&mut self, withdrawal: StrategyWithdrawal);
+    fn resolve_basket_withdrawal(
         &mut self,
         owner: AccountId,
-        receiver: AccountId,
+        token_id: AccountId,
+        amount: U128,
+        value: U128,
         shares: U128,
+        deposited: U128,
+    );
+    fn resolve_split_withdrawal(
+        &mut self,
+        owner: AccountId,
+        receiver: AccountId,
         assets: U128,
+        shares: U128,
+        deposited: U128,
         memo: Option<String>,
     );
+    fn resolve_migration(&mut self, sender_id: AccountId, shares: U128);
+    fn resolve_set_migration_source(&mut self, old_vault: AccountId);
+    fn resolve_migration_refund(&mut self);
+    fn redeem_to_registered_receiver(&mut self, request: RedeemRequest);
+    fn resolve_register_with_asset(&mut self, owner: AccountId, deposit: U128);
+    fn resolve_asset_storage_deposit(&mut self, owner: AccountId, deposit: U128);
+}
+
+/// The part of another vault's ABI used to migrate out of it.
+#[ext_contract(ext_vault)]
+pub trait _ExtVault {
+    fn asset(&self) -> AccountId;
+    fn redeem(
+        &mut self,
+        shares: U128,
+        receiver_id: Option<AccountId>,
+        memo: Option<String>,
+        unwrap: Option<bool>,
+        max_loss_bps: Option<u16>,
+        msg: Option<String>,
+    );
+}
+
+#[ext_contract(ext_withdraw_hook)]
+pub trait _ExtWithdrawHook {
+    fn on_vault_withdraw(
+        &mut self,
+        owner: AccountId,
+        receiver: AccountId,
+        shares: U128,
+        assets: U128,
+        success: bool,
+    );
+}
+
+/// ABI every strategy implements, so the vault plugs into lending markets, LP pools or any
+/// other yield source the same way.
+#[ext_contract(ext_strategy)]
+pub trait _AssetStrategy {
+    /// Puts `amount` of the asset, already transferred by the vault, to work.
+    fn deposit(&mut self, amount: U128);
+    /// Sends up to `amount` of the asset back to the vault, resolving to the amount sent.
+    fn withdraw(&mut self, amount: U128) -> U128;
+    /// Resolves to the current value of the vault's position, in units of the asset.
+    fn balance(&self) -> U128;
+    /// Sends the rewards earned in the asset to the vault with a plain `ft_transfer`, resolving
+    /// to the amount sent. Rewards don't count towards `balance`.
+    fn claim_rewards(&mut self) -> U128;
+}
+
+/// Minimal ABI of the oracle `total_assets_usd` reads the asset's price from.
+#[ext_contract(ext_price_oracle)]
+pub trait _PriceOracle {
+    /// Price of one unit of `asset` in the reference asset, scaled by the oracle's decimals.
+    fn get_price(&self, asset: AccountId) -> U128;
+}
+
+/// Supply and value conversions are priced against, read once so batch conversions don't walk
+/// `internal_total_value` per item. Borrows the vault, so it can't outlive a mutation.
+#[derive(Clone, Copy)]
+pub(crate) struct ConversionSnapshot<'a> {
+    vault: &'a TokenizedVault,
+    total_supply: u128,
+    total_value: u128,
+}
+
+impl ConversionSnapshot<'_> {
+    /// Assets per share as the exact fraction both conversions scale by.
+    pub fn exchange_rate(&self) -> (u128, u128) {
+        if self.total_supply == 0 {
+            (1, pow10(self.vault.extra_decimals))
+        } else {
+            (self.total_value + 1, self.total_supply)
+        }
+    }
+
+    pub fn convert_to_shares(&self, assets: u128, rounding: Rounding) -> u128 {
+        shares_for_assets(
+            assets,
+            self.total_supply,
+            self.total_value,
+            self.vault.extra_decimals,
+            rounding,
+        )
+    }
+
+    pub fn convert_to_assets(&self, shares: u128, rounding: Rounding) -> u128 {
+        assets_for_shares(
+            shares,
+            self.total_supply,
+            self.total_value,
+            self.vault.extra_decimals,
+            rounding,
+        )
+    }
 }
 
 impl TokenizedVault {
-    pub fn internal_transfer_assets_with_callback(
-        &self,
-        receiver_id: AccountId,
+    pub fn assert_owner(&self) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Not owner");
+    }
+
+    /// Logs the owner-only `action` called with `args` to the audit trail.
+    pub fn emit_admin_action(&self, action: &str, args: serde_json::Value) {
+        AdminAction {
+            action,
+            args_json: args.to_string(),
+            caller: &env::predecessor_account_id(),
+            timestamp: U64(env::block_timestamp()),
+        }
+        .emit();
+    }
+
+    pub fn assert_not_paused(&self) {
+        assert!(!self.paused, "Vault is paused");
+    }
+
+    /// Panics unless `required` gas is left for the promises about to be built, on top of what
+    /// the call itself still spends, so that shares are never burned by a call whose callbacks
+    /// can't run.
+    pub fn assert_gas_left(&self, required: Gas) {
+        assert!(
+            env::prepaid_gas().saturating_sub(env::used_gas())
+                >= required.saturating_add(GAS_FOR_WITHDRAW_OVERHEAD),
+            "Insufficient gas attached"
+        );
+    }
+
+    /// Gas `resolve_withdraw` needs, with the NEAR transfer of an unwrapped withdrawal and the
+    /// withdraw hook it goes on to.
+    pub fn internal_resolve_withdraw_gas(&self, unwrap: bool) -> Gas {
+        let mut gas = GAS_FOR_RESOLVE_WITHDRAW;
+        if unwrap {
+            gas = gas.saturating_add(GAS_FOR_RESOLVE_UNWRAPPED_TRANSFER);
+        }
+        if self.withdraw_hook.is_some() {
+            gas = gas.saturating_add(GAS_FOR_WITHDRAW_HOOK);
+        }
+        gas
+    }
+
+    /// Gas the asset transfer of a withdrawal and its `resolve_withdraw` need, a bridge being
+    /// paid with `ft_transfer_call`.
+    pub fn internal_transfer_gas(&self, unwrap: bool, bridge: bool) -> Gas {
+        let transfer = if bridge {
+            GAS_FOR_FT_TRANSFER_CALL
+        } else {
+            GAS_FOR_FT_TRANSFER
+        };
+        transfer.saturating_add(self.internal_resolve_withdraw_gas(unwrap))
+    }
+
+    /// Gas the whole chain of a withdrawal needs, with the strategy's liquidation when idle
+    /// liquidity falls short.
+    pub fn internal_withdrawal_gas(&self, from_strategy: bool, unwrap: bool, bridge: bool) -> Gas {
+        let transfer = self.internal_transfer_gas(unwrap, bridge);
+        if from_strategy {
+            GAS_FOR_STRATEGY_WITHDRAW
+                .saturating_add(GAS_FOR_RESOLVE_STRATEGY_WITHDRAWAL)
+                .saturating_add(transfer)
+        } else {
+            transfer
+        }
+    }
+
+    /// Panics when the exit fee `account_id` would pay is above the caller's `max_fee_bps`, so
+    /// that a fee raised after the caller's preview can't be charged.
+    pub fn assert_exit_fee_within(&self, account_id: &AccountId, max_fee_bps: Option<u16>) {
+        if let Some(max_fee_bps) = max_fee_bps {
+            assert!(
+                self.internal_exit_fee_bps(account_id) <= max_fee_bps as u128,
+                "Fee exceeds caller limit"
+            );
+        }
+    }
+
+    /// Panics unless `shares` of `account_id` are past their lock period.
+    pub fn assert_unlocked_shares(&self, account_id: &AccountId, shares: u128) {
+        assert!(
+            shares <= self.internal_unlocked_shares(account_id),
+            "Shares are locked"
+        );
+    }
+
+    pub fn internal_unlocked_shares(&self, account_id: &AccountId) -> u128 {
+        self.token
+            .ft_balance_of(account_id.clone())
+            .0
+            .saturating_sub(self.internal_account_state(account_id).locked_shares())
+    }
+
+    /// Start of the current window and the assets withdrawn in the previous and current one,
+    /// rolled forward to the current block.
+    fn internal_withdraw_windows(&self) -> (u64, u128, u128) {
+        let now = env::block_timestamp();
+        if self.withdraw_window_ns == 0 {
+            return (now, 0, 0);
+        }
+        let elapsed = now.saturating_sub(self.withdraw_window_start_ns);
+        match elapsed / self.withdraw_window_ns {
+            0 => (
+                self.withdraw_window_start_ns,
+                self.withdrawn_in_previous_window,
+                self.withdrawn_in_window,
+            ),
+            1 => (
+                self.withdraw_window_start_ns + self.withdraw_window_ns,
+                self.withdrawn_in_window,
+                0,
+            ),
+            _ => (now, 0, 0),
+        }
+    }
+
+    /// Assets withdrawn over the window sliding back from now: the current window's, plus the
+    /// previous one's weighted by how much of it the sliding window still overlaps.
+    pub fn internal_withdrawn_in_window(&self) -> u128 {
+        let (start, previous, current) = self.internal_withdraw_windows();
+        if previous == 0 {
+            return current;
+        }
+        let overlap = self.withdraw_window_ns - (env::block_timestamp() - start);
+        mul_div(
+            previous,
+            overlap as u128,
+            self.withdraw_window_ns as u128,
+            Rounding::Up,
+        )
+        .saturating_add(current)
+    }
+
+    /// Counts `assets` leaving through a withdrawal against the window's limit.
+    pub fn internal_record_outflow(&mut self, assets: u128) {
+        let Some(max) = self.max_withdraw_per_window else {
+            return;
+        };
+        let (start, previous, current) = self.internal_withdraw_windows();
+        self.withdraw_window_start_ns = start;
+        self.withdrawn_in_previous_window = previous;
+        self.withdrawn_in_window = current.saturating_add(assets);
+        assert!(
+            self.internal_withdrawn_in_window() <= max,
+            "Withdrawal limit reached, try later"
+        );
+    }
+
+    /// Gives the window back the `assets` of a withdrawal that was rolled back.
+    pub fn internal_release_outflow(&mut self, assets: u128) {
+        if self.max_withdraw_per_window.is_none() {
+            return;
+        }
+        let (start, previous, current) = self.internal_withdraw_windows();
+        let from_current = assets.min(current);
+        self.withdraw_window_start_ns = start;
+        self.withdrawn_in_previous_window = previous.saturating_sub(assets - from_current);
+        self.withdrawn_in_window = current - from_current;
+    }
+
+    /// Only one withdrawal per account can await its callback at a time.
+    pub fn assert_not_locked(&self, account_state: &AccountState) {
+        assert!(
+            !account_state.withdrawal_locked && account_state.pending_basket_legs == 0,
+            "Withdrawal already in progress"
+        );
+    }
+
+    /// Assets held by the vault itself: everything tracked that isn't invested into the
+    /// strategy, including what queued withdrawals are owed.
+    pub fn internal_idle_assets(&self) -> u128 {
+        self.total_assets
+            .checked_add(self.queued_assets)
+            .expect("idle assets overflow")
+            .saturating_sub(self.deployed_assets)
+    }
+
+    /// Idle assets not earmarked for pending or queued withdrawals. Conversions keep using
+    /// the total value, liquidity checks use this.
+    pub fn internal_available_assets(&self) -> u128 {
+        self.internal_idle_assets()
+            .saturating_sub(self.queued_assets)
+            .saturating_sub(self.reserved_assets)
+    }
+
+    pub fn internal_basket_index(&self, token_id: &AccountId) -> Option<usize> {
+        self.basket
+            .iter()
+            .position(|basket_asset| &basket_asset.token_id == token_id)
+    }
+
+    /// Value of `amount` of the basket token at `index`, in units of the asset.
+    pub fn internal_basket_value(&self, index: usize, amount: u128) -> u128 {
+        mul_div(
+            amount,
+            self.basket[index].price.0,
+            BASKET_PRICE_SCALE,
+            Rounding::Down,
+        )
+    }
+
+    /// `total_assets` plus the value of the basket, what shares are priced against.
+    pub fn internal_total_value(&self) -> u128 {
+        self.basket
+            .iter()
+            .enumerate()
+            .map(|(index, basket_asset)| self.internal_basket_value(index, basket_asset.balance.0))
+            .fold(self.total_assets, |total, value| {
+                total.checked_add(value).expect("total value overflow")
+            })
+    }
+
+    /// Mints `shares` back to `owner` when a withdrawal is rolled back. An owner who emptied
+    /// their balance and unregistered while the transfer was in flight is registered again,
+    /// on the vault's storage, rather than the callback panicking and the shares being lost.
+    pub fn internal_restore_shares(&mut self, owner: &AccountId, shares: u128) {
+        if !self.token.accounts.contains_key(owner) {
+            self.token.internal_register_account(owner);
+            self.registered_accounts.insert(owner);
+        }
+        self.token.internal_deposit(owner, shares);
+    }
+
+    /// Adds `amount` to `total_assets`. Every change to it goes through here or
+    /// `internal_decrease_total_assets`.
+    pub fn internal_increase_total_assets(&mut self, amount: u128) {
+        self.total_assets = self
+            .total_assets
+            .checked_add(amount)
+            .expect("total_assets overflow");
+    }
+
+    pub fn internal_decrease_total_assets(&mut self, amount: u128) {
+        self.total_assets = self
+            .total_assets
+            .checked_sub(amount)
+            .expect("total_assets underflow");
+    }
+
+    pub fn internal_credit_holding(&mut self, token_id: &AccountId, amount: u128) {
+        if token_id == &self.asset {
+            self.internal_increase_total_assets(amount);
+        } else {
+            let index = self.internal_basket_index(token_id).unwrap();
+            self.basket[index].balance.0 = self.basket[index]
+                .balance
+                .0
+                .checked_add(amount)
+                .expect("basket balance overflow");
+        }
+    }
+
+    pub fn internal_debit_holding(&mut self, token_id: &AccountId, amount: u128) {
+        if token_id == &self.asset {
+            self.internal_decrease_total_assets(amount);
+        } else {
+            let index = self.internal_basket_index(token_id).unwrap();
+            self.basket[index].balance.0 = self.basket[index]
+                .balance
+                .0
+                .checked_sub(amount)
+                .expect("Insufficient basket balance");
+        }
+    }
+
+    /// Redeems the `shares` of `old_vault` `sender_id` just transferred in, resolving to the
+    /// shares to refund once `resolve_migration` has deposited the assets paid out.
+    pub fn internal_migrate_from(
+        &mut self,
+        sender_id: AccountId,
+        old_vault: AccountId,
+        shares: u128,
+    ) -> Promise {
+        assert_eq!(
+            self.migration_source.as_ref(),
+            Some(&old_vault),
+            "Not the configured migration source"
+        );
+        self.assert_not_paused();
+        // The old vault pays out with a plain transfer, untracked until `resolve_migration`
+        self.pending_migrations += 1;
+
+        ext_vault::ext(old_vault)
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .with_static_gas(GAS_FOR_MIGRATION_REDEEM)
+            .redeem(
+                U128(shares),
+                None,
+                Some("Migration".to_string()),
+                None,
+                None,
+                None,
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_MIGRATION)
+                    .resolve_migration(sender_id, U128(shares)),
+            )
+    }
+
+    /// Burns `shares` of `owner` and sends `amount` of the basket token at `index` to
+    /// `receiver_id`, restoring both if the transfer fails. Its `value` counts against the
+    /// withdrawal limit like any other outflow.
+    pub fn internal_withdraw_basket_asset(
+        &mut self,
+        owner: &AccountId,
+        receiver_id: &AccountId,
+        index: usize,
         amount: u128,
-        owner: AccountId,
+        value: u128,
+        shares: u128,
+    ) -> Promise {
+        // Checked per leg, the legs before this one already took their gas
+        self.assert_gas_left(GAS_FOR_FT_TRANSFER.saturating_add(GAS_FOR_RESOLVE_BASKET_TRANSFER));
+        self.assert_unlocked_shares(owner, shares);
+        self.internal_record_outflow(value);
+        let mut owner_state = self.internal_account_state(owner);
+        let held_shares = self.token.ft_balance_of(owner.clone()).0;
+        let deposited = owner_state.take_deposited(shares, held_shares);
+        // Keeps the owner from withdrawing again until the leg settles, like `withdrawal_locked`
+        owner_state.pending_basket_legs += 1;
+        self.accounts.insert(owner, &owner_state);
+
+        self.token.internal_withdraw(owner, shares);
+        let token_id = self.basket[index].token_id.clone();
+        self.internal_debit_holding(&token_id, amount);
+        self.internal_record_price();
+
+        FtBurn {
+            owner_id: owner,
+            amount: U128(shares),
+            memo: Some("Withdrawal"),
+        }
+        .emit();
+
+        ext_ft_core::ext(token_id.clone())
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(receiver_id.clone(), U128(amount), None)
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_BASKET_TRANSFER)
+                    .resolve_basket_withdrawal(
+                        owner.clone(),
+                        token_id,
+                        U128(amount),
+                        U128(value),
+                        U128(shares),
+                        U128(deposited),
+                    ),
+            )
+    }
+
+    /// Credits `amount` of the basket token at `index`, minting shares for its value.
+    pub fn internal_handle_basket_deposit(
+        &mut self,
+        index: usize,
+        sender_id: AccountId,
+        amount: u128,
+        parsed_msg: DepositMessage,
+    ) -> u128 {
+        let token_id = self.basket[index].token_id.clone();
+        if parsed_msg.donate.unwrap_or(false) {
+            self.internal_credit_holding(&token_id, amount);
+            self.internal_accrue_performance_fee();
+            self.internal_record_price();
+
+            return 0;
+        }
+
+        let owner_id = parsed_msg.share_receiver(&sender_id);
+        if *owner_id == env::current_account_id() {
+            return amount;
+        }
+        if !self.token.accounts.contains_key(owner_id) {
+            env::log_str("Share receiver not registered");
+            return amount;
+        }
+
+        // Basket tokens are taken whole, there is no partial fill to cap the shares at
+        let value = self.internal_basket_value(index, amount);
+        let shares = self.internal_convert_to_shares(value, Rounding::Down);
+        if shares == 0
+            || self.internal_is_dust_for_receiver(&sender_id, owner_id, shares)
+            || parsed_msg.max_shares.is_some()
+            || self.internal_accepted_deposit(value) < value
+            || parsed_msg
+                .min_shares
+                .is_some_and(|min_shares| shares < min_shares.0)
+        {
+            return amount;
+        }
+
+        self.token.internal_deposit(owner_id, shares);
+        self.internal_credit_holding(&token_id, amount);
+        self.internal_record_price();
+        self.internal_record_deposit(&sender_id, owner_id, value, shares, &parsed_msg);
+
+        0
+    }
+
+    pub fn internal_reserve_assets(&mut self, amount: u128) {
+        assert!(amount > 0, "No assets to reserve");
+        assert!(
+            amount <= self.internal_available_assets(),
+            "Insufficient available assets"
+        );
+        self.reserved_assets = self
+            .reserved_assets
+            .checked_add(amount)
+            .expect("reserved_assets overflow");
+    }
+
+    pub fn internal_release_assets(&mut self, amount: u128) {
+        self.reserved_assets = self
+            .reserved_assets
+            .checked_sub(amount)
+            .expect("Exceeds reserved assets");
+    }
+
+    /// Checks the vault's counters against each other and against its asset `balance`, queried
+    /// while it tracked `idle` assets. Whatever left the balance since the query was sent was
+    /// booked out of the idle assets first, so the lower of both is what it must cover.
+    pub fn internal_invariant_report(&self, balance: u128, idle: u128) -> InvariantReport {
+        let tracked_assets = self.total_assets + self.queued_assets;
+        let total_supply = self.token.ft_total_supply().0;
+
+        let deployed_within_assets = self.deployed_assets <= tracked_assets;
+        let reserved_within_assets = self.reserved_assets <= self.total_assets;
+        let queue_within_idle = self.queued_assets <= self.internal_idle_assets();
+        let assets_held = balance >= idle.min(self.internal_idle_assets());
+        let supply_backed = total_supply == 0 || self.internal_total_value() > 0;
+
+        InvariantReport {
+            deployed_within_assets,
+            reserved_within_assets,
+            queue_within_idle,
+            assets_held,
+            supply_backed,
+            all_hold: deployed_within_assets
+                && reserved_within_assets
+                && queue_within_idle
+                && assets_held
+                && supply_backed,
+        }
+    }
+
+    /// Queries the vault's own balance on the asset contract. Resolves to the balance as `U128`,
+    /// chain a callback taking it as `#[callback_result]` to act on it.
+    pub fn query_asset_balance(&self) -> Promise {
+        ext_ft_core::ext(self.asset.clone())
+            .with_static_gas(GAS_FOR_FT_BALANCE_OF)
+            .ft_balance_of(env::current_account_id())
+    }
+
+    /// Exit fee on `account_id`'s shares in basis points, decaying linearly from
+    /// `max_early_exit_bps` at its entry time to zero after `exit_fee_decay_ns`. Zero for
+    /// fee-exempt accounts.
+    pub fn internal_exit_fee_bps(&self, account_id: &AccountId) -> u128 {
+        if self.fee_exempt.contains(account_id) {
+            return 0;
+        }
+        let Some(entry_ns) = self.internal_account_state(account_id).entry_ns else {
+            return 0;
+        };
+        let held_ns = env::block_timestamp().saturating_sub(entry_ns.0);
+        if self.max_early_exit_bps == 0 || held_ns >= self.exit_fee_decay_ns {
+            return 0;
+        }
+
+        mul_div(
+            self.max_early_exit_bps as u128,
+            (self.exit_fee_decay_ns - held_ns) as u128,
+            self.exit_fee_decay_ns as u128,
+            Rounding::Down,
+        )
+    }
+
+    /// Pays the exit fee on `shares` straight to the fee recipient, returning the fee shares.
+    pub fn internal_take_exit_fee(&mut self, account_id: &AccountId, shares: u128) -> u128 {
+        let fee_shares = self.internal_exit_fee_shares(account_id, shares);
+        self.internal_pay_exit_fee(account_id, fee_shares);
+        fee_shares
+    }
+
+    /// Pays `fee_shares` of `account_id` straight to the fee recipient.
+    pub fn internal_pay_exit_fee(&mut self, account_id: &AccountId, fee_shares: u128) {
+        if fee_shares > 0 {
+            let fee_recipient = self.fee_recipient.clone().unwrap();
+            self.token.internal_transfer(
+                account_id,
+                &fee_recipient,
+                fee_shares,
+                Some("Exit fee".to_string()),
+            );
+        }
+    }
+
+    /// Burns `shares` of `owner` and sends `assets` of idle liquidity to `receiver_id`, one leg
+    /// of a split withdrawal, restoring both if the transfer fails.
+    pub fn internal_withdraw_split_leg(
+        &mut self,
+        owner: &AccountId,
+        receiver_id: AccountId,
+        assets: u128,
         shares: u128,
         memo: Option<String>,
     ) -> Promise {
+        // Checked per leg, the legs before this one already took their gas
+        self.assert_gas_left(GAS_FOR_FT_TRANSFER.saturating_add(GAS_FOR_RESOLVE_WITHDRAW));
+        self.assert_unlocked_shares(owner, shares);
+        let mut owner_state = self.internal_account_state(owner);
+        let held_shares = self.token.ft_balance_of(owner.clone()).0;
+        let deposited = owner_state.take_deposited(shares, held_shares);
+        self.accounts.insert(owner, &owner_state);
+
+        self.token.internal_withdraw(owner, shares);
+        self.internal_decrease_total_assets(assets);
+        self.withdrawals_in_flight = self
+            .withdrawals_in_flight
+            .checked_add(assets)
+            .expect("withdrawals_in_flight overflow");
+        self.internal_record_price();
+
+        FtBurn {
+            owner_id: owner,
+            amount: U128(shares),
+            memo: Some("Withdrawal"),
+        }
+        .emit();
+
+        ext_ft_core::ext(self.asset.clone())
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(receiver_id.clone(), U128(assets), memo.clone())
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_WITHDRAW)
+                    .resolve_split_withdrawal(
+                        owner.clone(),
+                        receiver_id,
+                        U128(assets),
+                        U128(shares),
+                        U128(deposited),
+                        memo,
+                    ),
+            )
+    }
+
+    /// Part of `shares` redeemed by `account_id` taken as exit fee.
+    pub fn internal_exit_fee_shares(&self, account_id: &AccountId, shares: u128) -> u128 {
+        let fee_bps = self.internal_exit_fee_bps(account_id);
+        mul_div(shares, fee_bps, 10_000, Rounding::Up)
+    }
+
+    /// Blends the sender's entry time into the receiver's, weighted by the `shares` changing
+    /// hands against those the receiver already holds, so the exit fee can't be dodged by
+    /// transferring freshly minted shares and a dust transfer can't reset a holder's fee. Call
+    /// before the shares move.
+    pub fn internal_carry_entry_time(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        shares: u128,
+    ) {
+        let Some(sender_entry) = self.internal_account_state(sender_id).entry_ns else {
+            return;
+        };
+        let held_shares = self.token.ft_balance_of(receiver_id.clone()).0;
+        if held_shares + shares == 0 {
+            return;
+        }
+        let mut receiver_state = self.internal_account_state(receiver_id);
+        // Shares held without an entry time are counted as new, like a deposit does
+        let receiver_entry = receiver_state
+            .entry_ns
+            .map_or(env::block_timestamp(), |entry| entry.0);
+        receiver_state.entry_ns = Some(U64(((U256::from(receiver_entry)
+            * U256::from(held_shares)
+            + U256::from(sender_entry.0) * U256::from(shares))
+            / U256::from(held_shares + shares))
+        .as_u64()));
+        self.accounts.insert(receiver_id, &receiver_state);
+    }
+
+    /// Moves the exit fee shares of a withdrawal into the vault's escrow until it settles.
+    pub fn internal_escrow_exit_fee(&mut self, owner: &AccountId, fee_shares: u128) {
+        if fee_shares == 0 {
+            return;
+        }
+        self.token.internal_transfer(
+            owner,
+            &env::current_account_id(),
+            fee_shares,
+            Some("Exit fee escrow".to_string()),
+        );
+
+        let mut owner_state = self.internal_account_state(owner);
+        owner_state.pending_exit_fee = Some(U128(fee_shares));
+        self.accounts.insert(owner, &owner_state);
+    }
+
+    /// Releases the escrowed exit fee of the `used` part of a withdrawal of `assets` to the fee
+    /// recipient, the part refunded or rolled back goes back to the owner.
+    pub fn internal_settle_exit_fee(
+        &mut self,
+        owner: &AccountId,
+        owner_state: &mut AccountState,
+        used: u128,
+        assets: u128,
+    ) {
+        let Some(fee_shares) = owner_state.pending_exit_fee.take() else {
+            return;
+        };
+        let refunded_fee = if assets == 0 {
+            fee_shares.0
+        } else {
+            mul_div(fee_shares.0, assets - used, assets, Rounding::Down)
+        };
+        let charged_fee = fee_shares.0 - refunded_fee;
+        let fee_recipient = self
+            .fee_recipient
+            .clone()
+            .filter(|recipient| self.token.accounts.contains_key(recipient))
+            .unwrap_or(owner.clone());
+
+        for (account, amount) in [(&fee_recipient, charged_fee), (owner, refunded_fee)] {
+            if amount > 0 {
+                self.token.internal_transfer(
+                    &env::current_account_id(),
+                    account,
+                    amount,
+                    Some("Exit fee".to_string()),
+                );
+            }
+        }
+    }
+
+    /// Storage taken by the largest possible `AccountState` record of an account and its entry
+    /// in the registered accounts.
+    pub fn internal_measure_account_state_storage_usage(&mut self) -> StorageUsage {
+        let initial_storage_usage = env::storage_usage();
+        let tmp_account_id: AccountId = "a".repeat(64).parse().unwrap();
+        self.accounts
+            .insert(&tmp_account_id, &AccountState::max_sized());
+        self.registered_accounts.insert(&tmp_account_id);
+        let storage_usage = env::storage_usage() - initial_storage_usage;
+        self.accounts.remove(&tmp_account_id);
+        self.registered_accounts.remove(&tmp_account_id);
+        storage_usage
+    }
+
+    pub fn internal_account_state(&self, account_id: &AccountId) -> AccountState {
+        self.accounts.get(account_id).unwrap_or_default()
+    }
+
+    /// Drops the record of an account that has unregistered, once nothing it's owed or has in
+    /// flight is kept in it anymore.
+    pub fn internal_prune_account_state(&mut self, account_id: &AccountId) {
+        if self.token.accounts.contains_key(account_id) {
+            return;
+        }
+        let Some(account_state) = self.accounts.get(account_id) else {
+            return;
+        };
+        if account_state
+            .queued_withdrawal
+            .is_none_or(|queued| queued.0 == 0)
+            && !account_state.withdrawal_locked
+            && account_state.pending_basket_legs == 0
+            && account_state.pending_exit_fee.is_none()
+        {
+            self.accounts.remove(account_id);
+        }
+    }
+
+    /// Books the strategy's current value of the deployed assets, moving `total_assets` by the
+    /// gain or loss and charging the performance fee on gains.
+    pub fn internal_report_yield(&mut self, strategy_balance: u128) {
+        if strategy_balance >= self.deployed_assets {
+            let gain = strategy_balance - self.deployed_assets;
+            self.internal_increase_total_assets(gain);
+        } else {
+            let loss = self.deployed_assets - strategy_balance;
+            assert!(loss <= self.total_assets, "Loss exceeds total assets");
+            self.internal_decrease_total_assets(loss);
+        }
+        self.deployed_assets = strategy_balance;
+        self.internal_assert_price_floor();
+
+        self.internal_accrue_performance_fee();
+        self.internal_record_price();
+    }
+
+    /// Moves `amount` of the available assets to the strategy, deployed once the transfer
+    /// went through.
+    pub fn internal_invest(&mut self, amount: u128) -> Promise {
+        let strategy_id = self.strategy.clone().expect("No strategy set");
+        assert!(amount > 0, "No assets to invest");
+        assert!(
+            amount <= self.internal_available_assets(),
+            "Insufficient available assets"
+        );
+
+        self.deployed_assets = self
+            .deployed_assets
+            .checked_add(amount)
+            .expect("deployed_assets overflow");
+        self.withdrawals_in_flight = self
+            .withdrawals_in_flight
+            .checked_add(amount)
+            .expect("withdrawals_in_flight overflow");
+
         ext_ft_core::ext(self.asset.clone())
             .with_attached_deposit(NearToken::from_yoctonear(1))
             .with_static_gas(GAS_FOR_FT_TRANSFER)
-            .ft_transfer(receiver_id.clone(), U128(amount), memo.clone())
+            .ft_transfer(strategy_id, U128(amount), Some("Invest".to_string()))
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_INVEST)
+                    .resolve_invest(U128(amount)),
+            )
+    }
+
+    /// Notes an inbound transfer of the asset. Until its `ft_resolve_transfer` has run, the asset
+    /// balance can still hold an unused part that is about to be refunded.
+    pub fn internal_record_inbound(&mut self, amount: u128) {
+        self.inbound_assets = self.inbound_assets.wrapping_add(amount);
+        self.last_inbound_height = env::block_height();
+    }
+
+    /// Idle plus in-flight assets and the inbound sum to reconcile a balance query against, taken
+    /// when the query is sent. Refuses while a deposit's balance is still being verified, an
+    /// inbound transfer of this block may still refund its unused part, or a migration's assets
+    /// may be on the balance untracked.
+    pub fn internal_balance_snapshot(&self) -> (U128, U128) {
+        assert!(
+            !self.deposit_verification_pending,
+            "Another deposit is being verified, try again"
+        );
+        assert!(
+            self.last_inbound_height < env::block_height(),
+            "A deposit is settling, try again"
+        );
+        assert_eq!(
+            self.pending_migrations, 0,
+            "A migration is settling, try again"
+        );
+        let tracked = self
+            .internal_idle_assets()
+            .checked_add(self.withdrawals_in_flight)
+            .expect("tracked balance overflow");
+        (U128(tracked), U128(self.inbound_assets))
+    }
+
+    /// Books whatever the vault's asset `balance` holds above the `tracked` assets of the
+    /// snapshot as yield, returning it. A lower balance isn't booked as a loss, in-flight
+    /// refunds can still bring it back. `None` when the asset arrived since the snapshot was
+    /// taken or a migration is settling: the balance may or may not hold it, nothing is booked.
+    pub fn internal_absorb_untracked_assets(
+        &mut self,
+        balance: u128,
+        tracked: u128,
+        inbound: u128,
+    ) -> Option<u128> {
+        if self.inbound_assets != inbound || self.pending_migrations > 0 {
+            env::log_str("Assets arrived during the balance query, nothing absorbed");
+            return None;
+        }
+        let untracked = balance.saturating_sub(tracked);
+        self.untracked_balance = 0;
+        if untracked > 0 {
+            self.internal_increase_total_assets(untracked);
+            self.internal_accrue_performance_fee();
+            self.internal_record_price();
+        }
+        Some(untracked)
+    }
+
+    /// Books assets sent back by the strategy. Anything above what was deployed is yield.
+    pub fn internal_handle_divest(&mut self, amount: u128) {
+        let returned = amount.min(self.deployed_assets);
+        self.deployed_assets -= returned;
+        if amount > returned {
+            self.internal_increase_total_assets(amount - returned);
+            self.internal_accrue_performance_fee();
+            self.internal_record_price();
+        }
+    }
+
+    /// Keeps the referrer of `owner`'s withdrawal until it settles, only one can be in flight.
+    pub fn internal_stash_withdraw_referral(
+        &mut self,
+        owner: &AccountId,
+        referral: Option<AccountId>,
+    ) {
+        if referral.is_some() {
+            let mut owner_state = self.internal_account_state(owner);
+            owner_state.pending_referral = referral;
+            self.accounts.insert(owner, &owner_state);
+        }
+    }
+
+    /// Reverts when the price per share fell below the floor set by the owner.
+    pub fn internal_assert_price_floor(&self) {
+        if let Some(min_price_per_share) = self.min_price_per_share {
+            assert!(
+                self.internal_price_per_share(Rounding::Nearest) >= min_price_per_share,
+                "Price floor breached"
+            );
+        }
+    }
+
+    /// Records the current price per share, overwriting the oldest observation once the buffer
+    /// is full. Observations within the same block are merged into the latest one. Every state
+    /// change goes through here, so it also logs the TVL update.
+    pub fn internal_record_price(&mut self) {
+        let observation = PriceObservation {
+            timestamp: U64(env::block_timestamp()),
+            price_per_share: U128(self.internal_price_per_share(Rounding::Nearest)),
+        };
+        if self.emit_vault_events {
+            VaultTvlUpdated {
+                total_assets: U128(self.internal_total_value()),
+                total_supply: self.token.ft_total_supply(),
+                price_per_share: observation.price_per_share,
+                timestamp: observation.timestamp,
+            }
+            .emit();
+        }
+
+        let len = self.price_observations.len();
+        if len > 0 {
+            let latest_index = (self.price_observations_next + len - 1) % len;
+            let latest = self.price_observations.get(latest_index).unwrap();
+            if latest.timestamp == observation.timestamp {
+                self.price_observations.replace(latest_index, &observation);
+                return;
+            }
+        }
+
+        if len < PRICE_OBSERVATIONS_CAP {
+            self.price_observations.push(&observation);
+        } else {
+            self.price_observations
+                .replace(self.price_observations_next, &observation);
+            self.price_observations_next = (self.price_observations_next + 1) % len;
+        }
+    }
+
+    /// Averages the recorded prices per share over `[now - window_ns, now]`, each price holding
+    /// until the next observation. Time before the oldest observation isn't counted.
+    pub fn internal_twap(&self, window_ns: u64) -> u128 {
+        let len = self.price_observations.len();
+        if len == 0 {
+            return self.internal_price_per_share(Rounding::Nearest);
+        }
+
+        let now = env::block_timestamp();
+        let start = now.saturating_sub(window_ns);
+        let observations = self.internal_price_observations();
+
+        let mut weighted = U256::zero();
+        let mut elapsed = 0u64;
+        for (i, observation) in observations.iter().enumerate() {
+            let end = observations.get(i + 1).map_or(now, |next| next.timestamp.0);
+            let from = observation.timestamp.0.max(start);
+            if end > from {
+                weighted += U256::from(observation.price_per_share.0) * U256::from(end - from);
+                elapsed += end - from;
+            }
+        }
+
+        if elapsed == 0 {
+            return observations[observations.len() - 1].price_per_share.0;
+        }
+
+        let elapsed = U256::from(elapsed);
+        let twap = weighted / elapsed;
+        if (weighted % elapsed) * U256::from(2) >= elapsed {
+            (twap + U256::one()).as_u128()
+        } else {
+            twap.as_u128()
+        }
+    }
+
+    /// Simple annualized return in basis points implied by the price per share change over the
+    /// last `window_ns`, or over the whole buffer if it is shorter. Zero until the buffer holds
+    /// two observations.
+    pub fn internal_estimated_apy_bps(&self, window_ns: u64) -> i64 {
+        let observations = self.internal_price_observations();
+        if observations.len() < 2 {
+            return 0;
+        }
+
+        let now = env::block_timestamp();
+        let start = now.saturating_sub(window_ns);
+        let first = observations
+            .iter()
+            .rev()
+            .find(|observation| observation.timestamp.0 <= start)
+            .unwrap_or(&observations[0]);
+        let elapsed = now - first.timestamp.0.max(start);
+        let begin = first.price_per_share.0;
+        let end = observations[observations.len() - 1].price_per_share.0;
+        if elapsed == 0 || begin == 0 {
+            return 0;
+        }
+
+        let apy = U256::from(end.abs_diff(begin)) * U256::from(10_000u64) * U256::from(YEAR_NS)
+            / (U256::from(begin) * U256::from(elapsed));
+        let apy = if apy > U256::from(i64::MAX as u64) {
+            i64::MAX
+        } else {
+            apy.as_u64() as i64
+        };
+        if end < begin {
+            -apy
+        } else {
+            apy
+        }
+    }
+
+    /// Price observations from the oldest to the latest.
+    fn internal_price_observations(&self) -> Vec<PriceObservation> {
+        let len = self.price_observations.len();
+        (0..len)
+            .map(|i| {
+                self.price_observations
+                    .get((self.price_observations_next + i) % len)
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    /// Asset value of one whole share. Fees are charged on the price rounded down, displays
+    /// round to the nearest unit.
+    pub fn internal_price_per_share(&self, rounding: Rounding) -> u128 {
+        self.internal_convert_to_assets(pow10(self.metadata.decimals), rounding)
+    }
+
+    /// Takes the performance fee on the share price gain above the high-water mark, minting it
+    /// as shares to the fee recipient, and raises the mark. Call after `total_assets` grew.
+    /// While the recipient is unregistered the mark stays put, so the fee is taken once they
+    /// register again.
+    pub fn internal_accrue_performance_fee(&mut self) {
+        let Some(fee_recipient) = self.fee_recipient.clone() else {
+            return;
+        };
+        if !self.token.accounts.contains_key(&fee_recipient) {
+            return;
+        }
+        let total_supply = self.token.ft_total_supply().0;
+        let price_per_share = self.internal_price_per_share(Rounding::Down);
+        if self.performance_fee_bps == 0
+            || total_supply == 0
+            || price_per_share <= self.high_water_mark
+        {
+            return;
+        }
+
+        let gain = mul_div(
+            price_per_share - self.high_water_mark,
+            total_supply,
+            pow10(self.metadata.decimals),
+            Rounding::Down,
+        );
+        let fee_assets = mul_div(
+            gain,
+            self.performance_fee_bps as u128,
+            10_000,
+            Rounding::Down,
+        );
+        // Shares worth `fee_assets` once minted, diluting holders by exactly the fee
+        let fee_shares = mul_div(
+            fee_assets,
+            total_supply,
+            self.internal_total_value() + 1 - fee_assets,
+            Rounding::Down,
+        );
+
+        if fee_shares > 0 {
+            self.token.internal_deposit(&fee_recipient, fee_shares);
+            FtMint {
+                owner_id: &fee_recipient,
+                amount: U128(fee_shares),
+                memo: Some("Performance fee"),
+            }
+            .emit();
+        }
+
+        self.high_water_mark = self.internal_price_per_share(Rounding::Down);
+    }
+
+    /// Tells the withdraw hook, if any, how a withdrawal settled. The call isn't awaited so a
+    /// failing hook can't revert the withdrawal.
+    pub fn internal_notify_withdraw_hook(
+        &self,
+        owner: &AccountId,
+        receiver: &AccountId,
+        shares: U128,
+        assets: U128,
+        success: bool,
+    ) {
+        if let Some(hook) = self.withdraw_hook.clone() {
+            ext_withdraw_hook::ext(hook)
+                .with_static_gas(GAS_FOR_WITHDRAW_HOOK)
+                .on_vault_withdraw(owner.clone(), receiver.clone(), shares, assets, success);
+        }
+    }
+
+    /// Burns `shares` of `account` and queues the withdrawal of their assets, returning them.
+    pub fn internal_queue_redemption(
+        &mut self,
+        account: AccountId,
+        shares: u128,
+        memo: &str,
+    ) -> u128 {
+        // Queued shares are never minted back, their deposited assets go for good
+        let mut account_state = self.internal_account_state(&account);
+        let held_shares = self.token.ft_balance_of(account.clone()).0;
+        account_state.take_deposited(shares, held_shares);
+        self.accounts.insert(&account, &account_state);
+
+        // Nothing to roll back here, the exit fee goes straight to the recipient
+        let shares = shares - self.internal_take_exit_fee(&account, shares);
+
+        let assets = self.internal_convert_to_assets(shares, Rounding::Down);
+        assert!(assets > 0, "No assets to withdraw");
+        assert!(assets <= self.total_assets, "Insufficient vault assets");
+
+        // Burn now so the queued assets stop earning and can't be withdrawn twice
+        self.token.internal_withdraw(&account, shares);
+        self.internal_decrease_total_assets(assets);
+
+        self.internal_record_price();
+
+        FtBurn {
+            owner_id: &account,
+            amount: U128(shares),
+            memo: Some(memo),
+        }
+        .emit();
+
+        self.internal_queue_withdrawal(PendingWithdrawal {
+            account,
+            assets: U128(assets),
+            shares: U128(shares),
+            timestamp: U64(env::block_timestamp()),
+        });
+
+        assets
+    }
+
+    pub fn internal_queue_withdrawal(&mut self, pending: PendingWithdrawal) {
+        self.queued_assets = self
+            .queued_assets
+            .checked_add(pending.assets.0)
+            .expect("queued_assets overflow");
+
+        let mut account_state = self.internal_account_state(&pending.account);
+        let queued = account_state.queued_withdrawal.map_or(0, |q| q.0);
+        account_state.queued_withdrawal = Some(U128(
+            queued
+                .checked_add(pending.assets.0)
+                .expect("queued_withdrawal overflow"),
+        ));
+        self.accounts.insert(&pending.account, &account_state);
+
+        self.withdrawal_queue.push(&pending);
+    }
+
+    pub fn internal_pay_queued_withdrawal(&mut self, pending: PendingWithdrawal) -> Promise {
+        self.queued_assets = self
+            .queued_assets
+            .checked_sub(pending.assets.0)
+            .expect("queued_assets underflow");
+        self.withdrawals_in_flight = self
+            .withdrawals_in_flight
+            .checked_add(pending.assets.0)
+            .expect("withdrawals_in_flight overflow");
+
+        let mut account_state = self.internal_account_state(&pending.account);
+        let queued = account_state.queued_withdrawal.map_or(0, |q| q.0);
+        account_state.queued_withdrawal = Some(U128(
+            queued
+                .checked_sub(pending.assets.0)
+                .expect("queued_withdrawal underflow"),
+        ));
+        self.accounts.insert(&pending.account, &account_state);
+
+        ext_ft_core::ext(self.asset.clone())
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(
+                pending.account.clone(),
+                pending.assets,
+                Some("Queued withdrawal".to_string()),
+            )
             .then(
                 ext_self::ext(env::current_account_id())
                     .with_static_gas(Gas::from_tgas(10))
-                    .resolve_withdraw(owner, receiver_id, U128(shares), U128(amount), memo),
+                    .resolve_queued_withdrawal(pending),
             )
     }
 
+    pub fn internal_transfer_assets_with_callback(&self, transfer: WithdrawalTransfer) -> Promise {
+        let receiver_id = transfer.receiver.clone();
+        let amount = transfer.assets;
+        let memo = transfer.memo.clone();
+        // Unwrapping burns the vault's wNEAR for NEAR, sent on to the receiver once it succeeded
+        let promise = if transfer.unwrap {
+            ext_wrap_near::ext(self.asset.clone())
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .near_withdraw(amount)
+        } else if let Some(bridge_msg) = transfer.bridge_msg.clone() {
+            ext_ft_core::ext(self.asset.clone())
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .with_static_gas(GAS_FOR_FT_TRANSFER_CALL)
+                .ft_transfer_call(receiver_id, amount, memo, bridge_msg)
+        } else {
+            ext_ft_core::ext(self.asset.clone())
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .ft_transfer(receiver_id, amount, memo)
+        };
+
+        promise.then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(self.internal_resolve_withdraw_gas(transfer.unwrap))
+                .resolve_withdraw(transfer),
+        )
+    }
+
+    /// Burns `shares_to_burn` and sends `assets_to_transfer` to the receiver. When idle liquidity
+    /// falls short, the missing part is first pulled back from the strategy, and the withdrawal
+    /// is rolled back if that realizes a loss above `max_loss_bps` of the quoted assets.
+    #[allow(clippy::too_many_arguments)]
     pub fn internal_execute_withdrawal(
         &mut self,
         owner: AccountId,
@@ -47,27 +1317,64 @@ impl TokenizedVault {
         shares_to_burn: u128,
         assets_to_transfer: u128,
         memo: Option<String>,
+        unwrap: bool,
+        max_loss_bps: Option<u16>,
+        bridge_msg: Option<String>,
     ) -> Promise {
         let receiver_id = receiver_id.unwrap_or(owner.clone());
 
+        let mut owner_state = self.internal_account_state(&owner);
+
         // Checks
+        assert_ne!(
+            receiver_id, self.asset,
+            "Cannot withdraw to the asset contract"
+        );
+        assert!(!unwrap || self.is_wrap_near, "Asset is not wNEAR");
         assert!(
             self.token.ft_balance_of(owner.clone()).0 >= shares_to_burn,
             "Insufficient shares"
         );
+        self.assert_not_locked(&owner_state);
+        self.assert_unlocked_shares(&owner, shares_to_burn);
         assert!(assets_to_transfer > 0, "No assets to withdraw");
+        self.internal_record_outflow(assets_to_transfer);
+        let from_strategy = assets_to_transfer.saturating_sub(self.internal_available_assets());
         assert!(
-            assets_to_transfer <= self.total_assets,
+            from_strategy == 0
+                || (self.strategy.is_some() && from_strategy <= self.deployed_assets),
             "Insufficient vault assets"
         );
+        let max_loss_bps = max_loss_bps.unwrap_or(0);
+        assert!(max_loss_bps <= 10_000, "Invalid max loss");
+        // Only known here, which legs the chain takes
+        self.assert_gas_left(self.internal_withdrawal_gas(
+            from_strategy > 0,
+            unwrap,
+            bridge_msg.is_some(),
+        ));
 
         // Effects - CEI Pattern: Update state before external call
-        // Burn shares immediately (prevents reuse)
+        // The escrowed exit fee leaves the account too, it counts towards the shares withdrawn
+        let fee_shares = owner_state.pending_exit_fee.map_or(0, |fee| fee.0);
+        let held_shares = self.token.ft_balance_of(owner.clone()).0 + fee_shares;
+        let deposited = owner_state.take_deposited(shares_to_burn + fee_shares, held_shares);
+        owner_state.pending_deposited = Some(U128(deposited));
+        // Burn shares immediately (prevents reuse) and lock the owner until the callback settles
         self.token.internal_withdraw(&owner, shares_to_burn);
-        self.total_assets = self
-            .total_assets
-            .checked_sub(assets_to_transfer)
-            .expect("total_assets underflow");
+        owner_state.withdrawal_locked = true;
+        self.accounts.insert(&owner, &owner_state);
+        self.internal_decrease_total_assets(assets_to_transfer);
+        // Liquidated assets are booked out of the strategy now and in flight once they arrive
+        self.deployed_assets = self
+            .deployed_assets
+            .checked_sub(from_strategy)
+            .expect("deployed_assets underflow");
+        self.withdrawals_in_flight = self
+            .withdrawals_in_flight
+            .checked_add(assets_to_transfer - from_strategy)
+            .expect("withdrawals_in_flight overflow");
+        self.internal_record_price();
 
         FtBurn {
             owner_id: &owner,
@@ -77,40 +1384,416 @@ impl TokenizedVault {
         .emit();
 
         // Interactions - External call
-        self.internal_transfer_assets_with_callback(
+        if from_strategy > 0 {
+            return ext_strategy::ext(self.strategy.clone().unwrap())
+                .with_static_gas(GAS_FOR_STRATEGY_WITHDRAW)
+                .withdraw(U128(from_strategy))
+                .then(
+                    ext_self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_RESOLVE_STRATEGY_WITHDRAWAL.saturating_add(
+                            self.internal_transfer_gas(unwrap, bridge_msg.is_some()),
+                        ))
+                        .resolve_strategy_withdrawal(StrategyWithdrawal {
+                            owner,
+                            receiver: receiver_id,
+                            shares: U128(shares_to_burn),
+                            quoted: U128(assets_to_transfer),
+                            from_strategy: U128(from_strategy),
+                            memo,
+                            max_loss_bps,
+                            unwrap,
+                            bridge_msg,
+                        }),
+                );
+        }
+
+        self.internal_transfer_assets_with_callback(WithdrawalTransfer {
+            owner,
+            receiver: receiver_id,
+            shares: U128(shares_to_burn),
+            assets: U128(assets_to_transfer),
+            memo,
+            unwrap,
+            bridge_msg,
+        })
+    }
+
+    /// Redeems `shares` of `owner` after the caller's checks, charging the exit fee on them.
+    pub fn internal_redeem(&mut self, request: RedeemRequest) -> Promise {
+        let RedeemRequest {
+            owner,
+            shares,
             receiver_id,
-            assets_to_transfer,
+            memo,
+            unwrap,
+            max_loss_bps,
+            msg,
+            max_fee_bps,
+        } = request;
+        let shares = shares.0;
+        self.assert_exit_fee_within(&owner, max_fee_bps);
+        let parsed_msg = WithdrawMessage::parse(msg.as_deref());
+        parsed_msg.assert_not_expired();
+        let memo = memo.or(parsed_msg.memo);
+        assert_memo_len(memo.as_deref());
+
+        self.internal_stash_withdraw_referral(&owner, parsed_msg.referral);
+
+        assert!(
+            shares <= self.max_redeem(owner.clone()).0,
+            "Exceeds max redeem"
+        );
+
+        let fee_shares = self.internal_exit_fee_shares(&owner, shares);
+        self.internal_escrow_exit_fee(&owner, fee_shares);
+        let shares = shares - fee_shares;
+
+        let assets = self.internal_convert_to_assets(shares, Rounding::Down);
+
+        self.internal_execute_withdrawal(
             owner,
-            shares_to_burn,
+            receiver_id,
+            shares,
+            assets,
             memo,
+            unwrap,
+            max_loss_bps,
+            None,
         )
     }
 
-    pub fn internal_convert_to_shares(&self, assets: u128, rounding: Rounding) -> u128 {
-        let total_supply = self.token.ft_total_supply().0;
+    /// Queries the vault's asset balance before crediting a deposit, so that only the amount
+    /// actually received is credited. Only one verification may be in flight at a time,
+    /// otherwise concurrent deposits would be attributed each other's balance changes.
+    pub fn internal_verify_received(
+        &mut self,
+        sender_id: AccountId,
+        amount: u128,
+        msg: String,
+    ) -> Promise {
+        assert!(
+            !self.deposit_verification_pending,
+            "Another deposit is being verified, try again"
+        );
+        self.deposit_verification_pending = true;
+
+        self.query_asset_balance().then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_RESOLVE_VERIFIED_DEPOSIT)
+                .resolve_verified_deposit(sender_id, U128(amount), msg),
+        )
+    }
+
+    /// Credits a deposit of `amount` assets received from `sender_id`, returning the amount
+    /// left unused that the asset contract refunds to the sender.
+    pub fn internal_handle_deposit(
+        &mut self,
+        sender_id: AccountId,
+        amount: u128,
+        parsed_msg: DepositMessage,
+    ) -> u128 {
+        if parsed_msg.donate.unwrap_or(false) {
+            self.internal_increase_total_assets(amount);
+            self.internal_accrue_performance_fee();
+            self.internal_record_price();
+
+            return 0;
+        }
+
+        let owner_id = parsed_msg.share_receiver(&sender_id);
+
+        // Shares minted to the vault itself would inflate supply without a real holder
+        if *owner_id == env::current_account_id() {
+            return amount;
+        }
+
+        // Refund rather than panic inside internal_deposit when the receiver can't hold shares
+        if !self.token.accounts.contains_key(owner_id) {
+            env::log_str("Share receiver not registered");
+            return amount;
+        }
+
+        let quote = self.internal_quote_deposit(amount, parsed_msg.max_shares.map(|max| max.0));
+        let shares = quote.shares.0;
+        let used_amount = quote.used_assets.0;
+
+        // Check slippage protection - if min_shares requirement can't be met, reject the deposit
+        if let Some(min_shares) = parsed_msg.min_shares {
+            if shares < min_shares.0 {
+                // Return all amount as unused (reject the entire deposit)
+                return amount;
+            }
+        }
+
+        if self.internal_exceeds_deposit_slippage(amount) {
+            env::log_str("Deposit slippage above the vault's bound");
+            return amount;
+        }
+
+        if self.internal_is_dust_for_receiver(&sender_id, owner_id, shares) {
+            env::log_str("Too few shares for another receiver");
+            return amount;
+        }
+
+        let unused_amount = amount
+            .checked_sub(used_amount)
+            .expect("Overflow in unused amount calculation");
+
+        // Too small to mint a share, the dust accrues to the holders unless it's refunded
+        if shares == 0 && !self.reject_zero_share_deposits && self.token.ft_total_supply().0 > 0 {
+            let kept = self.internal_accepted_deposit(amount);
+            self.internal_increase_total_assets(kept);
+            self.internal_record_price();
+
+            return amount - kept;
+        }
+
+        // Nothing to credit, refund rather than panic so that deposit callbacks always settle
+        if shares == 0 || used_amount == 0 {
+            return amount;
+        }
 
-        // Handle empty vault case - return 1:1 ratio with extra decimals for first deposit
-        if total_supply == 0 {
-            return assets * 10u128.pow(self.extra_decimals as u32);
+        if quote.dead_shares.0 > 0 {
+            self.token
+                .internal_deposit(&env::current_account_id(), quote.dead_shares.0);
+            FtMint {
+                owner_id: &env::current_account_id(),
+                amount: quote.dead_shares,
+                memo: Some("Dead shares"),
+            }
+            .emit();
         }
+        self.token.internal_deposit(owner_id, shares);
+        self.internal_increase_total_assets(used_amount);
+        self.internal_record_price();
 
-        let supply_adj = total_supply;
-        let assets_adj = self.total_assets + 1;
+        self.internal_record_deposit(&sender_id, owner_id, used_amount, shares, &parsed_msg);
 
-        mul_div(assets, supply_adj, assets_adj, rounding)
+        unused_amount
     }
 
-    pub fn internal_convert_to_assets(&self, shares: u128, rounding: Rounding) -> u128 {
-        let total_supply = self.token.ft_total_supply().0;
+    /// Whether the vault's caps and dead shares leave a deposit of `amount` with fewer shares
+    /// than `max_deposit_slippage_bps` allows below its plain conversion. A `max_shares` the
+    /// depositor asked for isn't slippage and is left out.
+    pub fn internal_exceeds_deposit_slippage(&self, amount: u128) -> bool {
+        let Some(max_slippage_bps) = self.max_deposit_slippage_bps else {
+            return false;
+        };
+        let expected = self.internal_convert_to_shares(amount, Rounding::Down);
+        let min_shares = mul_div(
+            expected,
+            10_000 - max_slippage_bps as u128,
+            10_000,
+            Rounding::Up,
+        );
+
+        self.internal_quote_deposit(amount, None).shares.0 < min_shares
+    }
+
+    /// Logs why a deposit of `amount` is refunded in full, returning it as unused.
+    pub fn internal_reject_deposit(
+        &self,
+        sender_id: &AccountId,
+        amount: u128,
+        reason: &str,
+    ) -> U128 {
+        VaultDepositRejected {
+            sender_id,
+            amount: U128(amount),
+            reason,
+        }
+        .emit();
+        U128(amount)
+    }
+
+    /// Shares minted for a deposit of `amount` and the part of it used, the rest is refunded.
+    /// Deposits and their quotes both go through here so they can't diverge.
+    pub fn internal_quote_deposit(&self, amount: u128, max_shares: Option<u128>) -> DepositQuote {
+        let calculated_shares =
+            self.internal_convert_to_shares(self.internal_accepted_deposit(amount), Rounding::Down);
+        // The first deposit pays for the dead shares out of its own
+        let dead_shares = if self.token.ft_total_supply().0 == 0 {
+            self.dead_shares.min(calculated_shares)
+        } else {
+            0
+        };
+        let shares = calculated_shares - dead_shares;
+        let shares = max_shares.map_or(shares, |max| shares.min(max));
+
+        // Rounding up keeps the vault whole: it never holds less than the shares are worth
+        // rounded down, and as the shares themselves were rounded down this can't exceed the
+        // accepted amount, so the refund never goes negative
+        let used_assets = self.internal_convert_to_assets(shares + dead_shares, Rounding::Up);
+        DepositQuote {
+            shares: U128(shares),
+            fee_assets: U128(0),
+            used_assets: U128(used_assets),
+            dead_shares: U128(dead_shares),
+            refund: U128(amount.saturating_sub(used_assets)),
+        }
+    }
+
+    /// Whether `shares` deposited by `sender_id` for another `owner_id` are below the minimum
+    /// a receiver can be credited.
+    pub fn internal_is_dust_for_receiver(
+        &self,
+        sender_id: &AccountId,
+        owner_id: &AccountId,
+        shares: u128,
+    ) -> bool {
+        owner_id != sender_id && shares < self.min_shares_per_receiver
+    }
+
+    /// Part of a deposit of `amount` the caps let in, the excess is refunded.
+    pub fn internal_accepted_deposit(&self, amount: u128) -> u128 {
+        let room = self.total_assets_cap.map_or(u128::MAX, |cap| {
+            cap.saturating_sub(self.internal_total_value())
+        });
+        self.max_deposit_per_tx
+            .map_or(amount, |max| amount.min(max))
+            .min(room)
+    }
+
+    /// Account bookkeeping and events of a deposit that minted `shares` for `assets` worth.
+    pub fn internal_record_deposit(
+        &mut self,
+        sender_id: &AccountId,
+        owner_id: &AccountId,
+        assets: u128,
+        shares: u128,
+        parsed_msg: &DepositMessage,
+    ) {
+        let mut owner_state = self.internal_account_state(owner_id);
+        let deposited = owner_state.deposited.map_or(0, |d| d.0);
+        owner_state.deposited = Some(U128(
+            deposited.checked_add(assets).expect("deposited overflow"),
+        ));
+        // Staked shares leave the account right away, whatever the farm refuses is locked then
+        if self.share_lock_period_ns > 0 && parsed_msg.stake_to.is_none() {
+            owner_state.lock_shares(
+                shares,
+                env::block_timestamp().saturating_add(self.share_lock_period_ns),
+            );
+        }
+        // Shares held before may have arrived by transfer without an entry time, count them as new
+        let held_shares = self.token.ft_balance_of(owner_id.clone()).0 - shares;
+        let entry_ns = owner_state
+            .entry_ns
+            .map_or(env::block_timestamp(), |entry| entry.0);
+        owner_state.entry_ns = Some(U64(((U256::from(entry_ns) * U256::from(held_shares)
+            + U256::from(env::block_timestamp()) * U256::from(shares))
+            / U256::from(held_shares + shares))
+        .as_u64()));
+        // Priced like `price_per_share`, at what this deposit actually paid per share
+        let paid_price = mul_div(
+            assets,
+            pow10(self.metadata.decimals),
+            shares,
+            Rounding::Nearest,
+        );
+        let entry_price = owner_state.entry_price.map_or(paid_price, |price| price.0);
+        owner_state.entry_price = Some(U128(
+            ((U256::from(entry_price) * U256::from(held_shares)
+                + U256::from(paid_price) * U256::from(shares))
+                / U256::from(held_shares + shares))
+            .as_u128(),
+        ));
+        self.accounts.insert(owner_id, &owner_state);
+
+        FtMint {
+            owner_id,
+            amount: U128(shares),
+            memo: Some("Deposit"),
+        }
+        .emit();
+
+        // Emit VaultDeposit event
+        if self.emit_vault_events {
+            VaultDeposit {
+                sender_id,
+                owner_id,
+                assets: U128(assets),
+                shares: U128(shares),
+                memo: parsed_msg.memo.as_deref(),
+            }
+            .emit();
+
+            if let Some(referrer) = parsed_msg.referral.as_ref() {
+                VaultReferral {
+                    referrer,
+                    depositor: sender_id,
+                    assets: U128(assets),
+                    shares: U128(shares),
+                }
+                .emit();
+            }
+        }
+
+        if let Some(farm) = parsed_msg.stake_to.as_ref() {
+            self.internal_stake_shares(
+                owner_id,
+                farm,
+                shares,
+                parsed_msg.stake_msg.clone().unwrap_or_default(),
+            );
+        }
+    }
+
+    /// Panics unless `farm` is the configured farm and can hold shares.
+    pub fn assert_stake_target(&self, farm: &AccountId) {
+        assert_eq!(Some(farm), self.farm.as_ref(), "Not the configured farm");
+        assert!(
+            self.token.accounts.contains_key(farm),
+            "Farm not registered on vault"
+        );
+    }
 
-        // For empty vault, assume 1:1 ratio with extra decimals for consistency
-        if total_supply == 0 {
-            return shares / 10u128.pow(self.extra_decimals as u32);
+    /// Moves freshly minted `shares` of `owner_id` into the farm like `ft_transfer_call` would,
+    /// so whatever the farm refuses goes back to the owner as shares.
+    pub fn internal_stake_shares(
+        &mut self,
+        owner_id: &AccountId,
+        farm: &AccountId,
+        shares: u128,
+        msg: String,
+    ) -> Promise {
+        self.assert_stake_target(farm);
+        self.internal_carry_entry_time(owner_id, farm, shares);
+        self.token
+            .internal_transfer(owner_id, farm, shares, Some("Farm stake".to_string()));
+
+        ext_ft_receiver::ext(farm.clone())
+            .with_static_gas(GAS_FOR_FARM_STAKE)
+            .ft_on_transfer(owner_id.clone(), U128(shares), msg)
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_FARM_STAKE)
+                    .resolve_farm_stake(owner_id.clone(), farm.clone(), U128(shares)),
+            )
+    }
+
+    /// Snapshot of the current supply and value, for pricing many amounts in one go.
+    pub(crate) fn internal_conversion_snapshot(&self) -> ConversionSnapshot<'_> {
+        let total_supply = self.token.ft_total_supply().0;
+        ConversionSnapshot {
+            vault: self,
+            total_supply,
+            // The value only matters once shares exist
+            total_value: if total_supply == 0 {
+                0
+            } else {
+                self.internal_total_value()
+            },
         }
+    }
 
-        let supply_adj = total_supply;
-        let assets_adj = self.total_assets + 1;
+    pub fn internal_convert_to_shares(&self, assets: u128, rounding: Rounding) -> u128 {
+        self.internal_conversion_snapshot()
+            .convert_to_shares(assets, rounding)
+    }
 
-        mul_div(shares, assets_adj, supply_adj, rounding)
+    pub fn internal_convert_to_assets(&self, shares: u128, rounding: Rounding) -> u128 {
+        self.internal_conversion_snapshot()
+            .convert_to_assets(shares, rounding)
     }
 }
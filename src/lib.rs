@@ -1,291 +1,2713 @@
 mod contract_standards;
+mod init;
 mod internal;
+pub mod math;
 mod mul_div;
+mod vault_core;
+
+pub use contract_standards::events;
 
 use near_contract_standards::fungible_token::{
-    core::FungibleTokenCore,
+    core::{ext_ft_core, FungibleTokenCore},
     core_impl::FungibleToken,
-    events::FtMint,
-    metadata::{FungibleTokenMetadata, FungibleTokenMetadataProvider},
+    events::{FtBurn, FtMint},
+    metadata::{ext_ft_metadata, FungibleTokenMetadata, FungibleTokenMetadataProvider},
     receiver::FungibleTokenReceiver,
     FungibleTokenResolver,
 };
-use near_contract_standards::storage_management::StorageManagement;
+use near_contract_standards::storage_management::{
+    ext_storage_management, StorageBalance, StorageManagement,
+};
 use near_sdk::{
     assert_one_yocto,
     borsh::{self, BorshDeserialize, BorshSerialize},
-    serde::Deserialize,
+    serde::{Deserialize, Serialize},
+};
+use near_sdk::{
+    collections::{LookupMap, UnorderedSet, Vector},
+    json_types::{I128, U128, U64},
+    BorshStorageKey,
+};
+use near_sdk::{
+    env, near_bindgen, AccountId, Gas, NearToken, PanicOnDefault, Promise, PromiseError,
+    PromiseOrValue,
+};
+use serde_json::json;
+
+use crate::contract_standards::events::{
+    VaultAssetMigrated, VaultDecimalsMismatch, VaultSharesBurned, VaultWithdraw,
+    VaultWithdrawReferral,
 };
-use near_sdk::{env, near_bindgen, AccountId, Gas, NearToken, PanicOnDefault, PromiseOrValue};
-use near_sdk::{json_types::U128, BorshStorageKey};
+use crate::contract_standards::{VaultCore, WithdrawOutcome};
+use crate::internal::{ext_price_oracle, ext_self, ext_strategy, ext_vault};
+use crate::mul_div::{mul_div, pow10, Rounding};
+
+const GAS_FOR_FT_TRANSFER: Gas = Gas::from_tgas(30);
+const GAS_FOR_FT_TRANSFER_CALL: Gas = Gas::from_tgas(60);
+const GAS_FOR_FT_BALANCE_OF: Gas = Gas::from_tgas(10);
+const GAS_FOR_FT_METADATA: Gas = Gas::from_tgas(10);
+/// Covers the crediting and settling callbacks it schedules
+const GAS_FOR_RESOLVE_VERIFIED_DEPOSIT: Gas = Gas::from_tgas(45);
+const GAS_FOR_CREDIT_VERIFIED_DEPOSIT: Gas = Gas::from_tgas(30);
+const GAS_FOR_SETTLE_VERIFIED_DEPOSIT: Gas = Gas::from_tgas(5);
+const GAS_FOR_ON_CHECK_INVARIANTS: Gas = Gas::from_tgas(10);
+const GAS_FOR_STRATEGY_WITHDRAW: Gas = Gas::from_tgas(40);
+const GAS_FOR_STRATEGY_DEPOSIT: Gas = Gas::from_tgas(10);
+const GAS_FOR_STRATEGY_BALANCE: Gas = Gas::from_tgas(20);
+const GAS_FOR_STRATEGY_CLAIM: Gas = Gas::from_tgas(40);
+const GAS_FOR_RESOLVE_INVEST: Gas = Gas::from_tgas(25);
+const GAS_FOR_COMPOUND_FROM_STRATEGY: Gas = Gas::from_tgas(90);
+/// Spent by `resolve_strategy_withdrawal` itself, the transfer it goes on to comes on top
+const GAS_FOR_RESOLVE_STRATEGY_WITHDRAWAL: Gas = Gas::from_tgas(20);
+const GAS_FOR_WITHDRAW_HOOK: Gas = Gas::from_tgas(10);
+const GAS_FOR_RESOLVE_WITHDRAW: Gas = Gas::from_tgas(20);
+const GAS_FOR_RESOLVE_UNWRAPPED_TRANSFER: Gas = Gas::from_tgas(5);
+const PRICE_OBSERVATIONS_CAP: u64 = 32;
+const MAX_MEMO_LEN: usize = 256;
+const MAX_BATCH_VIEW_ACCOUNTS: usize = 100;
+const GAS_FOR_RESOLVE_BASKET_TRANSFER: Gas = Gas::from_tgas(20);
+/// Bounded by the gas `redeem_in_kind` needs to pay out every token next to the asset
+const MAX_BASKET_ASSETS: usize = 3;
+const MAX_SPLIT_RECEIVERS: usize = 4;
+const MAX_SHARE_LOTS: usize = 8;
+const GAS_FOR_FARM_STAKE: Gas = Gas::from_tgas(30);
+const GAS_FOR_RESOLVE_FARM_STAKE: Gas = Gas::from_tgas(10);
+const GAS_FOR_ORACLE_PRICE: Gas = Gas::from_tgas(10);
+const GAS_FOR_MIGRATION_REDEEM: Gas = Gas::from_tgas(100);
+const GAS_FOR_RESOLVE_MIGRATION: Gas = Gas::from_tgas(60);
+const GAS_FOR_RESOLVE_MIGRATION_REFUND: Gas = Gas::from_tgas(5);
+const GAS_FOR_VAULT_ASSET: Gas = Gas::from_tgas(5);
+const GAS_FOR_RESOLVE_MIGRATION_SOURCE: Gas = Gas::from_tgas(5);
+const GAS_FOR_STORAGE_BALANCE_OF: Gas = Gas::from_tgas(10);
+/// Spent by `redeem_to_registered_receiver` itself, the withdrawal it goes on to comes on top
+const GAS_FOR_REDEEM_AFTER_STORAGE_CHECK: Gas = Gas::from_tgas(20);
+const GAS_FOR_STORAGE_DEPOSIT: Gas = Gas::from_tgas(10);
+const GAS_FOR_RESOLVE_REGISTER_WITH_ASSET: Gas = Gas::from_tgas(25);
+const GAS_FOR_RESOLVE_ASSET_STORAGE_DEPOSIT: Gas = Gas::from_tgas(5);
+/// Left to a withdrawing call itself, on top of the promises it builds
+const GAS_FOR_WITHDRAW_OVERHEAD: Gas = Gas::from_tgas(10);
+/// Kept on top of the storage staked for the current state by `withdraw_stranded_near`, for the
+/// state the vault grows on its own, like price observations and queued withdrawals.
+const STRANDED_NEAR_BUFFER: NearToken = NearToken::from_near(1);
+const BASKET_PRICE_SCALE: u128 = 1_000_000_000_000_000_000_000_000;
+/// Nanoseconds in a 365-day year, used to annualize returns
+const YEAR_NS: u64 = 365 * 24 * 60 * 60 * 1_000_000_000;
+
+/// Discriminant a non-empty `ft_transfer_call` message must carry to be taken as a deposit.
+#[derive(Deserialize, PartialEq)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum DepositAction {
+    Deposit,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DepositMessage {
+    /// Required unless the message is empty, so that a message meant for another contract
+    /// isn't taken for a plain deposit
+    action: Option<DepositAction>,
+    min_shares: Option<U128>,
+    max_shares: Option<U128>,
+    receiver_id: Option<AccountId>,
+    /// Credits the shares to the sender, overriding `receiver_id`
+    self_receive: Option<bool>,
+    memo: Option<String>,
+    donate: Option<bool>,
+    referral: Option<AccountId>,
+    /// Stakes the minted shares into this farm, which must be the configured one
+    stake_to: Option<AccountId>,
+    /// Passed on to the farm's `ft_on_transfer`
+    stake_msg: Option<String>,
+}
+
+impl DepositMessage {
+    /// Plain deposits skip JSON parsing altogether. `None` when `msg` isn't valid JSON for a
+    /// deposit or lacks its `action`, so that the caller refunds it rather than deposit with
+    /// default options.
+    fn try_parse(msg: &str) -> Option<Self> {
+        if msg.is_empty() || msg == "{}" {
+            return Some(Self::default());
+        }
+
+        let parsed: Self = serde_json::from_str(msg).ok()?;
+        // Unknown fields are ignored, a foreign message would otherwise parse as a deposit
+        (parsed.action == Some(DepositAction::Deposit)).then_some(parsed)
+    }
+
+    /// For messages already accepted by `ft_on_transfer`.
+    fn parse(msg: &str) -> Self {
+        Self::try_parse(msg).unwrap_or_else(|| env::panic_str("Failed to parse deposit message"))
+    }
+
+    /// The account credited with the shares: the sender when `self_receive` is set, else
+    /// `receiver_id`, else the sender.
+    fn share_receiver<'a>(&'a self, sender_id: &'a AccountId) -> &'a AccountId {
+        if self.self_receive.unwrap_or(false) {
+            return sender_id;
+        }
+        self.receiver_id.as_ref().unwrap_or(sender_id)
+    }
+}
+
+/// Optional JSON `msg` of `redeem` and `withdraw`, the counterpart of `DepositMessage`.
+#[derive(Deserialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WithdrawMessage {
+    /// Used when no `memo` argument is given
+    memo: Option<String>,
+    referral: Option<AccountId>,
+    /// Block timestamp after which the withdrawal is refused
+    deadline_ns: Option<U64>,
+}
+
+impl WithdrawMessage {
+    fn parse(msg: Option<&str>) -> Self {
+        match msg {
+            None | Some("") => Self::default(),
+            Some(msg) => serde_json::from_str(msg)
+                .unwrap_or_else(|_| env::panic_str("Failed to parse withdraw message")),
+        }
+    }
+
+    fn assert_not_expired(&self) {
+        if let Some(deadline_ns) = self.deadline_ns {
+            assert!(
+                env::block_timestamp() <= deadline_ns.0,
+                "Withdrawal deadline passed"
+            );
+        }
+    }
+}
+
+/// Per-account vault bookkeeping, kept in a single record so that features touching the same
+/// account share one storage read and write.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountState {
+    /// Assets deposited for this account's shares, reduced in proportion to the shares withdrawn
+    pub deposited: Option<U128>,
+    /// Whether a withdrawal of this account is awaiting its callback
+    pub withdrawal_locked: bool,
+    /// Assets owed to this account by queued withdrawals that haven't been paid out yet
+    pub queued_withdrawal: Option<U128>,
+    /// Share-weighted average time this account's deposits were made, drives the exit fee
+    pub entry_ns: Option<U64>,
+    /// Exit fee shares escrowed by the vault while this account's withdrawal is in flight
+    pub pending_exit_fee: Option<U128>,
+    /// Deposited assets of the shares burned by this account's in-flight withdrawal
+    pub pending_deposited: Option<U128>,
+    /// Referrer of this account's in-flight withdrawal, logged once it went through
+    pub pending_referral: Option<AccountId>,
+    /// Shares a rollback of this account's in-flight withdrawal restores, when fewer than burned
+    pub pending_rollback_shares: Option<U128>,
+    /// Basket legs of this account's in-kind redeem still awaiting their callback
+    pub pending_basket_legs: u8,
+    /// Minted shares still under `share_lock_period_ns`, oldest first
+    pub share_lots: Vec<ShareLot>,
+    /// Share-weighted average price this account's deposits paid, scaled like `price_per_share`
+    pub entry_price: Option<U128>,
+    /// Assets withdrawn minus the deposited assets of the shares they came from, summed
+    pub realized_pnl: Option<I128>,
+}
+
+/// Shares minted by one deposit, which can't leave the account before `unlocks_ns`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ShareLot {
+    pub shares: U128,
+    pub unlocks_ns: U64,
+}
+
+impl AccountState {
+    /// The largest record an account can have, measured to price its storage on registration.
+    fn max_sized() -> Self {
+        Self {
+            deposited: Some(U128(u128::MAX)),
+            withdrawal_locked: true,
+            queued_withdrawal: Some(U128(u128::MAX)),
+            entry_ns: Some(U64(u64::MAX)),
+            pending_exit_fee: Some(U128(u128::MAX)),
+            pending_deposited: Some(U128(u128::MAX)),
+            pending_referral: Some("a".repeat(64).parse().unwrap()),
+            pending_rollback_shares: Some(U128(u128::MAX)),
+            pending_basket_legs: u8::MAX,
+            share_lots: vec![
+                ShareLot {
+                    shares: U128(u128::MAX),
+                    unlocks_ns: U64(u64::MAX),
+                };
+                MAX_SHARE_LOTS
+            ],
+            entry_price: Some(U128(u128::MAX)),
+            realized_pnl: Some(I128(i128::MIN)),
+        }
+    }
+
+    /// Books the gain (or loss) of `assets_out` withdrawn for shares deposited at `basis`.
+    fn realize_pnl(&mut self, assets_out: u128, basis: u128) {
+        let realized = self.realized_pnl.map_or(0, |pnl| pnl.0);
+        self.realized_pnl = Some(I128(
+            realized
+                .checked_add(assets_out as i128 - basis as i128)
+                .expect("realized_pnl overflow"),
+        ));
+    }
+
+    /// Shares of the lots still locked at the current block.
+    fn locked_shares(&self) -> u128 {
+        let now = env::block_timestamp();
+        self.share_lots
+            .iter()
+            .filter(|lot| lot.unlocks_ns.0 > now)
+            .map(|lot| lot.shares.0)
+            .sum()
+    }
+
+    /// Locks `shares` until `unlocks_ns`, dropping the lots that expired. Once the lots are
+    /// full, the newest one takes the shares and its lock is extended to `unlocks_ns`.
+    fn lock_shares(&mut self, shares: u128, unlocks_ns: u64) {
+        let now = env::block_timestamp();
+        self.share_lots.retain(|lot| lot.unlocks_ns.0 > now);
+        let full = self.share_lots.len() == MAX_SHARE_LOTS;
+        match self.share_lots.last_mut() {
+            Some(last) if full => {
+                last.shares = U128(last.shares.0 + shares);
+                last.unlocks_ns = U64(unlocks_ns);
+            }
+            _ => self.share_lots.push(ShareLot {
+                shares: U128(shares),
+                unlocks_ns: U64(unlocks_ns),
+            }),
+        }
+    }
+
+    /// Removes the deposited assets backing `shares` out of `held_shares`, returning them.
+    fn take_deposited(&mut self, shares: u128, held_shares: u128) -> u128 {
+        let deposited = self.deposited.map_or(0, |d| d.0);
+        if held_shares == 0 {
+            return 0;
+        }
+        let taken = mul_div(
+            deposited,
+            shares.min(held_shares),
+            held_shares,
+            Rounding::Down,
+        );
+        self.deposited = Some(U128(deposited - taken));
+        taken
+    }
+
+    /// Gives back `amount` of deposited assets taken by a withdrawal that got rolled back.
+    fn restore_deposited(&mut self, amount: u128) {
+        let deposited = self.deposited.map_or(0, |d| d.0);
+        self.deposited = Some(U128(
+            deposited.checked_add(amount).expect("deposited overflow"),
+        ));
+    }
+
+    /// Drops the deposited assets escrowed by the in-flight withdrawal once it went through,
+    /// or restores them when it was rolled back.
+    fn settle_deposited(&mut self, succeeded: bool) {
+        if let Some(pending) = self.pending_deposited.take() {
+            if !succeeded {
+                self.restore_deposited(pending.0);
+            }
+        }
+    }
+}
+
+/// A withdrawal waiting on the strategy to return the part idle liquidity couldn't cover.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StrategyWithdrawal {
+    pub owner: AccountId,
+    pub receiver: AccountId,
+    pub shares: U128,
+    /// Assets the burned shares were worth
+    pub quoted: U128,
+    /// Part of `quoted` requested from the strategy
+    pub from_strategy: U128,
+    pub memo: Option<String>,
+    pub max_loss_bps: u16,
+    pub unwrap: bool,
+    pub bridge_msg: Option<String>,
+}
+
+/// An asset transfer out of the vault, settled by `resolve_withdraw`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WithdrawalTransfer {
+    pub owner: AccountId,
+    pub receiver: AccountId,
+    /// Shares burned for the transfer
+    pub shares: U128,
+    pub assets: U128,
+    pub memo: Option<String>,
+    pub unwrap: bool,
+    pub bridge_msg: Option<String>,
+}
+
+/// A `redeem` carried through the receiver's storage check.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RedeemRequest {
+    pub owner: AccountId,
+    pub shares: U128,
+    pub receiver_id: Option<AccountId>,
+    pub memo: Option<String>,
+    pub unwrap: bool,
+    pub max_loss_bps: Option<u16>,
+    pub msg: Option<String>,
+    pub max_fee_bps: Option<u16>,
+}
+
+/// Price per share recorded after a state-changing operation, feeding the TWAP.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceObservation {
+    pub timestamp: U64,
+    pub price_per_share: U128,
+}
+
+/// A token held next to `asset`, valued in `asset` at a price reported by the owner.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BasketAsset {
+    pub token_id: AccountId,
+    pub balance: U128,
+    /// Units of `asset` one `BASKET_PRICE_SCALE` units of this token are worth
+    pub price: U128,
+}
+
+/// Outcome of the consistency checks run by `check_invariants`, `true` when a check holds.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InvariantReport {
+    /// Deployed assets don't exceed what the vault tracks, queued withdrawals included
+    pub deployed_within_assets: bool,
+    /// Reserved assets don't exceed what the vault tracks
+    pub reserved_within_assets: bool,
+    /// Queued withdrawals can be covered by the vault's idle assets
+    pub queue_within_idle: bool,
+    /// The asset balance covers the idle assets the vault tracks
+    pub assets_held: bool,
+    /// Shares exist only while the vault holds some value, dead shares included
+    pub supply_backed: bool,
+    pub all_hold: bool,
+}
+
+/// How `total_assets` learns about the assets the vault holds.
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, Debug, PartialEq,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub enum TotalAssetsMode {
+    /// Only deposits, withdrawals and reported yield move it
+    Tracked,
+    /// `refresh_total_assets` also books the vault's untracked asset balance as yield
+    BalanceBased,
+}
+
+/// What the amount passed to `exit` is denominated in.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ExitKind {
+    /// Shares to burn, as with `redeem`
+    Shares,
+    /// Assets to receive, as with `withdraw`
+    Assets,
+}
+
+/// Outcome of depositing an amount of assets, as computed by the deposit itself.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DepositQuote {
+    pub shares: U128,
+    /// Assets charged as entry fee, the vault doesn't charge one yet
+    pub fee_assets: U128,
+    /// Assets kept by the vault, the rest of the deposit is refunded. Rounded up, so at least
+    /// what the shares are worth and at most the assets deposited
+    pub used_assets: U128,
+    /// Shares locked in the vault for good out of the first deposit, paid by the depositor
+    pub dead_shares: U128,
+    /// Assets sent back to the depositor, those over the caps included
+    pub refund: U128,
+}
+
+/// A conversion whose input was capped at what an account can redeem or withdraw.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ClampedConversion {
+    pub value: U128,
+    /// Whether the input was above the account's max and the max was converted instead
+    pub clamped: bool,
+}
+
+/// A withdrawal whose shares are already burned, waiting for enough idle liquidity to be paid.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingWithdrawal {
+    pub account: AccountId,
+    pub assets: U128,
+    pub shares: U128,
+    pub timestamp: U64,
+}
+
+/// Memos end up in event logs, bound them so callers can't bloat logs for indexers.
+fn assert_memo_len(memo: Option<&str>) {
+    if let Some(memo) = memo {
+        assert!(memo.len() <= MAX_MEMO_LEN, "Memo too long");
+    }
+}
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct TokenizedVault {
+    pub token: FungibleToken,                     // Vault shares (NEP-141)
+    metadata: FungibleTokenMetadata,              // Metadata for shares
+    asset: AccountId,                             // Underlying asset (NEP-141 or NEP-245)
+    total_assets: u128,                           // Total managed assets
+    owner: AccountId,                             // Vault owner
+    extra_decimals: u8,                           // Extra decimals for shares (if any)
+    accounts: LookupMap<AccountId, AccountState>, // Per-account vault bookkeeping
+    verify_received: bool,                        // Credit deposits by the measured balance change
+    is_wrap_near: bool,                           // Asset is wNEAR, redeems may unwrap to NEAR
+    deposit_verification_pending: bool,           // A deposit is waiting for its balance query
+    withdrawals_in_flight: u128, // Assets sent out whose transfer hasn't settled yet
+    untracked_balance: u128,     // Asset balance above the tracked assets at the last balance query
+    inbound_assets: u128,        // Running sum of the asset received by `ft_on_transfer`, wrapping
+    last_inbound_height: u64, // Block of the last asset `ft_on_transfer`, its refund may be pending
+    pending_migrations: u32,  // Migrations whose assets may be on the vault's balance untracked
+    reserved_assets: u128,    // Assets earmarked for pending withdrawals, not yet sent
+    strategy: Option<AccountId>, // Account the owner invests idle assets into
+    deployed_assets: u128,    // Assets invested into the strategy
+    queued_assets: u128,      // Assets owed to queued withdrawals, no longer in total_assets
+    withdrawal_queue: Vector<PendingWithdrawal>, // Queued withdrawals, paid out FIFO
+    withdrawal_queue_head: u64, // Index of the next queued withdrawal to pay out
+    performance_fee_bps: u16, // Cut of share price gains above the high-water mark
+    fee_recipient: Option<AccountId>, // Receives fee shares
+    high_water_mark: u128,    // Highest price per share fees were charged up to
+    max_early_exit_bps: u16,  // Exit fee charged on shares redeemed right after depositing
+    exit_fee_decay_ns: u64,   // Time over which the exit fee decays linearly to zero
+    price_observations: Vector<PriceObservation>, // Ring buffer of recent prices per share
+    price_observations_next: u64, // Slot the next observation overwrites once the buffer is full
+    basket: Vec<BasketAsset>, // Tokens held next to `asset`, counted in `total_assets()`
+    paused: bool,             // Deposits and withdrawals are halted while set
+    withdraw_hook: Option<AccountId>, // Notified once each withdrawal settles
+    asset_decimals: Option<u8>, // Decimals of the asset, once read by `sync_asset_decimals`
+    max_deposit_per_tx: Option<u128>, // Largest amount a single transfer can deposit
+    dead_shares: u128, // Minted to the vault out of the first deposit so supply never drops to zero
+    registered_accounts: UnorderedSet<AccountId>, // Accounts registered for shares, enumerable
+    min_price_per_share: Option<u128>, // Owner price updates can't drop the price per share below
+    bridge_account: Option<AccountId>, // Receives the assets of `redeem_to_bridge` with `ft_transfer_call`
+    total_assets_mode: TotalAssetsMode, // Whether untracked balance is picked up as yield
+    total_assets_refreshed_at: u64, // Block height `total_assets` was last refreshed from the balance
+    guardian: Option<AccountId>,    // Can pause the vault, but nothing else
+    reject_zero_share_deposits: bool, // Refund deposits too small to mint a share instead of keeping them
+    farm: Option<AccountId>,          // Deposits with `stake_to` stake their shares here
+    share_lock_period_ns: u64, // Shares minted by a deposit stay in the depositor's account this long
+    emit_vault_events: bool,   // Off to log only the NEP-141 events of deposits and withdrawals
+    price_oracle: Option<AccountId>, // Prices the asset for `total_assets_usd`
+    price_oracle_decimals: u8, // Decimals the oracle's prices are scaled by
+    total_assets_cap: Option<u128>, // Deposits taking the vault's total value past this are refunded
+    migration_source: Option<AccountId>, // Older vault of the same asset whose shares can be migrated in
+    max_withdraw_per_window: Option<u128>, // Assets withdrawals can take out of the vault per window
+    withdraw_window_ns: u64, // Length of the window `max_withdraw_per_window` applies to
+    withdraw_window_start_ns: u64, // Block timestamp the current window started at
+    withdrawn_in_window: u128, // Assets withdrawn since the current window started
+    withdrawn_in_previous_window: u128, // Assets withdrawn in the window before the current one
+    min_shares_per_receiver: u128, // Fewest shares a deposit can credit to an account other than the sender
+    check_receiver_storage: bool, // `redeem` checks the receiver is registered with the asset before burning
+    max_deposit_slippage_bps: Option<u16>, // Deposits minting further below the plain conversion are refunded
+    fee_exempt: UnorderedSet<AccountId>,   // Accounts charged no exit fee
+}
+
+#[derive(BorshSerialize, BorshDeserialize, BorshStorageKey)]
+pub enum StorageKey {
+    FungibleToken,
+    AccountState,
+    WithdrawalQueue,
+    PriceObservations,
+    RegisteredAccounts,
+    FeeExempt,
+}
+
+#[near_bindgen]
+impl TokenizedVault {
+    /// Net assets `account_id` deposited for the shares it still holds, its cost basis.
+    pub fn deposited_assets_of(&self, account_id: AccountId) -> U128 {
+        self.internal_account_state(&account_id)
+            .deposited
+            .unwrap_or(U128(0))
+    }
+
+    /// Vault bookkeeping recorded for `account_id`, if any.
+    pub fn account_state_of(&self, account_id: AccountId) -> Option<AccountState> {
+        self.accounts.get(&account_id)
+    }
+
+    /// Assets currently redeemable by `account_id`'s shares.
+    pub fn assets_of(&self, account_id: AccountId) -> U128 {
+        self.convert_to_assets(self.token.ft_balance_of(account_id))
+    }
+
+    /// Share balances of `account_ids`, in the same order.
+    pub fn ft_balances_of(&self, account_ids: Vec<AccountId>) -> Vec<U128> {
+        assert!(
+            account_ids.len() <= MAX_BATCH_VIEW_ACCOUNTS,
+            "Too many accounts"
+        );
+        account_ids
+            .into_iter()
+            .map(|account_id| self.token.ft_balance_of(account_id))
+            .collect()
+    }
+
+    /// Accounts registered for shares and their balances, in registration order except for
+    /// unregistered accounts' slots taken over by the last one. At most 100 per page.
+    pub fn ft_accounts(&self, from_index: u64, limit: u64) -> Vec<(AccountId, U128)> {
+        let accounts = self.registered_accounts.as_vector();
+        let end = from_index
+            .saturating_add(limit.min(MAX_BATCH_VIEW_ACCOUNTS as u64))
+            .min(accounts.len());
+        (from_index..end)
+            .map(|index| {
+                let account_id = accounts.get(index).unwrap();
+                let balance = self.token.ft_balance_of(account_id.clone());
+                (account_id, balance)
+            })
+            .collect()
+    }
+
+    /// Assets redeemable by the shares of each of `account_ids`, in the same order.
+    pub fn assets_of_many(&self, account_ids: Vec<AccountId>) -> Vec<U128> {
+        let snapshot = self.internal_conversion_snapshot();
+        self.ft_balances_of(account_ids)
+            .into_iter()
+            .map(|shares| U128(snapshot.convert_to_assets(shares.0, Rounding::Down)))
+            .collect()
+    }
+
+    /// Assets not earmarked for pending withdrawals, available to fill new withdrawals.
+    pub fn available_assets(&self) -> U128 {
+        U128(self.internal_available_assets())
+    }
+
+    /// Shares `owner_id` can redeem right now out of the available assets, exit fee included.
+    /// Redeeming more has to pull assets back from the strategy first.
+    pub fn max_redeemable_now(&self, owner_id: AccountId) -> U128 {
+        let owner_state = self.internal_account_state(&owner_id);
+        if self.paused || owner_state.withdrawal_locked {
+            return U128(0);
+        }
+
+        let net_shares =
+            self.internal_convert_to_shares(self.internal_available_assets(), Rounding::Down);
+        // The exit fee is taken out of the redeemed shares, gross them up by it
+        let fee_bps = self.internal_exit_fee_bps(&owner_id);
+        let shares = mul_div(net_shares, 10_000, 10_000 - fee_bps, Rounding::Down);
+
+        U128(shares.min(self.token.ft_balance_of(owner_id).0))
+    }
+
+    /// Assets earmarked for pending withdrawals.
+    pub fn reserved_assets(&self) -> U128 {
+        U128(self.reserved_assets)
+    }
+
+    /// Halts deposits and withdrawals. The guardian can pause too, only the owner unpauses.
+    #[payable]
+    pub fn pause(&mut self) {
+        assert_one_yocto();
+        let caller = env::predecessor_account_id();
+        assert!(
+            caller == self.owner || self.guardian.as_ref() == Some(&caller),
+            "Not owner or guardian"
+        );
+        self.emit_admin_action("pause", json!({}));
+        self.paused = true;
+    }
+
+    #[payable]
+    pub fn unpause(&mut self) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("unpause", json!({}));
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Sets the account allowed to pause the vault next to the owner.
+    #[payable]
+    pub fn set_guardian(&mut self, guardian: Option<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("set_guardian", json!({ "guardian": guardian }));
+        self.guardian = guardian;
+    }
+
+    pub fn guardian(&self) -> Option<AccountId> {
+        self.guardian.clone()
+    }
+
+    /// Sets the contract notified through `on_vault_withdraw` once each withdrawal settles.
+    #[payable]
+    pub fn set_withdraw_hook(&mut self, hook_id: Option<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("set_withdraw_hook", json!({ "hook_id": hook_id }));
+        self.withdraw_hook = hook_id;
+    }
+
+    pub fn withdraw_hook(&self) -> Option<AccountId> {
+        self.withdraw_hook.clone()
+    }
+
+    /// Sets the bridge `redeem_to_bridge` forwards the redeemed assets to.
+    #[payable]
+    pub fn set_bridge_account(&mut self, bridge_account: Option<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action(
+            "set_bridge_account",
+            json!({ "bridge_account": bridge_account }),
+        );
+        self.bridge_account = bridge_account;
+    }
+
+    pub fn bridge_account(&self) -> Option<AccountId> {
+        self.bridge_account.clone()
+    }
+
+    /// Sets how long the shares minted by a deposit stay locked in the depositor's account,
+    /// zero to mint them transferable. Shares minted before keep their lock.
+    #[payable]
+    pub fn set_share_lock_period(&mut self, share_lock_period_ns: U64) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action(
+            "set_share_lock_period",
+            json!({ "share_lock_period_ns": share_lock_period_ns }),
+        );
+        self.share_lock_period_ns = share_lock_period_ns.0;
+    }
+
+    pub fn share_lock_period_ns(&self) -> U64 {
+        U64(self.share_lock_period_ns)
+    }
+
+    /// Average price `account_id` paid for its shares, scaled like `price_per_share`. Shares
+    /// received by transfer count as bought at the price of the next deposit.
+    pub fn entry_price_of(&self, account_id: AccountId) -> U128 {
+        self.internal_account_state(&account_id)
+            .entry_price
+            .unwrap_or(U128(0))
+    }
+
+    /// Gains (or losses) `account_id` realized by withdrawing, against its deposited assets.
+    pub fn realized_pnl_of(&self, account_id: AccountId) -> I128 {
+        self.internal_account_state(&account_id)
+            .realized_pnl
+            .unwrap_or(I128(0))
+    }
+
+    /// Shares `account_id` can transfer, withdraw or redeem, those still locked left out.
+    pub fn unlocked_shares_of(&self, account_id: AccountId) -> U128 {
+        U128(self.internal_unlocked_shares(&account_id))
+    }
+
+    /// Sets the farm deposits can stake their shares into with `stake_to`.
+    #[payable]
+    pub fn set_farm(&mut self, farm: Option<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("set_farm", json!({ "farm": farm }));
+        self.farm = farm;
+    }
+
+    pub fn farm(&self) -> Option<AccountId> {
+        self.farm.clone()
+    }
 
-use crate::contract_standards::events::{VaultDeposit, VaultWithdraw};
-use crate::contract_standards::VaultCore;
-use crate::mul_div::Rounding;
+    /// Sets the older vault of the same asset positions can be migrated from. Holders move in
+    /// with a single `ft_transfer_call` of their old shares to this vault, with the message
+    /// `"migrate:<old_vault>"`: the shares are redeemed and the assets deposited for them. The
+    /// old vault is only set once its `asset` is confirmed to be this vault's.
+    #[payable]
+    pub fn set_migration_source(
+        &mut self,
+        migration_source: Option<AccountId>,
+    ) -> PromiseOrValue<()> {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action(
+            "set_migration_source",
+            json!({ "migration_source": migration_source }),
+        );
+        let Some(old_vault) = migration_source else {
+            self.migration_source = None;
+            return PromiseOrValue::Value(());
+        };
+
+        PromiseOrValue::Promise(
+            ext_vault::ext(old_vault.clone())
+                .with_static_gas(GAS_FOR_VAULT_ASSET)
+                .asset()
+                .then(
+                    ext_self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_RESOLVE_MIGRATION_SOURCE)
+                        .resolve_set_migration_source(old_vault),
+                ),
+        )
+    }
+
+    #[private]
+    pub fn resolve_set_migration_source(
+        &mut self,
+        #[callback_result] asset: Result<AccountId, PromiseError>,
+        old_vault: AccountId,
+    ) {
+        assert_eq!(
+            asset.ok().as_ref(),
+            Some(&self.asset),
+            "Migration source holds another asset"
+        );
+        self.migration_source = Some(old_vault);
+    }
+
+    pub fn migration_source(&self) -> Option<AccountId> {
+        self.migration_source.clone()
+    }
+
+    /// Whether `redeem` first checks the receiver is registered with the asset, failing before
+    /// any share is burned instead of rolling back the failed transfer. Costs a round trip.
+    #[payable]
+    pub fn set_check_receiver_storage(&mut self, check_receiver_storage: bool) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action(
+            "set_check_receiver_storage",
+            json!({ "check_receiver_storage": check_receiver_storage }),
+        );
+        self.check_receiver_storage = check_receiver_storage;
+    }
+
+    pub fn check_receiver_storage(&self) -> bool {
+        self.check_receiver_storage
+    }
+
+    /// Registers the vault's own account with the asset, paying its storage out of the attached
+    /// NEAR, so that the vault can receive deposits and send withdrawals. A no-op refunding the
+    /// deposit once registered. The asset refunds what it doesn't need to the vault. Resolves to
+    /// the vault's storage balance on the asset.
+    #[payable]
+    pub fn register_with_asset(&mut self) -> Promise {
+        self.assert_owner();
+        let deposit = env::attached_deposit();
+        assert!(!deposit.is_zero(), "Requires attached deposit");
+        self.emit_admin_action(
+            "register_with_asset",
+            json!({ "deposit": U128(deposit.as_yoctonear()) }),
+        );
+
+        ext_storage_management::ext(self.asset.clone())
+            .with_static_gas(GAS_FOR_STORAGE_BALANCE_OF)
+            .storage_balance_of(env::current_account_id())
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_REGISTER_WITH_ASSET)
+                    .resolve_register_with_asset(
+                        env::predecessor_account_id(),
+                        U128(deposit.as_yoctonear()),
+                    ),
+            )
+    }
+
+    /// Redeems `shares` of the caller like `redeem` and forwards the assets to the bridge with
+    /// `ft_transfer_call`, passing `bridge_msg` (carrying the caller's destination address) as
+    /// the message. Whatever the bridge refunds is credited back to the caller in shares.
+    #[payable]
+    pub fn redeem_to_bridge(
+        &mut self,
+        shares: U128,
+        bridge_msg: String,
+    ) -> PromiseOrValue<WithdrawOutcome> {
+        assert_one_yocto();
+        self.assert_not_paused();
+        let bridge_account = self.bridge_account.clone().expect("No bridge set");
+
+        let owner = env::predecessor_account_id();
+
+        assert!(
+            shares.0 <= self.max_redeem(owner.clone()).0,
+            "Exceeds max redeem"
+        );
+
+        let fee_shares = self.internal_exit_fee_shares(&owner, shares.0);
+        self.internal_escrow_exit_fee(&owner, fee_shares);
+        let shares = U128(shares.0 - fee_shares);
+
+        let assets = self.internal_convert_to_assets(shares.0, Rounding::Down);
+
+        PromiseOrValue::Promise(self.internal_execute_withdrawal(
+            owner,
+            Some(bridge_account),
+            shares.0,
+            assets,
+            None,
+            false,
+            None,
+            Some(bridge_msg),
+        ))
+    }
+
+    /// Withdraws `assets` of the caller like `withdraw`, paid out of idle liquidity to several
+    /// receivers. Each receiver gets its weight in assets, so the weights must sum to `assets`.
+    /// The exit fee is charged up front, and a failed transfer only rolls back its own leg.
+    /// Returns the shares burned, the legs settle in their own callbacks.
+    #[payable]
+    pub fn withdraw_split(
+        &mut self,
+        assets: U128,
+        receivers: Vec<(AccountId, U128)>,
+        memo: Option<String>,
+    ) -> U128 {
+        assert_one_yocto();
+        self.assert_not_paused();
+        assert_memo_len(memo.as_deref());
+        let owner = env::predecessor_account_id();
+
+        assert!(
+            !receivers.is_empty() && receivers.len() <= MAX_SPLIT_RECEIVERS,
+            "Invalid number of receivers"
+        );
+        assert!(
+            receivers.iter().all(|(_, weight)| weight.0 > 0),
+            "Zero weight receiver"
+        );
+        assert!(
+            receivers
+                .iter()
+                .all(|(receiver_id, _)| *receiver_id != self.asset),
+            "Cannot withdraw to the asset contract"
+        );
+        let total_weight = receivers
+            .iter()
+            .try_fold(0u128, |sum, (_, weight)| sum.checked_add(weight.0))
+            .expect("Weights overflow");
+        assert_eq!(total_weight, assets.0, "Weights must sum to assets");
+        assert!(
+            assets.0 <= self.max_withdraw(owner.clone()).0,
+            "Exceeds max withdraw"
+        );
+        assert!(
+            assets.0 <= self.internal_available_assets(),
+            "Insufficient idle assets"
+        );
+        self.assert_not_locked(&self.internal_account_state(&owner));
+        self.internal_record_outflow(assets.0);
+
+        let shares = self.internal_convert_to_shares(assets.0, Rounding::Up);
+
+        // The fee comes on top of the shares burned for `assets`
+        let fee_bps = self.internal_exit_fee_bps(&owner);
+        let fee_shares = mul_div(shares, fee_bps, 10_000 - fee_bps, Rounding::Up);
+        assert!(
+            self.token.ft_balance_of(owner.clone()).0 >= shares + fee_shares,
+            "Insufficient shares"
+        );
+        self.internal_pay_exit_fee(&owner, fee_shares);
+
+        // Shares are split by weight, the last leg takes the rounding remainder
+        let legs = receivers.len();
+        let mut shares_left = shares;
+        for (index, (receiver_id, weight)) in receivers.into_iter().enumerate() {
+            let leg_shares = if index + 1 == legs {
+                shares_left
+            } else {
+                mul_div(shares, weight.0, assets.0, Rounding::Down)
+            };
+            shares_left -= leg_shares;
+            self.internal_withdraw_split_leg(
+                &owner,
+                receiver_id,
+                weight.0,
+                leg_shares,
+                memo.clone(),
+            );
+        }
+
+        U128(shares)
+    }
+
+    /// Reads the decimals off the asset's metadata and stores them, logging a
+    /// `vault_decimals_mismatch` event when the share decimals aren't the asset's plus
+    /// `extra_decimals`.
+    #[payable]
+    pub fn sync_asset_decimals(&mut self) -> Promise {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("sync_asset_decimals", json!({}));
+
+        ext_ft_metadata::ext(self.asset.clone())
+            .with_static_gas(GAS_FOR_FT_METADATA)
+            .ft_metadata()
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(Gas::from_tgas(5))
+                    .resolve_asset_decimals(),
+            )
+    }
+
+    #[private]
+    pub fn resolve_asset_decimals(
+        &mut self,
+        #[callback_result] metadata: Result<FungibleTokenMetadata, PromiseError>,
+    ) -> u8 {
+        let asset_decimals = metadata
+            .expect("Failed to read the asset metadata")
+            .decimals;
+        self.asset_decimals = Some(asset_decimals);
+
+        if self.metadata.decimals.checked_sub(asset_decimals) != Some(self.extra_decimals) {
+            VaultDecimalsMismatch {
+                share_decimals: self.metadata.decimals,
+                asset_decimals,
+            }
+            .emit();
+        }
+
+        asset_decimals
+    }
+
+    /// Repoints the vault to `new_asset`, for when the asset contract got redeployed. Only an
+    /// empty vault can migrate, nothing may be held, deployed, queued or in flight.
+    #[payable]
+    pub fn migrate_asset(&mut self, new_asset: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("migrate_asset", json!({ "new_asset": new_asset }));
+        assert!(
+            new_asset != self.asset && self.internal_basket_index(&new_asset).is_none(),
+            "Token already held"
+        );
+        assert!(
+            self.total_assets == 0
+                && self.deployed_assets == 0
+                && self.queued_assets == 0
+                && self.withdrawals_in_flight == 0,
+            "Vault still holds assets"
+        );
+
+        let old_asset = std::mem::replace(&mut self.asset, new_asset);
+        // The new asset's decimals have to be synced again
+        self.asset_decimals = None;
+
+        VaultAssetMigrated {
+            old_asset: &old_asset,
+            new_asset: &self.asset,
+        }
+        .emit();
+    }
+
+    /// Decimals of the asset, unset until given to `new` or read by `sync_asset_decimals`.
+    pub fn asset_decimals(&self) -> Option<u8> {
+        self.asset_decimals
+    }
+
+    /// Decimals of the shares, the asset's plus the `extra_decimals` conversions scale by.
+    pub fn share_decimals(&self) -> u8 {
+        self.metadata.decimals
+    }
+
+    /// Caps the assets a single transfer can deposit, the excess is refunded.
+    #[payable]
+    pub fn set_max_deposit_per_tx(&mut self, max_deposit_per_tx: Option<U128>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action(
+            "set_max_deposit_per_tx",
+            json!({ "max_deposit_per_tx": max_deposit_per_tx }),
+        );
+        self.max_deposit_per_tx = max_deposit_per_tx.map(|max| max.0);
+    }
+
+    pub fn max_deposit_per_tx(&self) -> Option<U128> {
+        self.max_deposit_per_tx.map(U128)
+    }
+
+    /// Caps the vault's total value, the part of a deposit that would take it past is
+    /// refunded.
+    #[payable]
+    pub fn set_total_assets_cap(&mut self, total_assets_cap: Option<U128>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action(
+            "set_total_assets_cap",
+            json!({ "total_assets_cap": total_assets_cap }),
+        );
+        self.total_assets_cap = total_assets_cap.map(|cap| cap.0);
+    }
+
+    pub fn total_assets_cap(&self) -> Option<U128> {
+        self.total_assets_cap.map(U128)
+    }
+
+    /// Deposits crediting a `receiver_id` other than the sender with fewer shares are refunded,
+    /// so nobody can be spammed with dust positions. Zero turns the check off.
+    #[payable]
+    pub fn set_min_shares_per_receiver(&mut self, min_shares_per_receiver: U128) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action(
+            "set_min_shares_per_receiver",
+            json!({ "min_shares_per_receiver": min_shares_per_receiver }),
+        );
+        self.min_shares_per_receiver = min_shares_per_receiver.0;
+    }
+
+    pub fn min_shares_per_receiver(&self) -> U128 {
+        U128(self.min_shares_per_receiver)
+    }
+
+    /// Deposits whose shares fall more than `max_deposit_slippage_bps` below what the plain
+    /// conversion of the whole amount gives, through the caps or the dead shares, are refunded
+    /// in full. `None` turns the check off.
+    #[payable]
+    pub fn set_max_deposit_slippage_bps(&mut self, max_deposit_slippage_bps: Option<u16>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action(
+            "set_max_deposit_slippage_bps",
+            json!({ "max_deposit_slippage_bps": max_deposit_slippage_bps }),
+        );
+        assert!(
+            max_deposit_slippage_bps.is_none_or(|bps| bps <= 10_000),
+            "Invalid slippage"
+        );
+        self.max_deposit_slippage_bps = max_deposit_slippage_bps;
+    }
+
+    pub fn max_deposit_slippage_bps(&self) -> Option<u16> {
+        self.max_deposit_slippage_bps
+    }
+
+    /// Limits the assets withdrawals take out of the vault to `max_withdraw_per_window` over any
+    /// `window_ns`, sliding: the previous window's withdrawals count for the part of it the
+    /// sliding window still overlaps.
+    #[payable]
+    pub fn set_withdraw_limit(&mut self, max_withdraw_per_window: Option<U128>, window_ns: U64) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action(
+            "set_withdraw_limit",
+            json!({
+                "max_withdraw_per_window": max_withdraw_per_window,
+                "window_ns": window_ns,
+            }),
+        );
+        // An empty window counts nothing, the limit would only cap each withdrawal on its own
+        assert!(
+            max_withdraw_per_window.is_none() || window_ns.0 > 0,
+            "Window must not be empty"
+        );
+        self.max_withdraw_per_window = max_withdraw_per_window.map(|max| max.0);
+        self.withdraw_window_ns = window_ns.0;
+    }
+
+    /// Assets that can still be withdrawn in the current window, unset without a limit.
+    pub fn withdrawable_in_window(&self) -> Option<U128> {
+        self.max_withdraw_per_window
+            .map(|max| U128(max.saturating_sub(self.internal_withdrawn_in_window())))
+    }
+
+    /// Whether deposits too small to mint a share are refunded, the default. When unset the
+    /// vault keeps such dust for its holders, as it used to.
+    #[payable]
+    pub fn set_reject_zero_share_deposits(&mut self, reject_zero_share_deposits: bool) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action(
+            "set_reject_zero_share_deposits",
+            json!({ "reject_zero_share_deposits": reject_zero_share_deposits }),
+        );
+        self.reject_zero_share_deposits = reject_zero_share_deposits;
+    }
+
+    pub fn reject_zero_share_deposits(&self) -> bool {
+        self.reject_zero_share_deposits
+    }
+
+    /// Sets the price per share a reported loss or repricing can't drop below, so the vault
+    /// halts on an implausible loss instead of socializing it.
+    #[payable]
+    pub fn set_min_price_per_share(&mut self, min_price_per_share: Option<U128>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action(
+            "set_min_price_per_share",
+            json!({ "min_price_per_share": min_price_per_share }),
+        );
+        self.min_price_per_share = min_price_per_share.map(|min| min.0);
+    }
+
+    pub fn min_price_per_share(&self) -> Option<U128> {
+        self.min_price_per_share.map(U128)
+    }
+
+    /// Earmarks `amount` of the available assets for a pending withdrawal. Reserved assets still
+    /// back the share price but can't be withdrawn until released.
+    #[payable]
+    pub fn reserve_assets(&mut self, amount: U128) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("reserve_assets", json!({ "amount": amount }));
+        self.internal_reserve_assets(amount.0);
+    }
+
+    /// Releases `amount` of previously reserved assets.
+    #[payable]
+    pub fn release_reserved_assets(&mut self, amount: U128) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("release_reserved_assets", json!({ "amount": amount }));
+        self.internal_release_assets(amount.0);
+    }
+
+    /// Account the vault's assets are invested into, if any.
+    pub fn strategy(&self) -> Option<AccountId> {
+        self.strategy.clone()
+    }
+
+    /// Assets currently invested into the strategy.
+    pub fn deployed_assets(&self) -> U128 {
+        U128(self.deployed_assets)
+    }
+
+    /// Assets held by the vault itself, including those owed to queued withdrawals.
+    pub fn idle_assets(&self) -> U128 {
+        U128(self.internal_idle_assets())
+    }
+
+    /// Runs the vault's consistency checks for monitoring, resolving to their report. The
+    /// vault's asset balance is queried so the idle assets it tracks are checked against what
+    /// it actually holds.
+    pub fn check_invariants(&self) -> Promise {
+        self.query_asset_balance().then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_ON_CHECK_INVARIANTS)
+                .on_check_invariants(U128(self.internal_idle_assets())),
+        )
+    }
+
+    #[private]
+    pub fn on_check_invariants(
+        &self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        idle: U128,
+    ) -> InvariantReport {
+        let balance = balance.expect("Failed to query the asset balance");
+        self.internal_invariant_report(balance.0, idle.0)
+    }
+
+    /// Resolves to whether every check of `check_invariants` holds, for monitoring bots. A
+    /// strategy loss lowers the share price, it only turns false once outstanding shares are
+    /// left without any value or the vault holds less than it tracks.
+    pub fn is_healthy(&self) -> Promise {
+        self.query_asset_balance().then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_ON_CHECK_INVARIANTS)
+                .on_is_healthy(U128(self.internal_idle_assets())),
+        )
+    }
+
+    #[private]
+    pub fn on_is_healthy(
+        &self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        idle: U128,
+    ) -> bool {
+        let balance = balance.expect("Failed to query the asset balance");
+        self.internal_invariant_report(balance.0, idle.0).all_hold
+    }
+
+    /// Shares a deposit of `assets` mints and the part of it that is used, exactly as
+    /// `ft_on_transfer` would credit it right now. A deposit the TVL and per-transaction caps
+    /// only partly accept is quoted as the partial fill, the rest as refund.
+    pub fn quote_deposit(&self, assets: U128) -> DepositQuote {
+        // Refunded in full while paused or cut down by the caps beyond the slippage bound
+        if self.paused || self.internal_exceeds_deposit_slippage(assets.0) {
+            return DepositQuote {
+                shares: U128(0),
+                fee_assets: U128(0),
+                used_assets: U128(0),
+                dead_shares: U128(0),
+                refund: assets,
+            };
+        }
+        self.internal_quote_deposit(assets.0, None)
+    }
+
+    /// `convert_to_assets` of `shares`, capped at what `account_id` can redeem rather than
+    /// reverting like `preview_redeem`.
+    pub fn clamped_convert_to_assets(
+        &self,
+        shares: U128,
+        account_id: AccountId,
+    ) -> ClampedConversion {
+        let max_shares = self.max_redeem(account_id);
+        ClampedConversion {
+            value: self.convert_to_assets(U128(shares.0.min(max_shares.0))),
+            clamped: shares > max_shares,
+        }
+    }
+
+    /// `convert_to_shares` of `assets`, capped at what `account_id` can withdraw rather than
+    /// reverting like `preview_withdraw`.
+    pub fn clamped_convert_to_shares(
+        &self,
+        assets: U128,
+        account_id: AccountId,
+    ) -> ClampedConversion {
+        let max_assets = self.max_withdraw(account_id);
+        ClampedConversion {
+            value: self.convert_to_shares(U128(assets.0.min(max_assets.0))),
+            clamped: assets > max_assets,
+        }
+    }
+
+    /// Asset value of one whole share, rounded to the nearest unit.
+    pub fn price_per_share(&self) -> U128 {
+        U128(self.internal_price_per_share(Rounding::Nearest))
+    }
+
+    /// Assets per share as `(numerator, denominator)`, unrounded: `convert_to_assets` is
+    /// `shares * numerator / denominator` rounded down and `convert_to_shares` the inverse.
+    pub fn exchange_rate(&self) -> (U128, U128) {
+        let (numerator, denominator) = self.internal_conversion_snapshot().exchange_rate();
+        (U128(numerator), U128(denominator))
+    }
+
+    /// Asset value of `10^scale_decimals` share units, for quoting at a fixed precision.
+    pub fn price_per_share_scaled(&self, scale_decimals: u8) -> U128 {
+        assert!(scale_decimals <= 38, "Scale too large");
+        U128(self.internal_convert_to_assets(pow10(scale_decimals), Rounding::Nearest))
+    }
+
+    /// Time-weighted average price per share over the last `window_ns`, from the observations
+    /// still in the buffer.
+    pub fn twap(&self, window_ns: U64) -> U128 {
+        U128(self.internal_twap(window_ns.0))
+    }
+
+    /// Annualized return in basis points implied by the price per share change over the last
+    /// `window_ns`. Negative after a loss, zero with fewer than two observations.
+    pub fn estimated_apy_bps(&self, window_ns: U64) -> i64 {
+        self.internal_estimated_apy_bps(window_ns.0)
+    }
+
+    /// Price per share up to which the performance fee has been charged.
+    pub fn high_water_mark(&self) -> U128 {
+        U128(self.high_water_mark)
+    }
+
+    /// Sets the performance fee and the account receiving it. Gains made before the fee is
+    /// enabled aren't charged.
+    #[payable]
+    pub fn set_performance_fee(&mut self, performance_fee_bps: u16, fee_recipient: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action(
+            "set_performance_fee",
+            json!({ "performance_fee_bps": performance_fee_bps, "fee_recipient": fee_recipient }),
+        );
+        assert!(performance_fee_bps <= 10_000, "Invalid fee");
+        assert!(
+            self.token.accounts.contains_key(&fee_recipient),
+            "Fee recipient not registered"
+        );
+
+        self.performance_fee_bps = performance_fee_bps;
+        self.fee_recipient = Some(fee_recipient);
+        self.high_water_mark = self
+            .high_water_mark
+            .max(self.internal_price_per_share(Rounding::Down));
+    }
+
+    /// Sets the exit fee charged in shares to the fee recipient on redeems and withdrawals. It
+    /// starts at `max_early_exit_bps` when depositing and decays linearly to zero over
+    /// `exit_fee_decay_ns`.
+    #[payable]
+    pub fn set_exit_fee(&mut self, max_early_exit_bps: u16, exit_fee_decay_ns: U64) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action(
+            "set_exit_fee",
+            json!({
+                "max_early_exit_bps": max_early_exit_bps,
+                "exit_fee_decay_ns": exit_fee_decay_ns,
+            }),
+        );
+        assert!(max_early_exit_bps < 10_000, "Invalid fee");
+        assert!(
+            max_early_exit_bps == 0 || self.fee_recipient.is_some(),
+            "Fee recipient not set"
+        );
+
+        self.max_early_exit_bps = max_early_exit_bps;
+        self.exit_fee_decay_ns = exit_fee_decay_ns.0;
+    }
+
+    /// Exit fee currently charged on `account_id`'s shares, in basis points.
+    pub fn exit_fee_bps_of(&self, account_id: AccountId) -> u16 {
+        self.internal_exit_fee_bps(&account_id) as u16
+    }
+
+    /// Exempts `account_id` from the exit fee, like the treasury or a partner.
+    #[payable]
+    pub fn add_fee_exempt(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("add_fee_exempt", json!({ "account_id": account_id }));
+        self.fee_exempt.insert(&account_id);
+    }
+
+    /// Charges `account_id` the exit fee again.
+    #[payable]
+    pub fn remove_fee_exempt(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("remove_fee_exempt", json!({ "account_id": account_id }));
+        self.fee_exempt.remove(&account_id);
+    }
+
+    pub fn is_fee_exempt(&self, account_id: AccountId) -> bool {
+        self.fee_exempt.contains(&account_id)
+    }
+
+    /// Adds a token to the basket, valued at `price` units of the asset per
+    /// `BASKET_PRICE_SCALE` units. The vault must be registered with the token.
+    #[payable]
+    pub fn add_basket_asset(&mut self, token_id: AccountId, price: U128) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action(
+            "add_basket_asset",
+            json!({ "token_id": token_id, "price": price }),
+        );
+        assert!(self.basket.len() < MAX_BASKET_ASSETS, "Basket is full");
+        assert!(
+            token_id != self.asset && self.internal_basket_index(&token_id).is_none(),
+            "Token already held"
+        );
+        assert!(price.0 > 0, "Invalid price");
+
+        self.basket.push(BasketAsset {
+            token_id,
+            balance: U128(0),
+            price,
+        });
+    }
+
+    /// Updates the price a basket token is valued at.
+    #[payable]
+    pub fn set_basket_price(&mut self, token_id: AccountId, price: U128) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action(
+            "set_basket_price",
+            json!({ "token_id": token_id, "price": price }),
+        );
+        assert!(price.0 > 0, "Invalid price");
+        let index = self
+            .internal_basket_index(&token_id)
+            .expect("Token not in basket");
+
+        self.basket[index].price = price;
+        self.internal_assert_price_floor();
+        self.internal_record_price();
+    }
+
+    /// Tokens held next to the asset, with their balances and prices.
+    pub fn basket(&self) -> Vec<BasketAsset> {
+        self.basket.clone()
+    }
+
+    /// `redeem` or `withdraw` depending on `kind`, with everything else, slippage checks from
+    /// `msg` and rollback included, left to them.
+    #[payable]
+    pub fn exit(
+        &mut self,
+        amount: U128,
+        kind: ExitKind,
+        receiver_id: Option<AccountId>,
+        memo: Option<String>,
+        msg: Option<String>,
+    ) -> PromiseOrValue<WithdrawOutcome> {
+        match kind {
+            ExitKind::Shares => self.redeem(amount, receiver_id, memo, None, None, msg, None),
+            ExitKind::Assets => self.withdraw(amount, receiver_id, memo, msg, None),
+        }
+    }
+
+    /// Burns `shares` of the caller for their pro rata part of the asset and of each basket
+    /// token, worth what a plain redeem of the shares would pay. Every token is sent and rolled
+    /// back on its own, the outcome covers the asset. Liquidating the strategy for the asset
+    /// leg tolerates `max_loss_bps` like `redeem`.
+    #[payable]
+    pub fn redeem_in_kind(
+        &mut self,
+        shares: U128,
+        receiver_id: Option<AccountId>,
+        max_loss_bps: Option<u16>,
+    ) -> PromiseOrValue<WithdrawOutcome> {
+        assert_one_yocto();
+        self.assert_not_paused();
+        let owner = env::predecessor_account_id();
+        self.assert_not_locked(&self.internal_account_state(&owner));
+        assert!(
+            shares.0 <= self.max_redeem(owner.clone()).0,
+            "Exceeds max redeem"
+        );
+        let shares = shares.0 - self.internal_take_exit_fee(&owner, shares.0);
+        assert!(shares > 0, "No shares to redeem");
+        let receiver_id = receiver_id.unwrap_or(owner.clone());
+
+        // Each token pays out its part of what the shares convert to
+        let value = self.internal_convert_to_assets(shares, Rounding::Down);
+        assert!(value > 0, "No assets to withdraw");
+        let total_value = self.internal_total_value();
+
+        // Shares are split by the value each token pays out, so a failed transfer only
+        // restores its own part
+        let mut basket_payouts = Vec::new();
+        let mut basket_shares = 0;
+        for (index, basket_asset) in self.basket.iter().enumerate() {
+            let amount = mul_div(basket_asset.balance.0, value, total_value, Rounding::Down);
+            let leg_value = self.internal_basket_value(index, amount);
+            let payout_shares = mul_div(shares, leg_value, value, Rounding::Down);
+            if amount > 0 && payout_shares > 0 {
+                basket_payouts.push((index, amount, leg_value, payout_shares));
+                basket_shares += payout_shares;
+            }
+        }
+        let asset_shares = shares - basket_shares;
+        let assets = mul_div(self.total_assets, value, total_value, Rounding::Down);
+
+        let outcome = if assets > 0 && asset_shares > 0 {
+            PromiseOrValue::Promise(self.internal_execute_withdrawal(
+                owner.clone(),
+                Some(receiver_id.clone()),
+                asset_shares,
+                assets,
+                None,
+                false,
+                max_loss_bps,
+                None,
+            ))
+        } else {
+            PromiseOrValue::Value(WithdrawOutcome {
+                assets: U128(0),
+                shares: U128(0),
+                rolled_back: false,
+            })
+        };
+
+        for (index, amount, leg_value, payout_shares) in basket_payouts {
+            self.internal_withdraw_basket_asset(
+                &owner,
+                &receiver_id,
+                index,
+                amount,
+                leg_value,
+                payout_shares,
+            );
+        }
+
+        outcome
+    }
+
+    #[private]
+    pub fn resolve_basket_withdrawal(
+        &mut self,
+        owner: AccountId,
+        token_id: AccountId,
+        amount: U128,
+        value: U128,
+        shares: U128,
+        deposited: U128,
+    ) -> bool {
+        let succeeded = matches!(
+            env::promise_result(0),
+            near_sdk::PromiseResult::Successful(_)
+        );
+        let mut owner_state = self.internal_account_state(&owner);
+        owner_state.pending_basket_legs -= 1;
+        if !succeeded {
+            owner_state.restore_deposited(deposited.0);
+            self.internal_release_outflow(value.0);
+            self.internal_credit_holding(&token_id, amount.0);
+            self.internal_restore_shares(&owner, shares.0);
+            self.internal_record_price();
+
+            FtMint {
+                owner_id: &owner,
+                amount: shares,
+                memo: Some("Withdrawal rollback"),
+            }
+            .emit();
+        }
+        self.accounts.insert(&owner, &owner_state);
+        self.internal_prune_account_state(&owner);
+
+        succeeded
+    }
+
+    #[private]
+    pub fn resolve_split_withdrawal(
+        &mut self,
+        owner: AccountId,
+        receiver: AccountId,
+        assets: U128,
+        shares: U128,
+        deposited: U128,
+        memo: Option<String>,
+    ) -> WithdrawOutcome {
+        self.withdrawals_in_flight = self
+            .withdrawals_in_flight
+            .checked_sub(assets.0)
+            .expect("withdrawals_in_flight underflow");
+
+        let succeeded = matches!(
+            env::promise_result(0),
+            near_sdk::PromiseResult::Successful(_)
+        );
+        let outcome = if succeeded {
+            let mut owner_state = self.internal_account_state(&owner);
+            owner_state.realize_pnl(assets.0, deposited.0);
+            self.accounts.insert(&owner, &owner_state);
+
+            if self.emit_vault_events {
+                VaultWithdraw {
+                    owner_id: &owner,
+                    receiver_id: &receiver,
+                    assets,
+                    shares,
+                    memo: memo.as_deref(),
+                }
+                .emit();
+            }
+
+            WithdrawOutcome {
+                assets,
+                shares,
+                rolled_back: false,
+            }
+        } else {
+            self.internal_restore_shares(&owner, shares.0);
+            self.internal_increase_total_assets(assets.0);
+            self.internal_release_outflow(assets.0);
+            let mut owner_state = self.internal_account_state(&owner);
+            owner_state.restore_deposited(deposited.0);
+            self.accounts.insert(&owner, &owner_state);
+            self.internal_record_price();
+
+            FtMint {
+                owner_id: &owner,
+                amount: shares,
+                memo: Some("Withdrawal rollback"),
+            }
+            .emit();
+
+            WithdrawOutcome {
+                assets: U128(0),
+                shares: U128(0),
+                rolled_back: true,
+            }
+        };
+
+        self.internal_notify_withdraw_hook(&owner, &receiver, shares, assets, succeeded);
+        self.internal_prune_account_state(&owner);
+        outcome
+    }
+
+    /// Deposits the assets the old vault paid out for `shares` migrated by `sender_id`,
+    /// returning the old shares to refund: all of them when the redemption failed. Never
+    /// panics, assets that can't be deposited are sent back to `sender_id`.
+    #[private]
+    pub fn resolve_migration(&mut self, sender_id: AccountId, shares: U128) -> U128 {
+        self.pending_migrations -= 1;
+        // Read by hand, an outcome that fails to parse must not panic the callback. Vaults
+        // predating `WithdrawOutcome` resolve `redeem` to the bare assets, zero when rolled back
+        let assets = match env::promise_result(0) {
+            near_sdk::PromiseResult::Successful(value) => {
+                match serde_json::from_slice::<WithdrawOutcome>(&value) {
+                    Ok(outcome) => Some(outcome.assets.0),
+                    Err(_) => serde_json::from_slice::<U128>(&value)
+                        .ok()
+                        .map(|assets| assets.0),
+                }
+            }
+            _ => {
+                env::log_str("Migration redeem failed, shares refunded");
+                return shares;
+            }
+        };
+        let assets = match assets {
+            // Rolled back, the old vault gave the shares back
+            Some(0) => return shares,
+            Some(assets) => assets,
+            None => {
+                // The shares are burned and whatever was paid out arrived untracked, for
+                // `absorb_untracked_assets` to pick up
+                env::log_str("Migration redeem outcome unreadable, the assets stay untracked");
+                return U128(0);
+            }
+        };
+
+        // Paid out with a plain transfer, a balance query meanwhile must not book it as yield
+        self.internal_record_inbound(assets);
+        let unused = if self.paused {
+            env::log_str("Vault is paused, migrated assets refunded");
+            assets
+        } else {
+            self.internal_handle_deposit(
+                sender_id.clone(),
+                assets,
+                DepositMessage {
+                    memo: Some("Migration".to_string()),
+                    ..Default::default()
+                },
+            )
+        };
+        if unused > 0 {
+            // Still on the vault's balance until the refund lands
+            self.pending_migrations += 1;
+            ext_ft_core::ext(self.asset.clone())
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .ft_transfer(
+                    sender_id,
+                    U128(unused),
+                    Some("Migration refund".to_string()),
+                )
+                .then(
+                    ext_self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_RESOLVE_MIGRATION_REFUND)
+                        .resolve_migration_refund(),
+                );
+        }
+
+        U128(0)
+    }
+
+    /// A refund that failed leaves the assets on the vault untracked, for
+    /// `absorb_untracked_assets` to pick up.
+    #[private]
+    pub fn resolve_migration_refund(&mut self) {
+        self.pending_migrations -= 1;
+        if !matches!(
+            env::promise_result(0),
+            near_sdk::PromiseResult::Successful(_)
+        ) {
+            env::log_str("Migration refund failed, the assets stay untracked");
+        }
+    }
+
+    /// Second half of `redeem` with `check_receiver_storage` set: fails before any share is
+    /// burned when the receiver isn't registered with the asset. Should the asset not answer,
+    /// the redeem goes ahead and a failed transfer is rolled back as usual.
+    #[private]
+    pub fn redeem_to_registered_receiver(
+        &mut self,
+        #[callback_result] storage_balance: Result<Option<StorageBalance>, PromiseError>,
+        request: RedeemRequest,
+    ) -> Promise {
+        assert!(
+            !matches!(storage_balance, Ok(None)),
+            "Receiver not registered with the asset"
+        );
+        self.assert_not_paused();
+        self.internal_redeem(request)
+    }
+
+    /// Second half of `register_with_asset`: pays for the vault's storage on the asset unless
+    /// it's already registered, in which case the deposit goes back to `owner`. Resolves to the
+    /// vault's storage balance on the asset, unset when the registration failed.
+    #[private]
+    pub fn resolve_register_with_asset(
+        &mut self,
+        #[callback_result] storage_balance: Result<Option<StorageBalance>, PromiseError>,
+        owner: AccountId,
+        deposit: U128,
+    ) -> PromiseOrValue<Option<StorageBalance>> {
+        if let Ok(Some(storage_balance)) = storage_balance {
+            Promise::new(owner).transfer(NearToken::from_yoctonear(deposit.0));
+            return PromiseOrValue::Value(Some(storage_balance));
+        }
+
+        PromiseOrValue::Promise(
+            ext_storage_management::ext(self.asset.clone())
+                .with_attached_deposit(NearToken::from_yoctonear(deposit.0))
+                .with_static_gas(GAS_FOR_STORAGE_DEPOSIT)
+                .storage_deposit(Some(env::current_account_id()), Some(true))
+                .then(
+                    ext_self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_RESOLVE_ASSET_STORAGE_DEPOSIT)
+                        .resolve_asset_storage_deposit(owner, deposit),
+                ),
+        )
+    }
+
+    /// The asset refunds a failed `storage_deposit`, and the surplus above its minimum, to the
+    /// vault. Both are passed on to `owner`, who paid for the registration.
+    #[private]
+    pub fn resolve_asset_storage_deposit(
+        &mut self,
+        #[callback_result] storage_balance: Result<StorageBalance, PromiseError>,
+        owner: AccountId,
+        deposit: U128,
+    ) -> Option<StorageBalance> {
+        let (refund, storage_balance) = match storage_balance {
+            Ok(storage_balance) => (
+                deposit
+                    .0
+                    .saturating_sub(storage_balance.total.as_yoctonear()),
+                Some(storage_balance),
+            ),
+            Err(_) => {
+                env::log_str("Registration with the asset failed, deposit returned");
+                (deposit.0, None)
+            }
+        };
+        if refund > 0 {
+            Promise::new(owner).transfer(NearToken::from_yoctonear(refund));
+        }
+        storage_balance
+    }
+
+    /// Books the strategy's current value of the deployed assets, moving `total_assets` by the
+    /// gain or loss and charging the performance fee on gains.
+    #[payable]
+    pub fn report_yield(&mut self, strategy_balance: U128) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action(
+            "report_yield",
+            json!({ "strategy_balance": strategy_balance }),
+        );
+        self.internal_report_yield(strategy_balance.0);
+    }
+
+    /// Like `report_yield`, with the value read from the strategy's `balance`. Nothing may be in
+    /// flight, a transfer that hasn't landed yet would be booked as a loss.
+    #[payable]
+    pub fn harvest(&mut self) -> Promise {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("harvest", json!({}));
+        let strategy_id = self.strategy.clone().expect("No strategy set");
+        assert_eq!(
+            self.withdrawals_in_flight, 0,
+            "Transfers in flight, try again"
+        );
+
+        ext_strategy::ext(strategy_id)
+            .with_static_gas(GAS_FOR_STRATEGY_BALANCE)
+            .balance()
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(Gas::from_tgas(20))
+                    .resolve_harvest(U128(self.deployed_assets)),
+            )
+    }
+
+    #[private]
+    pub fn resolve_harvest(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        deployed: U128,
+    ) -> U128 {
+        let balance = balance.expect("Failed to read the strategy balance");
+        // An invest or liquidation went through meanwhile, the balance no longer matches
+        assert_eq!(
+            self.deployed_assets, deployed.0,
+            "Deployed assets changed, try again"
+        );
+
+        self.internal_report_yield(balance.0);
+        U128(self.internal_total_value())
+    }
+
+    /// Claims the strategy's rewards into the vault, raising `total_assets` without minting
+    /// shares, then with `reinvest` puts all available assets back to work in the strategy.
+    #[payable]
+    pub fn compound(&mut self, reinvest: bool) -> Promise {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("compound", json!({ "reinvest": reinvest }));
+        let strategy_id = self.strategy.clone().expect("No strategy set");
+
+        ext_strategy::ext(strategy_id)
+            .with_static_gas(GAS_FOR_STRATEGY_CLAIM)
+            .claim_rewards()
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_COMPOUND_FROM_STRATEGY)
+                    .compound_from_strategy(reinvest),
+            )
+    }
+
+    /// Books the rewards claimed by `compound`, returning them.
+    #[private]
+    pub fn compound_from_strategy(
+        &mut self,
+        #[callback_result] claimed: Result<U128, PromiseError>,
+        reinvest: bool,
+    ) -> U128 {
+        let claimed = claimed.map_or(0, |claimed| claimed.0);
+        if claimed > 0 {
+            self.internal_increase_total_assets(claimed);
+            self.internal_accrue_performance_fee();
+            self.internal_record_price();
+        }
+
+        let idle = self.internal_available_assets();
+        if reinvest && idle > 0 {
+            self.internal_invest(idle);
+        }
+
+        U128(claimed)
+    }
+
+    /// Pulls up to `amount` of the deployed assets back from the strategy into idle liquidity.
+    #[payable]
+    pub fn divest(&mut self, amount: U128) -> Promise {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("divest", json!({ "amount": amount }));
+        let strategy_id = self.strategy.clone().expect("No strategy set");
+        assert!(amount.0 > 0, "No assets to divest");
+        assert!(amount.0 <= self.deployed_assets, "Exceeds deployed assets");
+
+        ext_strategy::ext(strategy_id)
+            .with_static_gas(GAS_FOR_STRATEGY_WITHDRAW)
+            .withdraw(amount)
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(Gas::from_tgas(10))
+                    .resolve_divest(amount),
+            )
+    }
+
+    #[private]
+    pub fn resolve_divest(
+        &mut self,
+        #[callback_result] returned: Result<U128, PromiseError>,
+        amount: U128,
+    ) -> U128 {
+        // What the strategy didn't send back stays deployed
+        let returned = returned.map_or(0, |returned| {
+            returned.0.min(amount.0).min(self.deployed_assets)
+        });
+        self.deployed_assets -= returned;
+        U128(returned)
+    }
+
+    /// Assets still owed to `account_id` by its queued withdrawals.
+    pub fn claimable_withdrawal(&self, account_id: AccountId) -> U128 {
+        self.internal_account_state(&account_id)
+            .queued_withdrawal
+            .unwrap_or(U128(0))
+    }
+
+    /// Sets the account idle assets get invested into. Can only change while nothing is deployed.
+    #[payable]
+    pub fn set_strategy(&mut self, strategy_id: Option<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("set_strategy", json!({ "strategy_id": strategy_id }));
+        assert_eq!(self.deployed_assets, 0, "Assets still deployed");
+        self.strategy = strategy_id;
+    }
+
+    /// Transfers `amount` of the available assets to the strategy. The strategy returns them
+    /// by sending the asset back with `ft_transfer_call`.
+    #[payable]
+    pub fn invest(&mut self, amount: U128) -> Promise {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("invest", json!({ "amount": amount }));
+        self.internal_invest(amount.0)
+    }
+
+    /// Sends the NEAR the vault holds above its storage stake and a buffer to `receiver`,
+    /// returning the amount sent. Calls only ever leave their one yocto behind, and
+    /// `ft_transfer` refunds what it doesn't need for registration, so this reclaims NEAR sent
+    /// to the vault by mistake. Not while unwrapped NEAR may be in flight to a receiver.
+    #[payable]
+    pub fn withdraw_stranded_near(&mut self, receiver: AccountId) -> U128 {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("withdraw_stranded_near", json!({ "receiver": receiver }));
+        assert_eq!(
+            self.withdrawals_in_flight, 0,
+            "Transfers in flight, try again"
+        );
+
+        let reserve = env::storage_byte_cost()
+            .saturating_mul(env::storage_usage() as u128)
+            .saturating_add(STRANDED_NEAR_BUFFER);
+        let stranded = env::account_balance().saturating_sub(reserve);
+        if !stranded.is_zero() {
+            Promise::new(receiver).transfer(stranded);
+        }
+        U128(stranded.as_yoctonear())
+    }
+
+    /// Queries the vault's actual balance on the asset contract, which may differ from
+    /// `total_assets` by idle, deployed and in-flight amounts or untracked transfers.
+    pub fn asset_balance(&self) -> Promise {
+        self.query_asset_balance().then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(Gas::from_tgas(5))
+                .on_asset_balance(),
+        )
+    }
+
+    #[private]
+    pub fn on_asset_balance(&self, #[callback_result] balance: Result<U128, PromiseError>) -> U128 {
+        balance.expect("Failed to query the asset balance")
+    }
+
+    /// Sets the oracle `total_assets_usd` prices the asset with, its prices scaled by
+    /// `decimals`.
+    #[payable]
+    pub fn set_price_oracle(&mut self, price_oracle: Option<AccountId>, decimals: u8) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action(
+            "set_price_oracle",
+            json!({ "price_oracle": price_oracle, "decimals": decimals }),
+        );
+        self.price_oracle = price_oracle;
+        self.price_oracle_decimals = decimals;
+    }
+
+    pub fn price_oracle(&self) -> Option<AccountId> {
+        self.price_oracle.clone()
+    }
+
+    /// Queries the oracle for the asset's price, resolving to `total_assets` valued in the
+    /// oracle's reference asset, in its smallest units.
+    pub fn total_assets_usd(&self) -> Promise {
+        let price_oracle = self.price_oracle.clone().expect("No price oracle set");
+
+        ext_price_oracle::ext(price_oracle)
+            .with_static_gas(GAS_FOR_ORACLE_PRICE)
+            .get_price(self.asset.clone())
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(Gas::from_tgas(5))
+                    .on_total_assets_usd(
+                        U128(self.internal_total_value()),
+                        self.price_oracle_decimals,
+                    ),
+            )
+    }
+
+    #[private]
+    pub fn on_total_assets_usd(
+        &self,
+        #[callback_result] price: Result<U128, PromiseError>,
+        total_assets: U128,
+        decimals: u8,
+    ) -> U128 {
+        let price = price.expect("Failed to query the asset price");
+        U128(mul_div(
+            total_assets.0,
+            price.0,
+            pow10(decimals),
+            Rounding::Down,
+        ))
+    }
 
-const GAS_FOR_FT_TRANSFER: Gas = Gas::from_tgas(30);
+    pub fn total_assets_mode(&self) -> TotalAssetsMode {
+        self.total_assets_mode
+    }
 
-#[derive(Deserialize)]
-#[serde(crate = "near_sdk::serde")]
-pub struct DepositMessage {
-    min_shares: Option<U128>,
-    max_shares: Option<U128>,
-    receiver_id: Option<AccountId>,
-    memo: Option<String>,
-    donate: Option<bool>,
-}
+    /// Balance-based vaults only: books whatever the vault holds on the asset contract above
+    /// the tracked assets as yield, resolving to the refreshed total value. Refreshes at most
+    /// once per block, later calls in the same block return the cached value.
+    #[payable]
+    pub fn refresh_total_assets(&mut self) -> PromiseOrValue<U128> {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("refresh_total_assets", json!({}));
+        assert_eq!(
+            self.total_assets_mode,
+            TotalAssetsMode::BalanceBased,
+            "Vault tracks its assets"
+        );
 
-#[near_bindgen]
-#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
-pub struct TokenizedVault {
-    pub token: FungibleToken,        // Vault shares (NEP-141)
-    metadata: FungibleTokenMetadata, // Metadata for shares
-    asset: AccountId,                // Underlying asset (NEP-141 or NEP-245)
-    total_assets: u128,              // Total managed assets
-    owner: AccountId,                // Vault owner
-    extra_decimals: u8,              // Extra decimals for shares (if any)
-}
+        if self.total_assets_refreshed_at == env::block_height() {
+            return PromiseOrValue::Value(U128(self.internal_total_value()));
+        }
 
-#[derive(BorshSerialize, BorshDeserialize, BorshStorageKey)]
-pub enum StorageKey {
-    FungibleToken,
-}
+        let (tracked, inbound) = self.internal_balance_snapshot();
+        PromiseOrValue::Promise(
+            self.query_asset_balance().then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(Gas::from_tgas(10))
+                    .resolve_refresh_total_assets(tracked, inbound),
+            ),
+        )
+    }
 
-#[near_bindgen]
-impl TokenizedVault {
-    #[init]
-    pub fn new(asset: AccountId, metadata: FungibleTokenMetadata, extra_decimals: u8) -> Self {
-        Self {
-            token: FungibleToken::new(StorageKey::FungibleToken),
-            metadata,
-            asset,
-            total_assets: 0,
-            owner: env::predecessor_account_id(),
-            extra_decimals,
+    #[private]
+    pub fn resolve_refresh_total_assets(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        tracked: U128,
+        inbound: U128,
+    ) -> U128 {
+        let balance = balance.expect("Failed to query the asset balance");
+        if self
+            .internal_absorb_untracked_assets(balance.0, tracked.0, inbound.0)
+            .is_some()
+        {
+            self.total_assets_refreshed_at = env::block_height();
         }
+
+        U128(self.internal_total_value())
+    }
+
+    /// Books assets sent to the vault with a plain `ft_transfer`, which `ft_on_transfer` never
+    /// saw, as yield for the current holders, resolving to the amount absorbed. Works in either
+    /// mode. The balance is reconciled against the assets tracked when the query was sent, and
+    /// nothing is absorbed when a deposit, a deposit refund or a migration's assets may have
+    /// moved while it was in flight.
+    #[payable]
+    pub fn absorb_untracked_assets(&mut self) -> Promise {
+        assert_one_yocto();
+        self.assert_owner();
+        self.emit_admin_action("absorb_untracked_assets", json!({}));
+
+        let (tracked, inbound) = self.internal_balance_snapshot();
+        self.query_asset_balance().then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(Gas::from_tgas(10))
+                .resolve_absorb_untracked_assets(tracked, inbound),
+        )
     }
 
     #[private]
-    pub fn resolve_withdraw(
+    pub fn resolve_absorb_untracked_assets(
         &mut self,
-        owner: AccountId,
-        receiver: AccountId,
-        shares: U128,
-        assets: U128,
-        memo: Option<String>,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        tracked: U128,
+        inbound: U128,
     ) -> U128 {
-        // Check if the transfer succeeded
-        match env::promise_result(0) {
-            near_sdk::PromiseResult::Successful(_) => {
-                // Transfer succeeded - finalize withdrawal
+        let balance = balance.expect("Failed to query the asset balance");
+        U128(
+            self.internal_absorb_untracked_assets(balance.0, tracked.0, inbound.0)
+                .unwrap_or(0),
+        )
+    }
 
-                // Emit VaultWithdraw event
-                VaultWithdraw {
-                    owner_id: &owner,
-                    receiver_id: &receiver,
-                    assets,
-                    shares,
-                    memo: memo.as_deref(),
-                }
-                .emit();
+    /// Burns `shares` of the caller and queues the withdrawal of their assets, to be paid out
+    /// by `process_withdrawals` once the vault holds enough idle liquidity.
+    #[payable]
+    pub fn request_withdraw(&mut self, shares: U128) -> U128 {
+        assert_one_yocto();
+        self.assert_not_paused();
+        let account = env::predecessor_account_id();
+        assert!(shares.0 > 0, "No shares to withdraw");
+        assert!(
+            self.token.ft_balance_of(account.clone()).0 >= shares.0,
+            "Insufficient shares"
+        );
+        self.assert_unlocked_shares(&account, shares.0);
+
+        let assets = self.internal_queue_redemption(account, shares.0, "Withdrawal requested");
+        self.internal_record_outflow(assets);
+        U128(assets)
+    }
 
-                assets
+    /// Pays out up to `max` queued withdrawals in FIFO order, stopping at the first one the idle
+    /// liquidity can't cover. Anyone may call it as payouts only go to the queued accounts.
+    pub fn process_withdrawals(&mut self, max: u32) -> u32 {
+        self.assert_not_paused();
+        let mut processed = 0;
+        while processed < max && self.withdrawal_queue_head < self.withdrawal_queue.len() {
+            let pending = self
+                .withdrawal_queue
+                .get(self.withdrawal_queue_head)
+                .expect("Missing queued withdrawal");
+            let liquidity = self
+                .internal_idle_assets()
+                .saturating_sub(self.reserved_assets);
+            if pending.assets.0 > liquidity {
+                break;
             }
-            _ => {
-                // Transfer failed - rollback state changes using callback parameters
-                // Restore shares that were burned
-                self.token.internal_deposit(&owner, shares.0);
-                // Restore total_assets that was reduced
-                self.total_assets = self
-                    .total_assets
-                    .checked_add(assets.0)
-                    .expect("total_assets overflow");
 
-                FtMint {
-                    owner_id: &owner,
-                    amount: U128(shares.0),
-                    memo: Some("Withdrawal rollback"),
-                }
-                .emit();
+            self.withdrawal_queue_head += 1;
+            // The promise is scheduled when dropped, each payout settles independently
+            let _ = self.internal_pay_queued_withdrawal(pending);
+            processed += 1;
+        }
 
-                0.into()
-            }
+        if self.withdrawal_queue_head == self.withdrawal_queue.len() {
+            self.withdrawal_queue.clear();
+            self.withdrawal_queue_head = 0;
         }
+
+        processed
     }
-}
 
-// ===== Implement FungibleTokenVaultCore Trait =====
-#[near_bindgen]
-impl VaultCore for TokenizedVault {
-    fn asset(&self) -> AccountId {
-        self.asset.clone()
+    #[private]
+    pub fn resolve_invest(&mut self, amount: U128) -> bool {
+        self.withdrawals_in_flight = self
+            .withdrawals_in_flight
+            .checked_sub(amount.0)
+            .expect("withdrawals_in_flight underflow");
+
+        let succeeded = matches!(
+            env::promise_result(0),
+            near_sdk::PromiseResult::Successful(_)
+        );
+        if succeeded {
+            // Not returned on purpose: a strategy failing to put the assets to work still holds
+            // them, they stay deployed
+            ext_strategy::ext(self.strategy.clone().unwrap())
+                .with_static_gas(GAS_FOR_STRATEGY_DEPOSIT)
+                .deposit(amount);
+        } else {
+            self.deployed_assets = self
+                .deployed_assets
+                .checked_sub(amount.0)
+                .expect("deployed_assets underflow");
+        }
+
+        succeeded
     }
 
-    fn total_assets(&self) -> U128 {
-        U128(self.total_assets)
+    #[private]
+    pub fn resolve_queued_withdrawal(&mut self, pending: PendingWithdrawal) -> bool {
+        self.withdrawals_in_flight = self
+            .withdrawals_in_flight
+            .checked_sub(pending.assets.0)
+            .expect("withdrawals_in_flight underflow");
+
+        match env::promise_result(0) {
+            near_sdk::PromiseResult::Successful(_) => {
+                if self.emit_vault_events {
+                    VaultWithdraw {
+                        owner_id: &pending.account,
+                        receiver_id: &pending.account,
+                        assets: pending.assets,
+                        shares: pending.shares,
+                        memo: Some("Queued withdrawal"),
+                    }
+                    .emit();
+                }
+                self.internal_prune_account_state(&pending.account);
+
+                true
+            }
+            _ => {
+                // Back to the end of the queue, the shares stay burned
+                self.internal_queue_withdrawal(pending);
+
+                false
+            }
+        }
     }
 
+    /// Burns the caller's shares without withdrawing assets, raising the value of every
+    /// remaining share.
     #[payable]
-    fn redeem(
-        &mut self,
-        shares: U128,
-        receiver_id: Option<AccountId>,
-        memo: Option<String>,
-    ) -> PromiseOrValue<U128> {
+    pub fn burn_shares(&mut self, shares: U128) {
         assert_one_yocto();
-
-        let owner = env::predecessor_account_id();
-
+        assert!(shares.0 > 0, "No shares to burn");
         assert!(
-            shares.0 <= self.max_redeem(owner.clone()).0,
-            "Exceeds max redeem"
+            shares.0 < self.token.ft_total_supply().0,
+            "Cannot burn the entire share supply"
         );
 
-        let assets = self.internal_convert_to_assets(shares.0, Rounding::Down);
+        let owner = env::predecessor_account_id();
+        // A pending withdrawal's rollback mints back against the supply it burned from
+        self.assert_not_locked(&self.internal_account_state(&owner));
+        self.assert_unlocked_shares(&owner, shares.0);
+        self.token.internal_withdraw(&owner, shares.0);
+        self.internal_record_price();
 
-        PromiseOrValue::Promise(self.internal_execute_withdrawal(
-            owner,
-            receiver_id,
-            shares.0,
-            assets,
-            memo,
-        ))
+        FtBurn {
+            owner_id: &owner,
+            amount: shares,
+            memo: Some("Shares burned"),
+        }
+        .emit();
+
+        if self.emit_vault_events {
+            VaultSharesBurned {
+                owner_id: &owner,
+                shares,
+            }
+            .emit();
+        }
     }
 
-    #[payable]
-    fn withdraw(
+    /// Measures what the transfer of `amount` brought in, then credits it. Never panics, so
+    /// that the surplus it records and the verification lock always settle.
+    #[private]
+    pub fn resolve_verified_deposit(
         &mut self,
-        assets: U128,
-        receiver_id: Option<AccountId>,
-        memo: Option<String>,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
     ) -> PromiseOrValue<U128> {
-        assert_one_yocto();
+        // An outbound transfer may have left the balance before its callback settled it, the
+        // change in balance can't be told apart from what this transfer brought in
+        let Some(balance) = balance.ok().filter(|_| self.withdrawals_in_flight == 0) else {
+            // Can't tell what was received, refund the whole transfer
+            self.deposit_verification_pending = false;
+            return PromiseOrValue::Value(amount);
+        };
 
-        let owner = env::predecessor_account_id();
-        assert!(
-            assets.0 <= self.max_withdraw(owner.clone()).0,
-            "Exceeds max withdraw"
-        );
+        // Only what the balance holds above the tracked assets and the surplus seen at the last
+        // query arrived with this transfer
+        let surplus = balance.0.saturating_sub(self.internal_idle_assets());
+        let received = surplus.saturating_sub(self.untracked_balance).min(amount.0);
+        self.untracked_balance = surplus - received;
 
-        let shares = self.internal_convert_to_shares(assets.0, Rounding::Up);
+        PromiseOrValue::Promise(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_CREDIT_VERIFIED_DEPOSIT)
+                .credit_verified_deposit(sender_id, U128(received), msg)
+                .then(
+                    ext_self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_SETTLE_VERIFIED_DEPOSIT)
+                        .settle_verified_deposit(U128(received)),
+                ),
+        )
+    }
 
-        PromiseOrValue::Promise(self.internal_execute_withdrawal(
+    /// Credits the `received` part of a verified deposit. What the asset burned in transit never
+    /// reached the vault, the unused amount is taken out of what did.
+    #[private]
+    pub fn credit_verified_deposit(
+        &mut self,
+        sender_id: AccountId,
+        received: U128,
+        msg: String,
+    ) -> U128 {
+        let parsed_msg = DepositMessage::parse(&msg);
+        U128(self.internal_handle_deposit(sender_id, received.0, parsed_msg))
+    }
+
+    /// Runs after every credited deposit, whether or not `credit_verified_deposit` succeeded,
+    /// so a failed one can't leave later deposits locked out. A failed one refunds no more than
+    /// was received, the rest would come out of the other holders' assets.
+    #[private]
+    pub fn settle_verified_deposit(
+        &mut self,
+        #[callback_result] unused: Result<U128, PromiseError>,
+        received: U128,
+    ) -> U128 {
+        self.deposit_verification_pending = false;
+
+        unused.unwrap_or(received)
+    }
+
+    #[private]
+    pub fn resolve_strategy_withdrawal(
+        &mut self,
+        #[callback_result] returned: Result<U128, PromiseError>,
+        withdrawal: StrategyWithdrawal,
+    ) -> PromiseOrValue<WithdrawOutcome> {
+        let StrategyWithdrawal {
             owner,
-            receiver_id,
+            receiver,
             shares,
-            assets.0,
+            quoted,
+            from_strategy,
             memo,
-        ))
+            max_loss_bps,
+            unwrap,
+            bridge_msg,
+        } = withdrawal;
+        let from_idle = quoted.0 - from_strategy.0;
+        let returned = returned.map_or(0, |returned| returned.0.min(from_strategy.0));
+        let realized = from_idle + returned;
+
+        let max_loss = mul_div(quoted.0, max_loss_bps as u128, 10_000, Rounding::Down);
+        if quoted.0 - realized > max_loss {
+            // Too much slippage, restore the redeem. What the strategy didn't return stays deployed
+            let mut owner_state = self.internal_account_state(&owner);
+            owner_state.withdrawal_locked = false;
+            self.internal_settle_exit_fee(&owner, &mut owner_state, 0, quoted.0);
+            owner_state.settle_deposited(false);
+            owner_state.pending_referral = None;
+            self.accounts.insert(&owner, &owner_state);
+            self.withdrawals_in_flight = self
+                .withdrawals_in_flight
+                .checked_sub(from_idle)
+                .expect("withdrawals_in_flight underflow");
+            self.deployed_assets = self
+                .deployed_assets
+                .checked_add(from_strategy.0 - returned)
+                .expect("deployed_assets overflow");
+            self.internal_increase_total_assets(quoted.0);
+            self.internal_release_outflow(quoted.0);
+            self.internal_restore_shares(&owner, shares.0);
+            self.internal_record_price();
+
+            FtMint {
+                owner_id: &owner,
+                amount: shares,
+                memo: Some("Withdrawal rollback"),
+            }
+            .emit();
+            self.internal_notify_withdraw_hook(&owner, &receiver, shares, quoted, false);
+
+            return PromiseOrValue::Value(WithdrawOutcome {
+                assets: U128(0),
+                shares: U128(0),
+                rolled_back: true,
+            });
+        }
+
+        // The redeemer bears the slippage, the unreturned part is written off. Should the
+        // transfer fail, only the shares the realized assets are worth come back
+        self.withdrawals_in_flight = self
+            .withdrawals_in_flight
+            .checked_add(returned)
+            .expect("withdrawals_in_flight overflow");
+        if realized < quoted.0 {
+            let mut owner_state = self.internal_account_state(&owner);
+            owner_state.pending_rollback_shares =
+                Some(U128(mul_div(shares.0, realized, quoted.0, Rounding::Down)));
+            self.accounts.insert(&owner, &owner_state);
+        }
+
+        PromiseOrValue::Promise(
+            self.internal_transfer_assets_with_callback(WithdrawalTransfer {
+                owner,
+                receiver,
+                shares,
+                assets: U128(realized),
+                memo,
+                unwrap,
+                bridge_msg,
+            }),
+        )
     }
 
-    fn convert_to_shares(&self, assets: U128) -> U128 {
-        U128(self.internal_convert_to_shares(assets.0, Rounding::Down))
+    /// Settles a deposit's stake like `ft_resolve_transfer`. The shares the farm refused come
+    /// back to the owner under the share lock they skipped when staked.
+    #[private]
+    pub fn resolve_farm_stake(&mut self, owner: AccountId, farm: AccountId, shares: U128) -> U128 {
+        let used = self.token.ft_resolve_transfer(owner.clone(), farm, shares);
+        let refunded = shares.0 - used.0;
+        if refunded > 0 && self.share_lock_period_ns > 0 {
+            let mut owner_state = self.internal_account_state(&owner);
+            owner_state.lock_shares(
+                refunded,
+                env::block_timestamp().saturating_add(self.share_lock_period_ns),
+            );
+            self.accounts.insert(&owner, &owner_state);
+        }
+        used
     }
 
-    fn convert_to_assets(&self, shares: U128) -> U128 {
-        U128(self.internal_convert_to_assets(shares.0, Rounding::Down))
+    /// The shares are burned and the wNEAR unwrapped by the time the NEAR is sent, so NEAR the
+    /// receiver couldn't take goes to the owner rather than staying on the vault.
+    #[private]
+    pub fn resolve_unwrapped_transfer(
+        &mut self,
+        #[callback_result] transferred: Result<(), PromiseError>,
+        owner: AccountId,
+        amount: U128,
+    ) {
+        if transferred.is_err() {
+            env::log_str("Receiver couldn't take the unwrapped NEAR, sent to the owner");
+            Promise::new(owner).transfer(NearToken::from_yoctonear(amount.0));
+        }
     }
 
-    fn preview_withdraw(&self, assets: U128) -> U128 {
-        U128(self.internal_convert_to_shares(assets.0, Rounding::Up))
+    #[private]
+    pub fn resolve_withdraw(&mut self, transfer: WithdrawalTransfer) -> WithdrawOutcome {
+        let WithdrawalTransfer {
+            owner,
+            receiver,
+            shares,
+            assets,
+            memo,
+            unwrap,
+            bridge_msg,
+        } = transfer;
+        let used = match env::promise_result(0) {
+            // The bridge resolves to the amount it used, the rest was refunded to the vault. An
+            // unreadable result can't prove a refund, the assets count as gone
+            near_sdk::PromiseResult::Successful(value) if bridge_msg.is_some() => {
+                serde_json::from_slice::<U128>(&value).map_or(assets.0, |used| used.0.min(assets.0))
+            }
+            near_sdk::PromiseResult::Successful(_) => assets.0,
+            _ => 0,
+        };
+        let succeeded = used > 0;
+        let refunded = assets.0 - used;
+
+        // The withdrawal is settled either way, release the owner's lock and escrowed fee
+        let mut owner_state = self.internal_account_state(&owner);
+        owner_state.withdrawal_locked = false;
+        let restorable = owner_state
+            .pending_rollback_shares
+            .take()
+            .map_or(shares.0, |shares| shares.0);
+        let refunded_shares = mul_div(restorable, refunded, assets.0, Rounding::Down);
+        self.internal_settle_exit_fee(&owner, &mut owner_state, used, assets.0);
+        let referral = owner_state.pending_referral.take();
+        if succeeded {
+            let pending = owner_state.pending_deposited.map_or(0, |p| p.0);
+            let restored = mul_div(pending, refunded, assets.0, Rounding::Down);
+            owner_state.restore_deposited(restored);
+            owner_state.realize_pnl(used, pending - restored);
+        }
+        owner_state.settle_deposited(succeeded);
+        self.accounts.insert(&owner, &owner_state);
+        self.withdrawals_in_flight = self
+            .withdrawals_in_flight
+            .checked_sub(assets.0)
+            .expect("withdrawals_in_flight underflow");
+
+        // Check if the transfer succeeded
+        let outcome = if succeeded {
+            let (assets, shares) = if refunded > 0 {
+                // Credit the part the bridge refunded back to the owner at the burn rate
+                self.internal_restore_shares(&owner, refunded_shares);
+                self.internal_increase_total_assets(refunded);
+                self.internal_release_outflow(refunded);
+                self.internal_record_price();
+
+                FtMint {
+                    owner_id: &owner,
+                    amount: U128(refunded_shares),
+                    memo: Some("Bridge refund"),
+                }
+                .emit();
+
+                (U128(used), U128(shares.0 - refunded_shares))
+            } else {
+                (assets, shares)
+            };
+
+            // Transfer succeeded - finalize withdrawal
+            if unwrap {
+                // The unwrapped NEAR is now held by the vault, pass it on
+                Promise::new(receiver.clone())
+                    .transfer(NearToken::from_yoctonear(assets.0))
+                    .then(
+                        ext_self::ext(env::current_account_id())
+                            .with_static_gas(GAS_FOR_RESOLVE_UNWRAPPED_TRANSFER)
+                            .resolve_unwrapped_transfer(owner.clone(), assets),
+                    );
+            }
+
+            // Emit VaultWithdraw event
+            if self.emit_vault_events {
+                VaultWithdraw {
+                    owner_id: &owner,
+                    receiver_id: &receiver,
+                    assets,
+                    shares,
+                    memo: memo.as_deref(),
+                }
+                .emit();
+
+                if let Some(referrer) = referral.as_ref() {
+                    VaultWithdrawReferral {
+                        referrer,
+                        owner_id: &owner,
+                        assets,
+                        shares,
+                    }
+                    .emit();
+                }
+            }
+
+            WithdrawOutcome {
+                assets,
+                shares,
+                rolled_back: false,
+            }
+        } else {
+            // Transfer failed - rollback state changes using callback parameters
+            // Restore shares that were burned
+            self.internal_restore_shares(&owner, restorable);
+            // Restore total_assets that was reduced, and the window's allowance
+            self.internal_increase_total_assets(assets.0);
+            self.internal_release_outflow(assets.0);
+            self.internal_record_price();
+
+            FtMint {
+                owner_id: &owner,
+                amount: U128(restorable),
+                memo: Some("Withdrawal rollback"),
+            }
+            .emit();
+
+            WithdrawOutcome {
+                assets: U128(0),
+                shares: U128(0),
+                rolled_back: true,
+            }
+        };
+
+        self.internal_notify_withdraw_hook(&owner, &receiver, shares, assets, succeeded);
+        self.internal_prune_account_state(&owner);
+        outcome
     }
 }
 
 #[near_bindgen]
 impl FungibleTokenReceiver for TokenizedVault {
+    /// Per NEP-141, resolves to the *unused* amount, which the asset's `ft_resolve_transfer`
+    /// refunds to the sender. The sender's `ft_transfer_call` in turn resolves to the used amount.
     fn ft_on_transfer(
         &mut self,
         sender_id: AccountId,
         amount: U128,
         msg: String,
     ) -> PromiseOrValue<U128> {
-        assert_eq!(
-            env::predecessor_account_id(),
-            self.asset.clone(),
-            "Only the underlying asset can be deposited"
-        );
-
-        let parsed_msg: DepositMessage = serde_json::from_str(&msg).unwrap_or_else(|_| {
-            // Return all tokens if message parsing fails
-            env::panic_str("Failed to parse deposit message");
-        });
-
-        if parsed_msg.donate.unwrap_or(false) {
-            self.total_assets = self
-                .total_assets
-                .checked_add(amount.0)
-                .expect("total_assets overflow");
+        // Nothing to credit, and a panic here would leave the sender's transfer half-resolved
+        if amount.0 == 0 {
+            return PromiseOrValue::Value(U128(0));
+        }
 
-            return PromiseOrValue::Value(0.into());
+        let token_id = env::predecessor_account_id();
+        // Shares sent back to the vault would mint shares against shares, their value would be
+        // counted twice and every later conversion skewed
+        assert_ne!(
+            token_id,
+            env::current_account_id(),
+            "Vault shares can't be deposited into the vault"
+        );
+        if let Some(old_vault) = msg.strip_prefix("migrate:") {
+            assert_eq!(
+                token_id.as_str(),
+                old_vault,
+                "Migrated shares must come from the old vault"
+            );
+            return PromiseOrValue::Promise(
+                self.internal_migrate_from(sender_id, token_id, amount.0),
+            );
         }
+        if token_id != self.asset {
+            let index = self
+                .internal_basket_index(&token_id)
+                .unwrap_or_else(|| env::panic_str("Only the underlying asset can be deposited"));
+            self.assert_not_paused();
+            let Some(parsed_msg) = DepositMessage::try_parse(&msg) else {
+                return PromiseOrValue::Value(self.internal_reject_deposit(
+                    &sender_id,
+                    amount.0,
+                    "invalid deposit message",
+                ));
+            };
+            assert_memo_len(parsed_msg.memo.as_deref());
 
-        let calculated_shares = self.convert_to_shares(amount).0;
+            return PromiseOrValue::Value(U128(
+                self.internal_handle_basket_deposit(index, sender_id, amount.0, parsed_msg),
+            ));
+        }
 
-        // Check slippage protection - if min_shares requirement can't be met, reject the deposit
-        if let Some(min_shares) = parsed_msg.min_shares {
-            if calculated_shares < min_shares.0 {
-                // Return all amount as unused (reject the entire deposit)
-                return PromiseOrValue::Value(amount);
-            }
+        self.internal_record_inbound(amount.0);
+        // Assets returned by the strategy don't mint shares
+        if self.strategy.as_ref() == Some(&sender_id) {
+            self.internal_handle_divest(amount.0);
+            return PromiseOrValue::Value(U128(0));
         }
+        self.assert_not_paused();
 
-        let shares = if let Some(max_shares) = parsed_msg.max_shares {
-            if calculated_shares > max_shares.0 {
-                max_shares.0
-            } else {
-                calculated_shares
-            }
-        } else {
-            calculated_shares
+        // A typo in the options must not turn into a deposit without them
+        let Some(parsed_msg) = DepositMessage::try_parse(&msg) else {
+            return PromiseOrValue::Value(self.internal_reject_deposit(
+                &sender_id,
+                amount.0,
+                "invalid deposit message",
+            ));
         };
-
-        let used_amount = self.internal_convert_to_assets(shares, Rounding::Up);
-        let unused_amount = amount
-            .0
-            .checked_sub(used_amount)
-            .expect("Overflow in unused amount calculation");
-
-        assert!(
-            used_amount > 0,
-            "No assets to deposit, shares: {}, amount: {}",
-            shares,
-            amount.0
-        );
-
-        let owner_id = parsed_msg.receiver_id.unwrap_or(sender_id.clone());
-        self.token.internal_deposit(&owner_id, shares);
-        self.total_assets = self
-            .total_assets
-            .checked_add(used_amount)
-            .expect("total_assets overflow");
-
-        FtMint {
-            owner_id: &owner_id,
-            amount: U128(shares),
-            memo: Some("Deposit"),
+        assert_memo_len(parsed_msg.memo.as_deref());
+        // Before the deposit is credited, a callback failing on it couldn't refund it in full
+        if let Some(farm) = parsed_msg.stake_to.as_ref() {
+            self.assert_stake_target(farm);
         }
-        .emit();
 
-        // Emit VaultDeposit event
-        VaultDeposit {
-            sender_id: &sender_id,
-            owner_id: &owner_id,
-            assets: U128(used_amount),
-            shares: U128(shares),
-            memo: parsed_msg.memo.as_deref(),
+        if self.verify_received {
+            return PromiseOrValue::Promise(
+                self.internal_verify_received(sender_id, amount.0, msg),
+            );
         }
-        .emit();
 
-        PromiseOrValue::Value(U128(unused_amount))
+        PromiseOrValue::Value(U128(
+            self.internal_handle_deposit(sender_id, amount.0, parsed_msg),
+        ))
+    }
+}
+
+#[near_bindgen]
+impl TokenizedVault {
+    /// NEP-245 receiver. The asset is a NEP-141 token, so multi-token transfers are refused and
+    /// returned to their sender.
+    #[allow(unused_variables)]
+    pub fn mt_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_ids: Vec<AccountId>,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+        msg: String,
+    ) -> PromiseOrValue<Vec<U128>> {
+        env::panic_str("Vault expects fungible-token deposits")
     }
 }
 
 // ===== Implement Fungible Token Traits for Vault Shares =====
 #[near_bindgen]
 impl FungibleTokenCore for TokenizedVault {
+    /// Transfers shares. A receiver that isn't registered yet gets registered when the caller
+    /// attaches at least the minimum storage balance plus one yocto, any excess is refunded.
     #[payable]
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
-        self.token.ft_transfer(receiver_id, amount, memo)
+        let sender_id = env::predecessor_account_id();
+        self.assert_unlocked_shares(&sender_id, amount.0);
+        if self.token.accounts.contains_key(&receiver_id) {
+            self.internal_carry_entry_time(&sender_id, &receiver_id, amount.0);
+            return self.token.ft_transfer(receiver_id, amount, memo);
+        }
+
+        let attached = env::attached_deposit().as_yoctonear();
+        let required = self.token.storage_balance_bounds().min.as_yoctonear() + 1;
+        assert!(
+            attached >= required,
+            "Receiver not registered on vault, call storage_deposit first"
+        );
+
+        self.token.internal_register_account(&receiver_id);
+        self.registered_accounts.insert(&receiver_id);
+        self.internal_carry_entry_time(&sender_id, &receiver_id, amount.0);
+        self.token
+            .internal_transfer(&sender_id, &receiver_id, amount.0, memo);
+
+        if attached > required {
+            Promise::new(sender_id).transfer(NearToken::from_yoctonear(attached - required));
+        }
     }
 
     #[payable]
@@ -296,6 +2718,13 @@ impl FungibleTokenCore for TokenizedVault {
         memo: Option<String>,
         msg: String,
     ) -> PromiseOrValue<U128> {
+        assert!(
+            self.token.accounts.contains_key(&receiver_id),
+            "Receiver not registered on vault, call storage_deposit first"
+        );
+        let sender_id = env::predecessor_account_id();
+        self.assert_unlocked_shares(&sender_id, amount.0);
+        self.internal_carry_entry_time(&sender_id, &receiver_id, amount.0);
         self.token.ft_transfer_call(receiver_id, amount, memo, msg)
     }
 
@@ -330,7 +2759,12 @@ impl StorageManagement for TokenizedVault {
         account_id: Option<AccountId>,
         registration_only: Option<bool>,
     ) -> near_contract_standards::storage_management::StorageBalance {
-        self.token.storage_deposit(account_id, registration_only)
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let storage_balance = self
+            .token
+            .storage_deposit(Some(account_id.clone()), registration_only);
+        self.registered_accounts.insert(&account_id);
+        storage_balance
     }
 
     #[payable]
@@ -354,9 +2788,60 @@ impl StorageManagement for TokenizedVault {
         self.token.storage_balance_of(account_id)
     }
 
+    /// Forcing out an account that still holds shares queues the withdrawal of their assets
+    /// instead of burning them to the benefit of the other holders.
     #[payable]
     fn storage_unregister(&mut self, force: Option<bool>) -> bool {
-        self.token.storage_unregister(force)
+        let account_id = env::predecessor_account_id();
+        let shares = self.token.ft_balance_of(account_id.clone()).0;
+        if force.unwrap_or(false)
+            && shares > 0
+            && self.internal_convert_to_assets(shares, Rounding::Down) > 0
+        {
+            // Queued like `request_withdraw`, under the same guards. A rollback would have to
+            // mint shares back into the closed account
+            self.assert_not_paused();
+            self.assert_not_locked(&self.internal_account_state(&account_id));
+            self.assert_unlocked_shares(&account_id, shares);
+            let assets = self.internal_queue_redemption(account_id.clone(), shares, "Unregister");
+            self.internal_record_outflow(assets);
+        }
+
+        let unregistered = self.token.storage_unregister(force);
+        if unregistered {
+            self.registered_accounts.remove(&account_id);
+            self.internal_prune_account_state(&account_id);
+        }
+        unregistered
+    }
+}
+
+#[near_bindgen]
+impl TokenizedVault {
+    /// Registers each of `account_ids` at the minimum storage balance in one call, skipping
+    /// those already registered, and refunds what's left of the attached deposit.
+    #[payable]
+    pub fn storage_deposit_many(&mut self, account_ids: Vec<AccountId>) {
+        let min_balance = self.token.storage_balance_bounds().min.as_yoctonear();
+        let mut required = 0;
+        for account_id in account_ids {
+            if self.token.accounts.contains_key(&account_id) {
+                continue;
+            }
+            self.token.internal_register_account(&account_id);
+            self.registered_accounts.insert(&account_id);
+            required += min_balance;
+        }
+
+        let attached = env::attached_deposit().as_yoctonear();
+        assert!(
+            attached >= required,
+            "The attached deposit is less than the minimum storage balance of the accounts"
+        );
+        if attached > required {
+            Promise::new(env::predecessor_account_id())
+                .transfer(NearToken::from_yoctonear(attached - required));
+        }
     }
 }
 
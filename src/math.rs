@@ -0,0 +1,97 @@
+//! Share and asset conversions as pure functions of the vault's supply and value, so they can be
+//! exercised without a sandbox.
+
+use crate::mul_div::{mul_div, pow10};
+
+pub use crate::mul_div::Rounding;
+
+/// Shares worth `assets` against `total_supply` shares backed by `total_assets`. An empty vault
+/// converts 1:1, scaled up by `extra_decimals`.
+pub fn shares_for_assets(
+    assets: u128,
+    total_supply: u128,
+    total_assets: u128,
+    extra_decimals: u8,
+    rounding: Rounding,
+) -> u128 {
+    // Handle empty vault case - return 1:1 ratio with extra decimals for first deposit
+    if total_supply == 0 {
+        return assets * pow10(extra_decimals);
+    }
+
+    mul_div(assets, total_supply, total_assets + 1, rounding)
+}
+
+/// Assets `shares` are worth against `total_supply` shares backed by `total_assets`, the inverse
+/// of `shares_for_assets`.
+pub fn assets_for_shares(
+    shares: u128,
+    total_supply: u128,
+    total_assets: u128,
+    extra_decimals: u8,
+    rounding: Rounding,
+) -> u128 {
+    // For empty vault, assume 1:1 ratio with extra decimals for consistency
+    if total_supply == 0 {
+        return shares / pow10(extra_decimals);
+    }
+
+    mul_div(shares, total_assets + 1, total_supply, rounding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_vault_converts_one_to_one() {
+        assert_eq!(shares_for_assets(1_000, 0, 0, 0, Rounding::Down), 1_000);
+        assert_eq!(assets_for_shares(1_000, 0, 0, 0, Rounding::Up), 1_000);
+        // Leftover assets without shares don't change the first deposit's rate
+        assert_eq!(shares_for_assets(1_000, 0, 500, 0, Rounding::Down), 1_000);
+    }
+
+    #[test]
+    fn test_empty_vault_scales_by_extra_decimals() {
+        assert_eq!(shares_for_assets(5, 0, 0, 6, Rounding::Down), 5_000_000);
+        assert_eq!(assets_for_shares(5_999_999, 0, 0, 6, Rounding::Down), 5);
+    }
+
+    #[test]
+    fn test_virtual_asset_offset() {
+        // 1000 shares backed by 1000 assets price against 1001
+        assert_eq!(shares_for_assets(500, 1_000, 1_000, 0, Rounding::Down), 499);
+        assert_eq!(shares_for_assets(500, 1_000, 1_000, 0, Rounding::Up), 500);
+        assert_eq!(
+            assets_for_shares(1_000, 1_000, 1_000, 0, Rounding::Down),
+            1_001
+        );
+        // A donation inflating a single share rounds small deposits down to nothing
+        assert_eq!(shares_for_assets(1_000, 1, 999_999, 0, Rounding::Down), 0);
+        assert_eq!(
+            assets_for_shares(1, 1, 999_999, 0, Rounding::Down),
+            1_000_000
+        );
+    }
+
+    #[test]
+    fn test_round_trip_never_gains() {
+        for assets in [1, 7, 999, 1_000_001] {
+            let shares = shares_for_assets(assets, 3_333, 10_000, 0, Rounding::Down);
+            assert!(assets_for_shares(shares, 3_333, 10_000, 0, Rounding::Down) <= assets);
+        }
+    }
+
+    #[test]
+    fn test_large_values_dont_overflow() {
+        let total = 10u128.pow(36);
+        assert_eq!(
+            assets_for_shares(total, total, total - 1, 0, Rounding::Down),
+            total
+        );
+        assert_eq!(
+            shares_for_assets(u128::MAX / 2, total, total - 1, 0, Rounding::Down),
+            u128::MAX / 2
+        );
+    }
+}
@@ -2,6 +2,8 @@
 pub enum Rounding {
     Down,
     Up,
+    /// To the closest integer, ties round up
+    Nearest,
 }
 
 pub fn mul_div(x: u128, y: u128, denominator: u128, rounding: Rounding) -> u128 {
@@ -21,5 +23,61 @@ pub fn mul_div(x: u128, y: u128, denominator: u128, rounding: Rounding) -> u128
                 result.as_u128()
             }
         }
+        Rounding::Nearest => {
+            if remainder * U256::from(2) >= denominator {
+                result.as_u128() + 1
+            } else {
+                result.as_u128()
+            }
+        }
+    }
+}
+
+/// `10^n`, panicking rather than wrapping when it doesn't fit a `u128` (`n > 38`).
+pub fn pow10(n: u8) -> u128 {
+    10u128.checked_pow(n as u32).expect("pow10 overflow")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pow10_bounds() {
+        assert_eq!(pow10(0), 1);
+        assert_eq!(
+            pow10(38),
+            100_000_000_000_000_000_000_000_000_000_000_000_000
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "pow10 overflow")]
+    fn test_pow10_overflow() {
+        pow10(39);
+    }
+
+    #[test]
+    fn test_nearest_rounds_ties_up() {
+        assert_eq!(mul_div(1, 1, 2, Rounding::Nearest), 1);
+        assert_eq!(mul_div(5, 3, 6, Rounding::Nearest), 3);
+    }
+
+    #[test]
+    fn test_nearest_just_below_half_rounds_down() {
+        assert_eq!(mul_div(49, 1, 100, Rounding::Nearest), 0);
+        assert_eq!(mul_div(149, 1, 100, Rounding::Nearest), 1);
+    }
+
+    #[test]
+    fn test_nearest_just_above_half_rounds_up() {
+        assert_eq!(mul_div(51, 1, 100, Rounding::Nearest), 1);
+        assert_eq!(mul_div(151, 1, 100, Rounding::Nearest), 2);
+    }
+
+    #[test]
+    fn test_nearest_exact_division() {
+        assert_eq!(mul_div(300, 2, 100, Rounding::Nearest), 6);
+        assert_eq!(mul_div(u128::MAX, 1, 1, Rounding::Nearest), u128::MAX);
     }
 }
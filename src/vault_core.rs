@@ -0,0 +1,135 @@
+//! `VaultCore` for the vault, kept apart so the lint allowance below covers the generated
+//! cross-contract wrapper of `redeem`: `near_bindgen` doesn't carry method attributes over to it.
+#![allow(clippy::too_many_arguments)]
+
+use super::*;
+
+#[near_bindgen]
+impl VaultCore for TokenizedVault {
+    fn asset(&self) -> AccountId {
+        self.asset.clone()
+    }
+
+    /// Value of everything the vault holds, basket tokens included, in units of the asset.
+    fn total_assets(&self) -> U128 {
+        U128(self.internal_total_value())
+    }
+
+    #[payable]
+    fn redeem(
+        &mut self,
+        shares: U128,
+        receiver_id: Option<AccountId>,
+        memo: Option<String>,
+        unwrap: Option<bool>,
+        max_loss_bps: Option<u16>,
+        msg: Option<String>,
+        max_fee_bps: Option<u16>,
+    ) -> PromiseOrValue<WithdrawOutcome> {
+        assert_one_yocto();
+        self.assert_not_paused();
+        let request = RedeemRequest {
+            owner: env::predecessor_account_id(),
+            shares,
+            receiver_id,
+            memo,
+            unwrap: unwrap.unwrap_or(false),
+            max_loss_bps,
+            msg,
+            max_fee_bps,
+        };
+
+        // Unwrapped NEAR needs no registration with the asset
+        if self.check_receiver_storage && !request.unwrap {
+            let receiver = request.receiver_id.clone().unwrap_or(request.owner.clone());
+            // Whether idle liquidity falls short is only known then, budget for a liquidation
+            let redeem_gas = GAS_FOR_REDEEM_AFTER_STORAGE_CHECK.saturating_add(
+                self.internal_withdrawal_gas(self.strategy.is_some(), false, false),
+            );
+            self.assert_gas_left(GAS_FOR_STORAGE_BALANCE_OF.saturating_add(redeem_gas));
+            return PromiseOrValue::Promise(
+                ext_storage_management::ext(self.asset.clone())
+                    .with_static_gas(GAS_FOR_STORAGE_BALANCE_OF)
+                    .storage_balance_of(receiver)
+                    .then(
+                        ext_self::ext(env::current_account_id())
+                            .with_static_gas(redeem_gas)
+                            .redeem_to_registered_receiver(request),
+                    ),
+            );
+        }
+
+        PromiseOrValue::Promise(self.internal_redeem(request))
+    }
+
+    #[payable]
+    fn withdraw(
+        &mut self,
+        assets: U128,
+        receiver_id: Option<AccountId>,
+        memo: Option<String>,
+        msg: Option<String>,
+        max_fee_bps: Option<u16>,
+    ) -> PromiseOrValue<WithdrawOutcome> {
+        assert_one_yocto();
+        self.assert_not_paused();
+        self.assert_exit_fee_within(&env::predecessor_account_id(), max_fee_bps);
+        let parsed_msg = WithdrawMessage::parse(msg.as_deref());
+        parsed_msg.assert_not_expired();
+        let memo = memo.or(parsed_msg.memo);
+        assert_memo_len(memo.as_deref());
+
+        let owner = env::predecessor_account_id();
+        self.internal_stash_withdraw_referral(&owner, parsed_msg.referral);
+        assert!(
+            assets.0 <= self.max_withdraw(owner.clone()).0,
+            "Exceeds max withdraw"
+        );
+
+        let shares = self.internal_convert_to_shares(assets.0, Rounding::Up);
+
+        // The fee comes on top of the shares burned for `assets`
+        let fee_bps = self.internal_exit_fee_bps(&owner);
+        let fee_shares = mul_div(shares, fee_bps, 10_000 - fee_bps, Rounding::Up);
+        assert!(
+            self.token.ft_balance_of(owner.clone()).0 >= shares + fee_shares,
+            "Insufficient shares"
+        );
+        self.internal_escrow_exit_fee(&owner, fee_shares);
+
+        PromiseOrValue::Promise(self.internal_execute_withdrawal(
+            owner,
+            receiver_id,
+            shares,
+            assets.0,
+            memo,
+            false,
+            None,
+            None,
+        ))
+    }
+
+    fn convert_to_shares(&self, assets: U128) -> U128 {
+        U128(self.internal_convert_to_shares(assets.0, Rounding::Down))
+    }
+
+    fn convert_to_assets(&self, shares: U128) -> U128 {
+        U128(self.internal_convert_to_assets(shares.0, Rounding::Down))
+    }
+
+    fn preview_withdraw(&self, assets: U128) -> U128 {
+        U128(self.internal_convert_to_shares(assets.0, Rounding::Up))
+    }
+
+    /// The quote `ft_on_transfer` mints from, so the dead shares of a first deposit and the
+    /// caps are accounted for the same way.
+    fn preview_deposit(&self, assets: U128) -> U128 {
+        self.internal_quote_deposit(assets.0, None).shares
+    }
+
+    /// Assets a deposit capped at `shares` with `max_shares` uses once the vault holds shares,
+    /// rounded up like the deposit.
+    fn preview_mint(&self, shares: U128) -> U128 {
+        U128(self.internal_convert_to_assets(shares.0, Rounding::Up))
+    }
+}
@@ -11,3 +11,59 @@ async fn test_mock_ft_contract_compilation() -> Result<(), Box<dyn std::error::E
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_malicious_ft_contract_compilation() -> Result<(), Box<dyn std::error::Error>> {
+    near_workspaces::compile_project("./mock_contracts/malicious_ft").await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fee_ft_contract_compilation() -> Result<(), Box<dyn std::error::Error>> {
+    near_workspaces::compile_project("./mock_contracts/fee_ft").await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_wnear_contract_compilation() -> Result<(), Box<dyn std::error::Error>> {
+    near_workspaces::compile_project("./mock_contracts/mock_wnear").await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_strategy_contract_compilation() -> Result<(), Box<dyn std::error::Error>> {
+    near_workspaces::compile_project("./mock_contracts/mock_strategy").await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_hook_contract_compilation() -> Result<(), Box<dyn std::error::Error>> {
+    near_workspaces::compile_project("./mock_contracts/mock_hook").await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_bridge_contract_compilation() -> Result<(), Box<dyn std::error::Error>> {
+    near_workspaces::compile_project("./mock_contracts/mock_bridge").await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_farm_contract_compilation() -> Result<(), Box<dyn std::error::Error>> {
+    near_workspaces::compile_project("./mock_contracts/mock_farm").await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_oracle_contract_compilation() -> Result<(), Box<dyn std::error::Error>> {
+    near_workspaces::compile_project("./mock_contracts/mock_oracle").await?;
+
+    Ok(())
+}
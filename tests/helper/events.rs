@@ -0,0 +1,155 @@
+use meteor_near_erc4626_vault::events::{
+    AdminAction, VaultDecimalsMismatch, VaultDeposit, VaultDepositRejected, VaultReferral,
+    VaultSharesBurned, VaultTvlUpdated, VaultWithdraw, VaultWithdrawReferral,
+};
+use near_sdk::{json_types::U128, serde::Deserialize, AccountId};
+
+/// NEP-141 mint, the standard's own event type can only be serialized.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtMint {
+    pub owner_id: AccountId,
+    pub amount: U128,
+    pub memo: Option<String>,
+}
+
+/// NEP-141 burn, the standard's own event type can only be serialized.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtBurn {
+    pub owner_id: AccountId,
+    pub amount: U128,
+    pub memo: Option<String>,
+}
+
+/// One entry of an event logged by the vault, borrowing from the log it was parsed from.
+#[derive(Debug, Clone)]
+pub enum VaultEvent<'a> {
+    VaultDeposit(VaultDeposit<'a>),
+    VaultWithdraw(VaultWithdraw<'a>),
+    VaultReferral(VaultReferral<'a>),
+    VaultWithdrawReferral(VaultWithdrawReferral<'a>),
+    VaultSharesBurned(VaultSharesBurned<'a>),
+    VaultDecimalsMismatch(VaultDecimalsMismatch),
+    VaultDepositRejected(VaultDepositRejected<'a>),
+    VaultTvlUpdated(VaultTvlUpdated),
+    FtMint(FtMint),
+    FtBurn(FtBurn),
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+enum LoggedEvent<'a> {
+    #[serde(borrow)]
+    VaultDeposit(Vec<VaultDeposit<'a>>),
+    #[serde(borrow)]
+    VaultWithdraw(Vec<VaultWithdraw<'a>>),
+    #[serde(borrow)]
+    VaultReferral(Vec<VaultReferral<'a>>),
+    #[serde(borrow)]
+    VaultWithdrawReferral(Vec<VaultWithdrawReferral<'a>>),
+    #[serde(borrow)]
+    VaultSharesBurned(Vec<VaultSharesBurned<'a>>),
+    VaultDecimalsMismatch(Vec<VaultDecimalsMismatch>),
+    #[serde(borrow)]
+    VaultDepositRejected(Vec<VaultDepositRejected<'a>>),
+    VaultTvlUpdated(Vec<VaultTvlUpdated>),
+    FtMint(Vec<FtMint>),
+    FtBurn(Vec<FtBurn>),
+}
+
+/// Events of other standards, like asset transfers, are skipped.
+const PARSED_EVENTS: [&str; 10] = [
+    "vault_deposit",
+    "vault_withdraw",
+    "vault_referral",
+    "vault_withdraw_referral",
+    "vault_shares_burned",
+    "vault_decimals_mismatch",
+    "vault_deposit_rejected",
+    "vault_tvl_updated",
+    "ft_mint",
+    "ft_burn",
+];
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct EventName<'a> {
+    event: &'a str,
+}
+
+/// Parses the NEP-297 events out of `logs`, one entry per event data item, skipping plain logs.
+pub fn parse_vault_events(logs: &[String]) -> Vec<VaultEvent<'_>> {
+    logs.iter()
+        .filter_map(|log| log.strip_prefix("EVENT_JSON:"))
+        .filter(|event| {
+            let name: EventName = serde_json::from_str(event).expect("Malformed event");
+            PARSED_EVENTS.contains(&name.event)
+        })
+        .flat_map(|event| -> Vec<VaultEvent> {
+            let event: LoggedEvent = serde_json::from_str(event).expect("Malformed event");
+            match event {
+                LoggedEvent::VaultDeposit(data) => {
+                    data.into_iter().map(VaultEvent::VaultDeposit).collect()
+                }
+                LoggedEvent::VaultWithdraw(data) => {
+                    data.into_iter().map(VaultEvent::VaultWithdraw).collect()
+                }
+                LoggedEvent::VaultReferral(data) => {
+                    data.into_iter().map(VaultEvent::VaultReferral).collect()
+                }
+                LoggedEvent::VaultWithdrawReferral(data) => data
+                    .into_iter()
+                    .map(VaultEvent::VaultWithdrawReferral)
+                    .collect(),
+                LoggedEvent::VaultSharesBurned(data) => data
+                    .into_iter()
+                    .map(VaultEvent::VaultSharesBurned)
+                    .collect(),
+                LoggedEvent::VaultDecimalsMismatch(data) => data
+                    .into_iter()
+                    .map(VaultEvent::VaultDecimalsMismatch)
+                    .collect(),
+                LoggedEvent::VaultDepositRejected(data) => data
+                    .into_iter()
+                    .map(VaultEvent::VaultDepositRejected)
+                    .collect(),
+                LoggedEvent::VaultTvlUpdated(data) => {
+                    data.into_iter().map(VaultEvent::VaultTvlUpdated).collect()
+                }
+                LoggedEvent::FtMint(data) => data.into_iter().map(VaultEvent::FtMint).collect(),
+                LoggedEvent::FtBurn(data) => data.into_iter().map(VaultEvent::FtBurn).collect(),
+            }
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct StandardName<'a> {
+    standard: &'a str,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct LoggedAdminActions<'a> {
+    #[serde(borrow)]
+    data: Vec<AdminAction<'a>>,
+}
+
+/// Parses the audit trail of owner-only actions out of `logs`, logged under the `vault_admin`
+/// standard.
+pub fn parse_admin_actions(logs: &[String]) -> Vec<AdminAction<'_>> {
+    logs.iter()
+        .filter_map(|log| log.strip_prefix("EVENT_JSON:"))
+        .filter(|event| {
+            let name: StandardName = serde_json::from_str(event).expect("Malformed event");
+            name.standard == "vault_admin"
+        })
+        .flat_map(|event| {
+            let event: LoggedAdminActions = serde_json::from_str(event).expect("Malformed event");
+            event.data
+        })
+        .collect()
+}
@@ -0,0 +1,37 @@
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
+use near_workspaces::{Account, Contract};
+use serde_json::json;
+
+pub async fn deploy_and_init_fee_ft(
+    owner: &Account,
+    total_supply: u128,
+    fee_bps: u16,
+) -> Result<Contract, Box<dyn std::error::Error>> {
+    let contract_code = near_workspaces::compile_project("./mock_contracts/fee_ft").await?;
+
+    let contract = owner.deploy(&contract_code).await?.into_result()?;
+
+    let metadata = FungibleTokenMetadata {
+        spec: "ft-1.0.0".to_string(),
+        name: "Fee Token".to_string(),
+        symbol: "FEE".to_string(),
+        icon: None,
+        reference: None,
+        reference_hash: None,
+        decimals: 6,
+    };
+
+    contract
+        .call("new")
+        .args_json(json!({
+            "owner_id": owner.id(),
+            "total_supply": total_supply.to_string(),
+            "metadata": metadata,
+            "fee_bps": fee_bps,
+        }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    Ok(contract)
+}
@@ -0,0 +1,55 @@
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
+use near_sdk::json_types::U128;
+use near_workspaces::{result::ExecutionFinalResult, Account, Contract};
+use serde_json::json;
+
+pub async fn deploy_and_init_malicious_ft(
+    owner: &Account,
+    total_supply: u128,
+) -> Result<Contract, Box<dyn std::error::Error>> {
+    let contract_code = near_workspaces::compile_project("./mock_contracts/malicious_ft").await?;
+
+    let contract = owner.deploy(&contract_code).await?.into_result()?;
+
+    let metadata = FungibleTokenMetadata {
+        spec: "ft-1.0.0".to_string(),
+        name: "Evil Token".to_string(),
+        symbol: "EVIL".to_string(),
+        icon: None,
+        reference: None,
+        reference_hash: None,
+        decimals: 6,
+    };
+
+    contract
+        .call("new")
+        .args_json(json!({
+            "owner_id": owner.id(),
+            "total_supply": total_supply.to_string(),
+            "metadata": metadata,
+        }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    Ok(contract)
+}
+
+pub async fn malicious_ft_attack(
+    malicious_ft: &Contract,
+    caller: &Account,
+    vault_contract: &Contract,
+    shares: u128,
+) -> Result<ExecutionFinalResult, Box<dyn std::error::Error>> {
+    let result = caller
+        .call(malicious_ft.id(), "attack")
+        .args_json(json!({
+            "vault_id": vault_contract.id(),
+            "shares": U128(shares),
+        }))
+        .gas(near_workspaces::types::Gas::from_tgas(300))
+        .transact()
+        .await?;
+
+    Ok(result)
+}
@@ -0,0 +1,22 @@
+use near_workspaces::{Account, Contract};
+
+pub async fn deploy_mock_bridge(owner: &Account) -> Result<Contract, Box<dyn std::error::Error>> {
+    let contract_code = near_workspaces::compile_project("./mock_contracts/mock_bridge").await?;
+
+    let bridge_account = owner
+        .create_subaccount("bridge")
+        .initial_balance(near_workspaces::types::NearToken::from_near(5))
+        .transact()
+        .await?
+        .into_result()?;
+    let contract = bridge_account.deploy(&contract_code).await?.into_result()?;
+
+    Ok(contract)
+}
+
+pub async fn mock_bridge_transfers(
+    bridge_contract: &Contract,
+) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
+    let result: Vec<serde_json::Value> = bridge_contract.view("transfers").await?.json()?;
+    Ok(result)
+}
@@ -0,0 +1,29 @@
+use near_sdk::json_types::U128;
+use near_workspaces::{Account, Contract};
+use serde_json::json;
+
+pub async fn deploy_mock_farm(owner: &Account) -> Result<Contract, Box<dyn std::error::Error>> {
+    let contract_code = near_workspaces::compile_project("./mock_contracts/mock_farm").await?;
+
+    let farm_account = owner
+        .create_subaccount("farm")
+        .initial_balance(near_workspaces::types::NearToken::from_near(5))
+        .transact()
+        .await?
+        .into_result()?;
+    let contract = farm_account.deploy(&contract_code).await?.into_result()?;
+
+    Ok(contract)
+}
+
+pub async fn mock_farm_staked_of(
+    farm_contract: &Contract,
+    account: &Account,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result: U128 = farm_contract
+        .view("staked_of")
+        .args_json(json!({ "account_id": account.id() }))
+        .await?
+        .json()?;
+    Ok(result)
+}
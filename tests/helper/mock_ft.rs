@@ -8,6 +8,16 @@ pub async fn deploy_and_init_mock_ft(
     ft_name: &str,
     ft_symbol: &str,
     total_supply: Option<u128>,
+) -> Result<Contract, Box<dyn std::error::Error>> {
+    deploy_and_init_mock_ft_with_decimals(owner, ft_name, ft_symbol, total_supply, 6).await
+}
+
+pub async fn deploy_and_init_mock_ft_with_decimals(
+    owner: &Account,
+    ft_name: &str,
+    ft_symbol: &str,
+    total_supply: Option<u128>,
+    decimals: u8,
 ) -> Result<Contract, Box<dyn std::error::Error>> {
     let contract_code = near_workspaces::compile_project("./mock_contracts/mock_ft").await?;
 
@@ -20,7 +30,7 @@ pub async fn deploy_and_init_mock_ft(
         icon: None,
         reference: None,
         reference_hash: None,
-        decimals: 6,
+        decimals,
     };
 
     contract
@@ -0,0 +1,22 @@
+use near_workspaces::{Account, Contract};
+
+pub async fn deploy_mock_hook(owner: &Account) -> Result<Contract, Box<dyn std::error::Error>> {
+    let contract_code = near_workspaces::compile_project("./mock_contracts/mock_hook").await?;
+
+    let hook_account = owner
+        .create_subaccount("hook")
+        .initial_balance(near_workspaces::types::NearToken::from_near(5))
+        .transact()
+        .await?
+        .into_result()?;
+    let contract = hook_account.deploy(&contract_code).await?.into_result()?;
+
+    Ok(contract)
+}
+
+pub async fn mock_hook_calls(
+    hook_contract: &Contract,
+) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
+    let result: Vec<serde_json::Value> = hook_contract.view("calls").await?.json()?;
+    Ok(result)
+}
@@ -0,0 +1,54 @@
+use near_workspaces::{Account, Contract};
+use serde_json::json;
+
+pub async fn deploy_and_init_mock_legacy_vault(
+    owner: &Account,
+    asset_contract: &Contract,
+) -> Result<Contract, Box<dyn std::error::Error>> {
+    let contract_code =
+        near_workspaces::compile_project("./mock_contracts/mock_legacy_vault").await?;
+
+    let vault_account = owner
+        .create_subaccount("legacy_vault")
+        .initial_balance(near_workspaces::types::NearToken::from_near(5))
+        .transact()
+        .await?
+        .into_result()?;
+    let contract = vault_account.deploy(&contract_code).await?.into_result()?;
+
+    contract
+        .call("new")
+        .args_json(json!({ "asset": asset_contract.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    Ok(contract)
+}
+
+pub async fn mock_legacy_vault_mint(
+    vault_contract: &Contract,
+    account: &Account,
+    amount: u128,
+) -> Result<(), Box<dyn std::error::Error>> {
+    vault_contract
+        .call("mint")
+        .args_json(json!({ "account_id": account.id(), "amount": amount.to_string() }))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn mock_legacy_vault_set_roll_back(
+    vault_contract: &Contract,
+    roll_back: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    vault_contract
+        .call("set_roll_back")
+        .args_json(json!({ "roll_back": roll_back }))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
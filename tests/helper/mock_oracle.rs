@@ -0,0 +1,33 @@
+use near_workspaces::{Account, Contract};
+use serde_json::json;
+
+pub async fn deploy_mock_oracle(owner: &Account) -> Result<Contract, Box<dyn std::error::Error>> {
+    let contract_code = near_workspaces::compile_project("./mock_contracts/mock_oracle").await?;
+
+    let oracle_account = owner
+        .create_subaccount("oracle")
+        .initial_balance(near_workspaces::types::NearToken::from_near(5))
+        .transact()
+        .await?
+        .into_result()?;
+    let contract = oracle_account.deploy(&contract_code).await?.into_result()?;
+
+    Ok(contract)
+}
+
+pub async fn mock_oracle_set_price(
+    oracle_contract: &Contract,
+    asset: &Contract,
+    price: u128,
+) -> Result<(), Box<dyn std::error::Error>> {
+    oracle_contract
+        .call("set_price")
+        .args_json(json!({
+            "asset": asset.id(),
+            "price": price.to_string(),
+        }))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use near_sdk::json_types::U128;
+use near_workspaces::{Account, Contract};
+use serde_json::json;
+
+pub async fn deploy_and_init_mock_strategy(
+    owner: &Account,
+    asset_contract: &Contract,
+    vault_contract: &Contract,
+    slippage_bps: u16,
+) -> Result<Contract, Box<dyn std::error::Error>> {
+    let contract_code = near_workspaces::compile_project("./mock_contracts/mock_strategy").await?;
+
+    let strategy_account = owner
+        .create_subaccount("strategy")
+        .initial_balance(near_workspaces::types::NearToken::from_near(5))
+        .transact()
+        .await?
+        .into_result()?;
+    let contract = strategy_account
+        .deploy(&contract_code)
+        .await?
+        .into_result()?;
+
+    contract
+        .call("new")
+        .args_json(json!({
+            "asset": asset_contract.id(),
+            "vault": vault_contract.id(),
+            "slippage_bps": slippage_bps,
+        }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    Ok(contract)
+}
+
+pub async fn mock_strategy_deposited(
+    strategy_contract: &Contract,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result: U128 = strategy_contract.view("deposited").await?.json()?;
+    Ok(result)
+}
+
+pub async fn mock_strategy_accrue_rewards(
+    strategy_contract: &Contract,
+    amount: u128,
+) -> Result<(), Box<dyn std::error::Error>> {
+    strategy_contract
+        .call("accrue_rewards")
+        .args_json(json!({ "amount": amount.to_string() }))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
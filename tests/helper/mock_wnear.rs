@@ -0,0 +1,29 @@
+use near_sdk::NearToken;
+use near_workspaces::{Account, Contract};
+
+pub async fn deploy_and_init_mock_wnear(
+    owner: &Account,
+) -> Result<Contract, Box<dyn std::error::Error>> {
+    let contract_code = near_workspaces::compile_project("./mock_contracts/mock_wnear").await?;
+
+    let contract = owner.deploy(&contract_code).await?.into_result()?;
+
+    contract.call("new").transact().await?.into_result()?;
+
+    Ok(contract)
+}
+
+pub async fn near_deposit(
+    wnear_contract: &Contract,
+    account: &Account,
+    amount: NearToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    account
+        .call(wnear_contract.id(), "near_deposit")
+        .deposit(amount)
+        .transact()
+        .await?
+        .into_result()?;
+
+    Ok(())
+}
@@ -1,4 +1,25 @@
+#[allow(unused)]
+pub mod events;
+#[allow(unused)]
+pub mod fee_ft;
+#[allow(unused)]
+pub mod malicious_ft;
+#[allow(unused)]
+pub mod mock_bridge;
+#[allow(unused)]
+pub mod mock_farm;
+#[allow(unused)]
 pub mod mock_ft;
+#[allow(unused)]
+pub mod mock_hook;
+#[allow(unused)]
+pub mod mock_legacy_vault;
+#[allow(unused)]
+pub mod mock_oracle;
+#[allow(unused)]
+pub mod mock_strategy;
+#[allow(unused)]
+pub mod mock_wnear;
 
 #[allow(unused)]
 pub mod vault;
@@ -1,25 +1,98 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
-use near_sdk::{json_types::U128, NearToken};
-use near_workspaces::{Account, Contract};
+use near_sdk::{
+    json_types::{I128, U128},
+    serde::Deserialize,
+    NearToken,
+};
+use near_workspaces::{result::ExecutionFinalResult, Account, Contract};
 use serde_json::json;
 
+static NEXT_VAULT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WithdrawOutcome {
+    pub assets: U128,
+    pub shares: U128,
+    pub rolled_back: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ClampedConversion {
+    pub value: U128,
+    pub clamped: bool,
+}
+
 pub async fn deploy_and_init_vault(
     owner: &Account,
     asset_contract: &Contract,
     vault_name: &str,
     vault_symbol: &str,
     extra_decimals: u8,
+) -> Result<Contract, Box<dyn std::error::Error>> {
+    deploy_and_init_vault_with_config(
+        owner,
+        asset_contract,
+        vault_name,
+        vault_symbol,
+        extra_decimals,
+        json!({}),
+    )
+    .await
+}
+
+/// Deploys a vault passing the extra fields of `config` as additional `new` arguments.
+pub async fn deploy_and_init_vault_with_config(
+    owner: &Account,
+    asset_contract: &Contract,
+    vault_name: &str,
+    vault_symbol: &str,
+    extra_decimals: u8,
+    config: serde_json::Value,
+) -> Result<Contract, Box<dyn std::error::Error>> {
+    let contract = deploy_and_init_vault_unregistered(
+        owner,
+        asset_contract,
+        vault_name,
+        vault_symbol,
+        extra_decimals,
+        config,
+    )
+    .await?;
+
+    // Register the vault with the underlying asset for storage
+    contract
+        .as_account()
+        .call(asset_contract.id(), "storage_deposit")
+        .args_json(json!({
+            "account_id": contract.id(),
+            "registration_only": false,
+        }))
+        .deposit(NearToken::from_near(1))
+        .transact()
+        .await?
+        .into_result()?;
+
+    Ok(contract)
+}
+
+/// Deploys and initializes a vault left unregistered with its asset.
+pub async fn deploy_and_init_vault_unregistered(
+    owner: &Account,
+    asset_contract: &Contract,
+    vault_name: &str,
+    vault_symbol: &str,
+    extra_decimals: u8,
+    config: serde_json::Value,
 ) -> Result<Contract, Box<dyn std::error::Error>> {
     let contract_code = near_workspaces::compile_project("./").await?;
 
-    // Create a unique account for each vault deployment with sufficient balance
-    let vault_id = format!(
-        "v{}",
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
-    );
+    // Create a unique account for each vault deployment with sufficient balance. A counter
+    // rather than the clock, so back-to-back deploys by the same owner don't collide
+    let vault_id = format!("v{}", NEXT_VAULT_ID.fetch_add(1, Ordering::Relaxed));
     let vault_account = owner
         .create_subaccount(&vault_id)
         .initial_balance(near_workspaces::types::NearToken::from_near(10))
@@ -38,30 +111,39 @@ pub async fn deploy_and_init_vault(
         decimals: 6 + extra_decimals,
     };
 
-    contract
-        .call("new")
-        .args_json(json!({
-            "asset": asset_contract.id(),
-            "metadata": metadata,
-            "extra_decimals": extra_decimals,
-        }))
+    let mut args = json!({
+        "asset": asset_contract.id(),
+        "metadata": metadata,
+        "extra_decimals": extra_decimals,
+    });
+    if let Some(config) = config.as_object() {
+        args.as_object_mut().unwrap().extend(config.clone());
+    }
+
+    // Initialized by `owner` so that it becomes the vault owner
+    owner
+        .call(contract.id(), "new")
+        .args_json(args)
         .transact()
         .await?
         .into_result()?;
 
-    // Register the vault with the underlying asset for storage
-    vault_account
-        .call(asset_contract.id(), "storage_deposit")
-        .args_json(json!({
-            "account_id": contract.id(),
-            "registration_only": false,
-        }))
-        .deposit(NearToken::from_near(1))
+    Ok(contract)
+}
+
+pub async fn vault_register_with_asset(
+    vault_contract: &Contract,
+    owner: &Account,
+    deposit: NearToken,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let result = owner
+        .call(vault_contract.id(), "register_with_asset")
+        .deposit(deposit)
+        .gas(near_workspaces::types::Gas::from_tgas(100))
         .transact()
         .await?
         .into_result()?;
-
-    Ok(contract)
+    Ok(result.json()?)
 }
 
 pub async fn vault_storage_deposit(
@@ -82,6 +164,7 @@ pub async fn vault_storage_deposit(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn ft_transfer_call_deposit(
     ft_contract: &Contract,
     vault_contract: &Contract,
@@ -100,6 +183,7 @@ pub async fn ft_transfer_call_deposit(
         || donate.is_some()
     {
         json!({
+            "action": "deposit",
             "receiver_id": receiver_id.map(|acc| acc.id()),
             "min_shares": min_shares.map(|s| s.to_string()),
             "max_shares": max_shares.map(|s| s.to_string()),
@@ -127,13 +211,35 @@ pub async fn ft_transfer_call_deposit(
     Ok(result.json()?)
 }
 
+pub async fn ft_transfer_call_with_msg(
+    ft_contract: &Contract,
+    vault_contract: &Contract,
+    sender: &Account,
+    amount: u128,
+    msg: &str,
+) -> Result<ExecutionFinalResult, Box<dyn std::error::Error>> {
+    let result = sender
+        .call(ft_contract.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": vault_contract.id(),
+            "amount": amount.to_string(),
+            "msg": msg,
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(100))
+        .transact()
+        .await?;
+
+    Ok(result)
+}
+
 pub async fn vault_redeem(
     vault_contract: &Contract,
     account: &Account,
     shares: u128,
     receiver_id: Option<&Account>,
     memo: Option<&str>,
-) -> Result<U128, Box<dyn std::error::Error>> {
+) -> Result<WithdrawOutcome, Box<dyn std::error::Error>> {
     let result = account
         .call(vault_contract.id(), "redeem")
         .args_json(json!({
@@ -156,7 +262,7 @@ pub async fn vault_withdraw(
     assets: u128,
     receiver_id: Option<&Account>,
     memo: Option<&str>,
-) -> Result<U128, Box<dyn std::error::Error>> {
+) -> Result<WithdrawOutcome, Box<dyn std::error::Error>> {
     let result = account
         .call(vault_contract.id(), "withdraw")
         .args_json(json!({
@@ -210,6 +316,32 @@ pub async fn vault_convert_to_assets(
     Ok(result)
 }
 
+pub async fn vault_preview_deposit(
+    vault_contract: &Contract,
+    account: &Account,
+    assets: u128,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result: U128 = account
+        .view(vault_contract.id(), "preview_deposit")
+        .args_json(json!({"assets": assets.to_string()}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_preview_mint(
+    vault_contract: &Contract,
+    account: &Account,
+    shares: u128,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result: U128 = account
+        .view(vault_contract.id(), "preview_mint")
+        .args_json(json!({"shares": shares.to_string()}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
 pub async fn vault_preview_withdraw(
     vault_contract: &Contract,
     account: &Account,
@@ -254,3 +386,1280 @@ pub async fn vault_total_supply(
         .json()?;
     Ok(result)
 }
+
+pub async fn vault_assets_of(
+    vault_contract: &Contract,
+    account: &Account,
+    account_id: &Account,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result: U128 = account
+        .view(vault_contract.id(), "assets_of")
+        .args_json(json!({"account_id": account_id.id()}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_burn_shares(
+    vault_contract: &Contract,
+    account: &Account,
+    shares: u128,
+) -> Result<(), Box<dyn std::error::Error>> {
+    account
+        .call(vault_contract.id(), "burn_shares")
+        .args_json(json!({"shares": shares.to_string()}))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_account_state_of(
+    vault_contract: &Contract,
+    account: &Account,
+    account_id: &Account,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let result: serde_json::Value = account
+        .view(vault_contract.id(), "account_state_of")
+        .args_json(json!({"account_id": account_id.id()}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_available_assets(
+    vault_contract: &Contract,
+    account: &Account,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result: U128 = account
+        .view(vault_contract.id(), "available_assets")
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_reserve_assets(
+    vault_contract: &Contract,
+    owner: &Account,
+    amount: u128,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "reserve_assets")
+        .args_json(json!({"amount": amount.to_string()}))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_set_strategy(
+    vault_contract: &Contract,
+    owner: &Account,
+    strategy: Option<&Account>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_strategy")
+        .args_json(json!({"strategy_id": strategy.map(|acc| acc.id())}))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_invest(
+    vault_contract: &Contract,
+    owner: &Account,
+    amount: u128,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let result = owner
+        .call(vault_contract.id(), "invest")
+        .args_json(json!({"amount": amount.to_string()}))
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(100))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(result.json()?)
+}
+
+pub async fn vault_deployed_assets(
+    vault_contract: &Contract,
+    account: &Account,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result: U128 = account
+        .view(vault_contract.id(), "deployed_assets")
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_request_withdraw(
+    vault_contract: &Contract,
+    account: &Account,
+    shares: u128,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result = account
+        .call(vault_contract.id(), "request_withdraw")
+        .args_json(json!({"shares": shares.to_string()}))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(result.json()?)
+}
+
+pub async fn vault_process_withdrawals(
+    vault_contract: &Contract,
+    account: &Account,
+    max: u32,
+) -> Result<u32, Box<dyn std::error::Error>> {
+    let result = account
+        .call(vault_contract.id(), "process_withdrawals")
+        .args_json(json!({"max": max}))
+        .gas(near_workspaces::types::Gas::from_tgas(300))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(result.json()?)
+}
+
+pub async fn vault_claimable_withdrawal(
+    vault_contract: &Contract,
+    account: &Account,
+    account_id: &Account,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result: U128 = account
+        .view(vault_contract.id(), "claimable_withdrawal")
+        .args_json(json!({"account_id": account_id.id()}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_asset_balance(
+    vault_contract: &Contract,
+    account: &Account,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result = account
+        .call(vault_contract.id(), "asset_balance")
+        .gas(near_workspaces::types::Gas::from_tgas(50))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(result.json()?)
+}
+
+pub async fn vault_absorb_untracked_assets(
+    vault_contract: &Contract,
+    owner: &Account,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result = owner
+        .call(vault_contract.id(), "absorb_untracked_assets")
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(50))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(result.json()?)
+}
+
+pub async fn vault_withdraw_stranded_near(
+    vault_contract: &Contract,
+    owner: &Account,
+    receiver: &Account,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result = owner
+        .call(vault_contract.id(), "withdraw_stranded_near")
+        .args_json(json!({ "receiver": receiver.id() }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(result.json()?)
+}
+
+pub async fn vault_set_performance_fee(
+    vault_contract: &Contract,
+    owner: &Account,
+    performance_fee_bps: u16,
+    fee_recipient: &Account,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_performance_fee")
+        .args_json(json!({
+            "performance_fee_bps": performance_fee_bps,
+            "fee_recipient": fee_recipient.id(),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_report_yield(
+    vault_contract: &Contract,
+    owner: &Account,
+    strategy_balance: u128,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "report_yield")
+        .args_json(json!({"strategy_balance": strategy_balance.to_string()}))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_high_water_mark(
+    vault_contract: &Contract,
+    account: &Account,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result: U128 = account
+        .view(vault_contract.id(), "high_water_mark")
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_price_per_share(
+    vault_contract: &Contract,
+    account: &Account,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result: U128 = account
+        .view(vault_contract.id(), "price_per_share")
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_twap(
+    vault_contract: &Contract,
+    account: &Account,
+    window_ns: u64,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result: U128 = account
+        .view(vault_contract.id(), "twap")
+        .args_json(json!({"window_ns": window_ns.to_string()}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_estimated_apy_bps(
+    vault_contract: &Contract,
+    account: &Account,
+    window_ns: u64,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let result: i64 = account
+        .view(vault_contract.id(), "estimated_apy_bps")
+        .args_json(json!({"window_ns": window_ns.to_string()}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_share_transfer(
+    vault_contract: &Contract,
+    sender: &Account,
+    receiver: &Account,
+    amount: u128,
+    deposit: NearToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    sender
+        .call(vault_contract.id(), "ft_transfer")
+        .args_json(json!({
+            "receiver_id": receiver.id(),
+            "amount": amount.to_string(),
+        }))
+        .deposit(deposit)
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+/// Calls `exit` with `kind` being `"Shares"` or `"Assets"`.
+pub async fn vault_exit(
+    vault_contract: &Contract,
+    account: &Account,
+    amount: u128,
+    kind: &str,
+    receiver_id: Option<&Account>,
+) -> Result<WithdrawOutcome, Box<dyn std::error::Error>> {
+    let result = account
+        .call(vault_contract.id(), "exit")
+        .args_json(json!({
+            "amount": amount.to_string(),
+            "kind": kind,
+            "receiver_id": receiver_id.map(|acc| acc.id()),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(100))
+        .transact()
+        .await?
+        .into_result()?;
+
+    Ok(result.json()?)
+}
+
+pub async fn vault_redeem_unwrap(
+    vault_contract: &Contract,
+    account: &Account,
+    shares: u128,
+    receiver_id: Option<&Account>,
+) -> Result<WithdrawOutcome, Box<dyn std::error::Error>> {
+    let result = account
+        .call(vault_contract.id(), "redeem")
+        .args_json(json!({
+            "shares": shares.to_string(),
+            "receiver_id": receiver_id.map(|acc| acc.id()),
+            "unwrap": true,
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(100))
+        .transact()
+        .await?
+        .into_result()?;
+
+    Ok(result.json()?)
+}
+
+pub async fn vault_ft_balances_of(
+    vault_contract: &Contract,
+    account: &Account,
+    account_ids: &[&Account],
+) -> Result<Vec<U128>, Box<dyn std::error::Error>> {
+    let account_ids: Vec<_> = account_ids.iter().map(|acc| acc.id()).collect();
+    let result: Vec<U128> = account
+        .view(vault_contract.id(), "ft_balances_of")
+        .args_json(json!({"account_ids": account_ids}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_assets_of_many(
+    vault_contract: &Contract,
+    account: &Account,
+    account_ids: &[&Account],
+) -> Result<Vec<U128>, Box<dyn std::error::Error>> {
+    let account_ids: Vec<_> = account_ids.iter().map(|acc| acc.id()).collect();
+    let result: Vec<U128> = account
+        .view(vault_contract.id(), "assets_of_many")
+        .args_json(json!({"account_ids": account_ids}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_redeem_with_max_loss(
+    vault_contract: &Contract,
+    account: &Account,
+    shares: u128,
+    max_loss_bps: u16,
+) -> Result<WithdrawOutcome, Box<dyn std::error::Error>> {
+    let result = account
+        .call(vault_contract.id(), "redeem")
+        .args_json(json!({
+            "shares": shares.to_string(),
+            "max_loss_bps": max_loss_bps,
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(300))
+        .transact()
+        .await?
+        .into_result()?;
+
+    Ok(result.json()?)
+}
+
+/// Calls `method`, `redeem` or `withdraw`, on `amount` with `max_fee_bps`, returning the raw
+/// result so that a revert can be inspected.
+pub async fn vault_exit_with_max_fee(
+    vault_contract: &Contract,
+    account: &Account,
+    method: &str,
+    amount: u128,
+    max_fee_bps: u16,
+) -> Result<ExecutionFinalResult, Box<dyn std::error::Error>> {
+    let amount_key = if method == "redeem" {
+        "shares"
+    } else {
+        "assets"
+    };
+    let result = account
+        .call(vault_contract.id(), method)
+        .args_json(json!({
+            (amount_key): amount.to_string(),
+            "max_fee_bps": max_fee_bps,
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(100))
+        .transact()
+        .await?;
+
+    Ok(result)
+}
+
+pub async fn vault_storage_balance_bounds_min(
+    vault_contract: &Contract,
+    account: &Account,
+) -> Result<NearToken, Box<dyn std::error::Error>> {
+    let result: serde_json::Value = account
+        .view(vault_contract.id(), "storage_balance_bounds")
+        .await?
+        .json()?;
+    let min: u128 = result["min"].as_str().unwrap().parse()?;
+    Ok(NearToken::from_yoctonear(min))
+}
+
+pub async fn vault_storage_deposit_exact(
+    vault_contract: &Contract,
+    account: &Account,
+    amount: NearToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    account
+        .call(vault_contract.id(), "storage_deposit")
+        .args_json(json!({ "account_id": account.id() }))
+        .deposit(amount)
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_storage_deposit_many(
+    vault_contract: &Contract,
+    payer: &Account,
+    accounts: &[&Account],
+    amount: NearToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let account_ids: Vec<_> = accounts.iter().map(|acc| acc.id()).collect();
+    payer
+        .call(vault_contract.id(), "storage_deposit_many")
+        .args_json(json!({ "account_ids": account_ids }))
+        .deposit(amount)
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_set_exit_fee(
+    vault_contract: &Contract,
+    owner: &Account,
+    max_early_exit_bps: u16,
+    exit_fee_decay_ns: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_exit_fee")
+        .args_json(json!({
+            "max_early_exit_bps": max_early_exit_bps,
+            "exit_fee_decay_ns": exit_fee_decay_ns.to_string(),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_set_fee_exempt(
+    vault_contract: &Contract,
+    owner: &Account,
+    account: &Account,
+    exempt: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let method = if exempt {
+        "add_fee_exempt"
+    } else {
+        "remove_fee_exempt"
+    };
+    owner
+        .call(vault_contract.id(), method)
+        .args_json(json!({ "account_id": account.id() }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_exit_fee_bps_of(
+    vault_contract: &Contract,
+    account: &Account,
+    account_id: &Account,
+) -> Result<u16, Box<dyn std::error::Error>> {
+    let result: u16 = account
+        .view(vault_contract.id(), "exit_fee_bps_of")
+        .args_json(json!({"account_id": account_id.id()}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_add_basket_asset(
+    vault_contract: &Contract,
+    owner: &Account,
+    token: &Contract,
+    price: u128,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "add_basket_asset")
+        .args_json(json!({
+            "token_id": token.id(),
+            "price": price.to_string(),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_redeem_in_kind(
+    vault_contract: &Contract,
+    account: &Account,
+    shares: u128,
+) -> Result<WithdrawOutcome, Box<dyn std::error::Error>> {
+    let result = account
+        .call(vault_contract.id(), "redeem_in_kind")
+        .args_json(json!({ "shares": shares.to_string() }))
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(300))
+        .transact()
+        .await?
+        .into_result()?;
+
+    Ok(result.json()?)
+}
+
+pub async fn vault_pause(
+    vault_contract: &Contract,
+    account: &Account,
+) -> Result<(), Box<dyn std::error::Error>> {
+    account
+        .call(vault_contract.id(), "pause")
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_unpause(
+    vault_contract: &Contract,
+    account: &Account,
+) -> Result<(), Box<dyn std::error::Error>> {
+    account
+        .call(vault_contract.id(), "unpause")
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_is_paused(
+    vault_contract: &Contract,
+    account: &Account,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let result: bool = account
+        .view(vault_contract.id(), "is_paused")
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_price_per_share_scaled(
+    vault_contract: &Contract,
+    account: &Account,
+    scale_decimals: u8,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result: U128 = account
+        .view(vault_contract.id(), "price_per_share_scaled")
+        .args_json(json!({ "scale_decimals": scale_decimals }))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_check_invariants(
+    vault_contract: &Contract,
+    account: &Account,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let result = account
+        .call(vault_contract.id(), "check_invariants")
+        .gas(near_workspaces::types::Gas::from_tgas(50))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(result.json()?)
+}
+
+pub async fn vault_exchange_rate(
+    vault_contract: &Contract,
+    account: &Account,
+) -> Result<(u128, u128), Box<dyn std::error::Error>> {
+    let (numerator, denominator): (U128, U128) = account
+        .view(vault_contract.id(), "exchange_rate")
+        .await?
+        .json()?;
+    Ok((numerator.0, denominator.0))
+}
+
+pub async fn vault_is_healthy(
+    vault_contract: &Contract,
+    account: &Account,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let result = account
+        .call(vault_contract.id(), "is_healthy")
+        .gas(near_workspaces::types::Gas::from_tgas(50))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(result.json()?)
+}
+
+pub async fn vault_storage_unregister(
+    vault_contract: &Contract,
+    account: &Account,
+    force: bool,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let result = account
+        .call(vault_contract.id(), "storage_unregister")
+        .args_json(json!({ "force": force }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(result.json()?)
+}
+
+/// Returns the account's storage balance after the withdrawal, as `(total, available)`.
+pub async fn vault_storage_withdraw(
+    vault_contract: &Contract,
+    account: &Account,
+    amount: Option<NearToken>,
+) -> Result<(NearToken, NearToken), Box<dyn std::error::Error>> {
+    let result: serde_json::Value = account
+        .call(vault_contract.id(), "storage_withdraw")
+        .args_json(json!({ "amount": amount }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?
+        .json()?;
+    let total: u128 = result["total"].as_str().unwrap().parse()?;
+    let available: u128 = result["available"].as_str().unwrap().parse()?;
+    Ok((
+        NearToken::from_yoctonear(total),
+        NearToken::from_yoctonear(available),
+    ))
+}
+
+pub async fn vault_set_withdraw_hook(
+    vault_contract: &Contract,
+    owner: &Account,
+    hook: Option<&Account>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_withdraw_hook")
+        .args_json(json!({ "hook_id": hook.map(|acc| acc.id()) }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_sync_asset_decimals(
+    vault_contract: &Contract,
+    owner: &Account,
+) -> Result<ExecutionFinalResult, Box<dyn std::error::Error>> {
+    let result = owner
+        .call(vault_contract.id(), "sync_asset_decimals")
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(50))
+        .transact()
+        .await?;
+    Ok(result)
+}
+
+pub async fn vault_asset_decimals(
+    vault_contract: &Contract,
+    account: &Account,
+) -> Result<Option<u8>, Box<dyn std::error::Error>> {
+    let result: Option<u8> = account
+        .view(vault_contract.id(), "asset_decimals")
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_share_decimals(
+    vault_contract: &Contract,
+    account: &Account,
+) -> Result<u8, Box<dyn std::error::Error>> {
+    let result: u8 = account
+        .view(vault_contract.id(), "share_decimals")
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_deposited_assets_of(
+    vault_contract: &Contract,
+    account: &Account,
+    account_id: &Account,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result: U128 = account
+        .view(vault_contract.id(), "deposited_assets_of")
+        .args_json(json!({"account_id": account_id.id()}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_set_max_deposit_per_tx(
+    vault_contract: &Contract,
+    owner: &Account,
+    max_deposit_per_tx: Option<u128>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_max_deposit_per_tx")
+        .args_json(json!({
+            "max_deposit_per_tx": max_deposit_per_tx.map(|max| max.to_string()),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_set_total_assets_cap(
+    vault_contract: &Contract,
+    owner: &Account,
+    total_assets_cap: Option<u128>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_total_assets_cap")
+        .args_json(json!({
+            "total_assets_cap": total_assets_cap.map(|cap| cap.to_string()),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_set_withdraw_limit(
+    vault_contract: &Contract,
+    owner: &Account,
+    max_withdraw_per_window: Option<u128>,
+    window_ns: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_withdraw_limit")
+        .args_json(json!({
+            "max_withdraw_per_window": max_withdraw_per_window.map(|max| max.to_string()),
+            "window_ns": window_ns.to_string(),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_withdrawable_in_window(
+    vault_contract: &Contract,
+    account: &Account,
+) -> Result<Option<U128>, Box<dyn std::error::Error>> {
+    let result: Option<U128> = account
+        .view(vault_contract.id(), "withdrawable_in_window")
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_set_max_deposit_slippage_bps(
+    vault_contract: &Contract,
+    owner: &Account,
+    max_deposit_slippage_bps: Option<u16>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_max_deposit_slippage_bps")
+        .args_json(json!({ "max_deposit_slippage_bps": max_deposit_slippage_bps }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_set_min_shares_per_receiver(
+    vault_contract: &Contract,
+    owner: &Account,
+    min_shares_per_receiver: u128,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_min_shares_per_receiver")
+        .args_json(json!({
+            "min_shares_per_receiver": min_shares_per_receiver.to_string(),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_mt_on_transfer(
+    vault_contract: &Contract,
+    account: &Account,
+    amount: u128,
+) -> Result<ExecutionFinalResult, Box<dyn std::error::Error>> {
+    let result = account
+        .call(vault_contract.id(), "mt_on_transfer")
+        .args_json(json!({
+            "sender_id": account.id(),
+            "previous_owner_ids": [account.id()],
+            "token_ids": ["1"],
+            "amounts": [amount.to_string()],
+            "msg": "",
+        }))
+        .transact()
+        .await?;
+    Ok(result)
+}
+
+pub async fn vault_quote_deposit(
+    vault_contract: &Contract,
+    account: &Account,
+    assets: u128,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let result: serde_json::Value = account
+        .view(vault_contract.id(), "quote_deposit")
+        .args_json(json!({"assets": assets.to_string()}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_clamped_convert_to_assets(
+    vault_contract: &Contract,
+    account: &Account,
+    shares: u128,
+) -> Result<ClampedConversion, Box<dyn std::error::Error>> {
+    let result: ClampedConversion = account
+        .view(vault_contract.id(), "clamped_convert_to_assets")
+        .args_json(json!({"shares": shares.to_string(), "account_id": account.id()}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_clamped_convert_to_shares(
+    vault_contract: &Contract,
+    account: &Account,
+    assets: u128,
+) -> Result<ClampedConversion, Box<dyn std::error::Error>> {
+    let result: ClampedConversion = account
+        .view(vault_contract.id(), "clamped_convert_to_shares")
+        .args_json(json!({"assets": assets.to_string(), "account_id": account.id()}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_migrate_asset(
+    vault_contract: &Contract,
+    owner: &Account,
+    new_asset: &Contract,
+) -> Result<ExecutionFinalResult, Box<dyn std::error::Error>> {
+    let result = owner
+        .call(vault_contract.id(), "migrate_asset")
+        .args_json(json!({ "new_asset": new_asset.id() }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?;
+    Ok(result)
+}
+
+pub async fn vault_max_redeemable_now(
+    vault_contract: &Contract,
+    account: &Account,
+    owner_id: &Account,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result: U128 = account
+        .view(vault_contract.id(), "max_redeemable_now")
+        .args_json(json!({"owner_id": owner_id.id()}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_ft_accounts(
+    vault_contract: &Contract,
+    account: &Account,
+    from_index: u64,
+    limit: u64,
+) -> Result<Vec<(String, U128)>, Box<dyn std::error::Error>> {
+    let result: Vec<(String, U128)> = account
+        .view(vault_contract.id(), "ft_accounts")
+        .args_json(json!({ "from_index": from_index, "limit": limit }))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_set_min_price_per_share(
+    vault_contract: &Contract,
+    owner: &Account,
+    min_price_per_share: Option<u128>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_min_price_per_share")
+        .args_json(json!({
+            "min_price_per_share": min_price_per_share.map(|min| min.to_string()),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_set_bridge_account(
+    vault_contract: &Contract,
+    owner: &Account,
+    bridge_account: Option<&Contract>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_bridge_account")
+        .args_json(json!({ "bridge_account": bridge_account.map(|bridge| bridge.id()) }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_set_share_lock_period(
+    vault_contract: &Contract,
+    owner: &Account,
+    share_lock_period_ns: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_share_lock_period")
+        .args_json(json!({ "share_lock_period_ns": share_lock_period_ns.to_string() }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_unlocked_shares_of(
+    vault_contract: &Contract,
+    account: &Account,
+    account_id: &Account,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result: U128 = account
+        .view(vault_contract.id(), "unlocked_shares_of")
+        .args_json(json!({"account_id": account_id.id()}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_entry_price_of(
+    vault_contract: &Contract,
+    account: &Account,
+    account_id: &Account,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result: U128 = account
+        .view(vault_contract.id(), "entry_price_of")
+        .args_json(json!({"account_id": account_id.id()}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_realized_pnl_of(
+    vault_contract: &Contract,
+    account: &Account,
+    account_id: &Account,
+) -> Result<I128, Box<dyn std::error::Error>> {
+    let result: I128 = account
+        .view(vault_contract.id(), "realized_pnl_of")
+        .args_json(json!({"account_id": account_id.id()}))
+        .await?
+        .json()?;
+    Ok(result)
+}
+
+pub async fn vault_set_farm(
+    vault_contract: &Contract,
+    owner: &Account,
+    farm: Option<&Contract>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_farm")
+        .args_json(json!({ "farm": farm.map(|farm| farm.id()) }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_set_price_oracle(
+    vault_contract: &Contract,
+    owner: &Account,
+    price_oracle: Option<&Contract>,
+    decimals: u8,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_price_oracle")
+        .args_json(json!({
+            "price_oracle": price_oracle.map(|oracle| oracle.id()),
+            "decimals": decimals,
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_total_assets_usd(
+    vault_contract: &Contract,
+    account: &Account,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result = account
+        .call(vault_contract.id(), "total_assets_usd")
+        .gas(near_workspaces::types::Gas::from_tgas(50))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(result.json()?)
+}
+
+pub async fn vault_set_migration_source(
+    vault_contract: &Contract,
+    owner: &Account,
+    migration_source: Option<&Contract>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_migration_source")
+        .args_json(json!({
+            "migration_source": migration_source.map(|vault| vault.id()),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_set_check_receiver_storage(
+    vault_contract: &Contract,
+    owner: &Account,
+    check_receiver_storage: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_check_receiver_storage")
+        .args_json(json!({ "check_receiver_storage": check_receiver_storage }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+/// Moves `shares` of `old_vault` into `new_vault` with the `"migrate:<old_vault>"` message.
+pub async fn vault_migrate(
+    old_vault: &Contract,
+    new_vault: &Contract,
+    account: &Account,
+    shares: u128,
+) -> Result<ExecutionFinalResult, Box<dyn std::error::Error>> {
+    let result = account
+        .call(old_vault.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": new_vault.id(),
+            "amount": shares.to_string(),
+            "msg": format!("migrate:{}", old_vault.id()),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(300))
+        .transact()
+        .await?;
+    Ok(result)
+}
+
+pub async fn vault_redeem_to_bridge(
+    vault_contract: &Contract,
+    account: &Account,
+    shares: u128,
+    bridge_msg: &str,
+) -> Result<WithdrawOutcome, Box<dyn std::error::Error>> {
+    let result = account
+        .call(vault_contract.id(), "redeem_to_bridge")
+        .args_json(json!({
+            "shares": shares.to_string(),
+            "bridge_msg": bridge_msg,
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(150))
+        .transact()
+        .await?
+        .into_result()?;
+
+    Ok(result.json()?)
+}
+
+pub async fn vault_refresh_total_assets(
+    vault_contract: &Contract,
+    owner: &Account,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result = owner
+        .call(vault_contract.id(), "refresh_total_assets")
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(50))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(result.json()?)
+}
+
+pub async fn vault_set_guardian(
+    vault_contract: &Contract,
+    owner: &Account,
+    guardian: Option<&Account>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_guardian")
+        .args_json(json!({ "guardian": guardian.map(|guardian| guardian.id()) }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_set_reject_zero_share_deposits(
+    vault_contract: &Contract,
+    owner: &Account,
+    reject_zero_share_deposits: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    owner
+        .call(vault_contract.id(), "set_reject_zero_share_deposits")
+        .args_json(json!({ "reject_zero_share_deposits": reject_zero_share_deposits }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn vault_harvest(
+    vault_contract: &Contract,
+    owner: &Account,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result = owner
+        .call(vault_contract.id(), "harvest")
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(100))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(result.json()?)
+}
+
+pub async fn vault_compound(
+    vault_contract: &Contract,
+    owner: &Account,
+    reinvest: bool,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result = owner
+        .call(vault_contract.id(), "compound")
+        .args_json(json!({ "reinvest": reinvest }))
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(200))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(result.json()?)
+}
+
+pub async fn vault_divest(
+    vault_contract: &Contract,
+    owner: &Account,
+    amount: u128,
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let result = owner
+        .call(vault_contract.id(), "divest")
+        .args_json(json!({"amount": amount.to_string()}))
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(100))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(result.json()?)
+}
+
+/// Redeems passing a JSON `msg`, returning the raw result so that its logs can be inspected.
+pub async fn vault_redeem_with_msg(
+    vault_contract: &Contract,
+    account: &Account,
+    shares: u128,
+    msg: &str,
+) -> Result<ExecutionFinalResult, Box<dyn std::error::Error>> {
+    let result = account
+        .call(vault_contract.id(), "redeem")
+        .args_json(json!({
+            "shares": shares.to_string(),
+            "msg": msg,
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(100))
+        .transact()
+        .await?;
+
+    Ok(result)
+}
+
+pub async fn vault_withdraw_split(
+    vault_contract: &Contract,
+    account: &Account,
+    assets: u128,
+    receivers: &[(&Account, u128)],
+) -> Result<U128, Box<dyn std::error::Error>> {
+    let receivers: Vec<_> = receivers
+        .iter()
+        .map(|(receiver, weight)| json!([receiver.id(), weight.to_string()]))
+        .collect();
+    let result = account
+        .call(vault_contract.id(), "withdraw_split")
+        .args_json(json!({
+            "assets": assets.to_string(),
+            "receivers": receivers,
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(300))
+        .transact()
+        .await?
+        .into_result()?;
+
+    Ok(result.json()?)
+}
@@ -0,0 +1,188 @@
+use meteor_near_erc4626_vault::math::{assets_for_shares, shares_for_assets, Rounding};
+
+const ACCOUNTS: usize = 4;
+const STEPS: usize = 2_000;
+const SEEDS: [u64; 4] = [1, 42, 0xdead_beef, 0x5eed_5eed_5eed_5eed];
+
+/// xorshift64*, seeded so a failing sequence can be replayed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform-ish in `0..=max`, skewed towards small amounts half the time to hit rounding.
+    fn amount(&mut self, max: u128) -> u128 {
+        let max = if self.next() % 2 == 0 {
+            max.min(1_000)
+        } else {
+            max
+        };
+        (self.next() as u128) % (max + 1)
+    }
+}
+
+/// The vault's share ledger, driven through the same conversions and roundings as the contract.
+struct Ledger {
+    balances: [u128; ACCOUNTS],
+    total_supply: u128,
+    total_assets: u128,
+    extra_decimals: u8,
+}
+
+impl Ledger {
+    fn to_shares(&self, assets: u128, rounding: Rounding) -> u128 {
+        shares_for_assets(
+            assets,
+            self.total_supply,
+            self.total_assets,
+            self.extra_decimals,
+            rounding,
+        )
+    }
+
+    fn to_assets(&self, shares: u128, rounding: Rounding) -> u128 {
+        assets_for_shares(
+            shares,
+            self.total_supply,
+            self.total_assets,
+            self.extra_decimals,
+            rounding,
+        )
+    }
+
+    /// Mints the shares `assets` buy, taking only the assets they are worth rounded up.
+    fn deposit(&mut self, account: usize, assets: u128) {
+        let shares = self.to_shares(assets, Rounding::Down);
+        if shares == 0 {
+            return;
+        }
+        let used = if self.total_supply == 0 {
+            assets
+        } else {
+            self.to_assets(shares, Rounding::Up)
+        };
+        assert!(used <= assets, "Deposit used more than transferred");
+        self.balances[account] += shares;
+        self.total_supply += shares;
+        self.total_assets += used;
+    }
+
+    /// Burns the shares for `assets` rounded up, within what the account can withdraw.
+    fn withdraw(&mut self, account: usize, assets: u128) {
+        let max = self.to_assets(self.balances[account], Rounding::Down);
+        let assets = assets.min(max);
+        if assets == 0 {
+            return;
+        }
+        let shares = self.to_shares(assets, Rounding::Up);
+        assert!(
+            shares <= self.balances[account],
+            "Withdrawal burned more shares than held"
+        );
+        self.burn(account, shares, assets);
+    }
+
+    /// Pays out the assets `shares` are worth rounded down.
+    fn redeem(&mut self, account: usize, shares: u128) {
+        let shares = shares.min(self.balances[account]);
+        let assets = self.to_assets(shares, Rounding::Down);
+        if assets == 0 {
+            return;
+        }
+        self.burn(account, shares, assets);
+    }
+
+    /// Payouts above the assets held are refused, like the vault's "Insufficient vault assets".
+    fn burn(&mut self, account: usize, shares: u128, assets: u128) {
+        if assets > self.total_assets {
+            return;
+        }
+        self.balances[account] -= shares;
+        self.total_supply -= shares;
+        self.total_assets -= assets;
+    }
+
+    /// Every holder redeeming at once can't claim more than the vault holds, give or take the
+    /// virtual asset the conversions price shares against.
+    fn assert_no_over_issuance(&self, step: usize, seed: u64) {
+        let claimable: u128 = self
+            .balances
+            .iter()
+            .map(|&balance| self.to_assets(balance, Rounding::Down))
+            .sum();
+        assert!(
+            claimable <= self.total_assets + 1,
+            "Over-issuance at step {step} with seed {seed}: {claimable} claimable, {} held",
+            self.total_assets
+        );
+    }
+}
+
+fn run(seed: u64, extra_decimals: u8) {
+    let mut rng = Rng(seed);
+    let mut ledger = Ledger {
+        balances: [0; ACCOUNTS],
+        total_supply: 0,
+        total_assets: 0,
+        extra_decimals,
+    };
+
+    for step in 0..STEPS {
+        let account = rng.next() as usize % ACCOUNTS;
+        match rng.next() % 4 {
+            0 => ledger.deposit(account, rng.amount(1_000_000_000_000)),
+            1 => ledger.withdraw(account, rng.amount(1_000_000_000_000)),
+            2 => ledger.redeem(account, rng.amount(ledger.balances[account])),
+            _ => ledger.total_assets += rng.amount(1_000_000),
+        }
+        ledger.assert_no_over_issuance(step, seed);
+    }
+}
+
+/// Test randomized deposits, withdrawals, redeems and donations never over-issue assets
+#[test]
+fn test_conversion_round_trips_never_over_issue() {
+    for seed in SEEDS {
+        run(seed, 0);
+    }
+}
+
+/// Test the same sequences on a vault with extra share decimals
+#[test]
+fn test_conversion_round_trips_with_extra_decimals() {
+    for seed in SEEDS {
+        run(seed, 6);
+    }
+}
+
+/// Test a deposit's used amount, rounded up, covers at least what its shares are worth rounded
+/// down and never exceeds the amount transferred, so the refund can't go negative
+#[test]
+fn test_deposit_used_amount_bounds() {
+    for seed in SEEDS {
+        let mut rng = Rng(seed);
+        for _ in 0..STEPS {
+            let total_supply = 1 + rng.amount(1_000_000_000_000);
+            let total_assets = rng.amount(1_000_000_000_000);
+            let amount = rng.amount(1_000_000_000_000);
+
+            let shares = shares_for_assets(amount, total_supply, total_assets, 0, Rounding::Down);
+            let used = assets_for_shares(shares, total_supply, total_assets, 0, Rounding::Up);
+            let worth = assets_for_shares(shares, total_supply, total_assets, 0, Rounding::Down);
+
+            assert!(
+                used >= worth,
+                "Used {used} below the {worth} {shares} shares are worth, seed {seed}"
+            );
+            assert!(
+                amount.checked_sub(used).is_some(),
+                "Used {used} of {amount} transferred, seed {seed}"
+            );
+        }
+    }
+}
@@ -1,9 +1,49 @@
+use near_sdk::{json_types::U128, NearToken};
+use serde_json::json;
+
 use crate::helper::{
-    mock_ft::{deploy_and_init_mock_ft, ft_balance_of, ft_storage_deposit, ft_transfer},
+    events::{parse_vault_events, VaultEvent},
+    mock_bridge::{deploy_mock_bridge, mock_bridge_transfers},
+    mock_farm::{deploy_mock_farm, mock_farm_staked_of},
+    mock_ft::{
+        deploy_and_init_mock_ft, deploy_and_init_mock_ft_with_decimals, ft_balance_of,
+        ft_storage_deposit, ft_transfer,
+    },
+    mock_hook::{deploy_mock_hook, mock_hook_calls},
+    mock_legacy_vault::{
+        deploy_and_init_mock_legacy_vault, mock_legacy_vault_mint, mock_legacy_vault_set_roll_back,
+    },
+    mock_oracle::{deploy_mock_oracle, mock_oracle_set_price},
+    mock_strategy::{
+        deploy_and_init_mock_strategy, mock_strategy_accrue_rewards, mock_strategy_deposited,
+    },
+    mock_wnear::{deploy_and_init_mock_wnear, near_deposit},
     vault::{
-        deploy_and_init_vault, ft_transfer_call_deposit, vault_asset, vault_balance_of,
-        vault_convert_to_assets, vault_convert_to_shares, vault_preview_withdraw, vault_redeem,
-        vault_storage_deposit, vault_total_assets, vault_total_supply, vault_withdraw,
+        deploy_and_init_vault, deploy_and_init_vault_unregistered,
+        deploy_and_init_vault_with_config, ft_transfer_call_deposit, ft_transfer_call_with_msg,
+        vault_absorb_untracked_assets, vault_account_state_of, vault_add_basket_asset, vault_asset,
+        vault_asset_balance, vault_asset_decimals, vault_assets_of, vault_assets_of_many,
+        vault_balance_of, vault_burn_shares, vault_claimable_withdrawal,
+        vault_clamped_convert_to_assets, vault_clamped_convert_to_shares, vault_compound,
+        vault_convert_to_assets, vault_convert_to_shares, vault_deployed_assets,
+        vault_deposited_assets_of, vault_divest, vault_entry_price_of, vault_estimated_apy_bps,
+        vault_exchange_rate, vault_exit, vault_exit_fee_bps_of, vault_exit_with_max_fee,
+        vault_ft_accounts, vault_ft_balances_of, vault_harvest, vault_high_water_mark,
+        vault_invest, vault_is_healthy, vault_max_redeemable_now, vault_migrate,
+        vault_migrate_asset, vault_pause, vault_preview_withdraw, vault_price_per_share,
+        vault_price_per_share_scaled, vault_process_withdrawals, vault_quote_deposit,
+        vault_realized_pnl_of, vault_redeem, vault_redeem_in_kind, vault_redeem_to_bridge,
+        vault_redeem_unwrap, vault_redeem_with_max_loss, vault_redeem_with_msg,
+        vault_refresh_total_assets, vault_register_with_asset, vault_report_yield,
+        vault_request_withdraw, vault_set_bridge_account, vault_set_exit_fee, vault_set_farm,
+        vault_set_fee_exempt, vault_set_max_deposit_per_tx, vault_set_max_deposit_slippage_bps,
+        vault_set_migration_source, vault_set_min_price_per_share, vault_set_performance_fee,
+        vault_set_price_oracle, vault_set_share_lock_period, vault_set_strategy,
+        vault_set_total_assets_cap, vault_set_withdraw_hook, vault_set_withdraw_limit,
+        vault_share_decimals, vault_share_transfer, vault_storage_deposit,
+        vault_storage_unregister, vault_sync_asset_decimals, vault_total_assets,
+        vault_total_assets_usd, vault_total_supply, vault_twap, vault_unlocked_shares_of,
+        vault_withdraw, vault_withdrawable_in_window, WithdrawOutcome,
     },
 };
 
@@ -33,6 +73,22 @@ async fn test_vault_initialization() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Test the same owner can deploy two vaults back-to-back
+#[tokio::test]
+async fn test_deploy_two_vaults_same_owner() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let first = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+    let second = deploy_and_init_vault(&owner, &usdt, "USDT Vault 2", "vUSDT2", 0).await?;
+
+    assert_ne!(first.id(), second.id());
+    assert_eq!(vault_asset(&second, &owner).await?, usdt.id().to_string());
+
+    Ok(())
+}
+
 /// Test deposit functionality via ft_transfer_call
 #[tokio::test]
 async fn test_deposit_functionality() -> Result<(), Box<dyn std::error::Error>> {
@@ -63,7 +119,8 @@ async fn test_deposit_functionality() -> Result<(), Box<dyn std::error::Error>>
     )
     .await?;
 
-    // Verify result is 1000 (used amount) - ft_resolve_transfer returns used amount
+    // Verify result is 1000 (used amount) - the vault's ft_on_transfer returns the unused
+    // amount, which ft_resolve_transfer refunds and turns into the used amount for the sender
     assert_eq!(result.0, 1000);
 
     // Verify vault state
@@ -119,6 +176,48 @@ async fn test_conversion_functions() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Test clamped conversions cap the input at the account's max instead of reverting
+#[tokio::test]
+async fn test_clamped_conversions() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    // Above the balance, the balance is converted
+    let assets = vault_clamped_convert_to_assets(&vault, &alice, 5000).await?;
+    assert!(assets.clamped);
+    assert_eq!(
+        assets.value,
+        vault_convert_to_assets(&vault, &alice, 1000).await?
+    );
+
+    let shares = vault_clamped_convert_to_shares(&vault, &alice, 5000).await?;
+    assert!(shares.clamped);
+    assert_eq!(
+        shares.value,
+        vault_convert_to_shares(&vault, &alice, 1000).await?
+    );
+
+    // Within the balance, same as the plain conversion
+    let assets = vault_clamped_convert_to_assets(&vault, &alice, 400).await?;
+    assert!(!assets.clamped);
+    assert_eq!(
+        assets.value,
+        vault_convert_to_assets(&vault, &alice, 400).await?
+    );
+
+    Ok(())
+}
+
 /// Test redeem functionality (burn shares for assets)
 #[tokio::test]
 async fn test_redeem_functionality() -> Result<(), Box<dyn std::error::Error>> {
@@ -157,7 +256,9 @@ async fn test_redeem_functionality() -> Result<(), Box<dyn std::error::Error>> {
     let assets_received = vault_redeem(&vault, &alice, redeem_shares, None, None).await?;
 
     // Should receive 500 assets (500 shares at 1:1 ratio)
-    assert_eq!(assets_received.0, 500);
+    assert_eq!(assets_received.assets.0, 500);
+    assert_eq!(assets_received.shares.0, redeem_shares);
+    assert!(!assets_received.rolled_back);
 
     // Verify alice's balances
     let final_alice_ft_balance = ft_balance_of(&usdt, &alice).await?;
@@ -211,10 +312,12 @@ async fn test_withdraw_functionality() -> Result<(), Box<dyn std::error::Error>>
 
     // Withdraw specific asset amount
     let withdraw_assets = 500u128;
-    let shares_used = vault_withdraw(&vault, &alice, withdraw_assets, None, None).await?;
+    let outcome = vault_withdraw(&vault, &alice, withdraw_assets, None, None).await?;
 
     // Should use 500 shares (500 assets at 1:1 ratio, rounded up)
-    assert_eq!(shares_used.0, 500);
+    assert_eq!(outcome.shares.0, 500);
+    assert_eq!(outcome.assets.0, withdraw_assets);
+    assert!(!outcome.rolled_back);
 
     // Verify alice's balances
     let final_alice_ft_balance = ft_balance_of(&usdt, &alice).await?;
@@ -233,6 +336,59 @@ async fn test_withdraw_functionality() -> Result<(), Box<dyn std::error::Error>>
     Ok(())
 }
 
+/// Test exit matches redeem for shares and withdraw for assets
+#[tokio::test]
+async fn test_exit_matches_redeem_and_withdraw() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    for account in [&alice, &bob] {
+        ft_storage_deposit(&usdt, account).await?;
+        vault_storage_deposit(&vault, account).await?;
+        ft_transfer(&usdt, &owner, account, 10000).await?;
+        ft_transfer_call_deposit(&usdt, &vault, account, 1000, None, None, None, None, None)
+            .await?;
+    }
+    // Yield so that conversions round
+    ft_transfer_call_deposit(
+        &usdt,
+        &vault,
+        &owner,
+        333,
+        None,
+        None,
+        None,
+        None,
+        Some(true),
+    )
+    .await?;
+
+    let redeemed = vault_redeem(&vault, &alice, 400, None, None).await?;
+    let exited = vault_exit(&vault, &bob, 400, "Shares", None).await?;
+    assert_eq!(exited, redeemed);
+
+    let withdrawn = vault_withdraw(&vault, &alice, 200, None, None).await?;
+    let exited = vault_exit(&vault, &bob, 200, "Assets", None).await?;
+    assert_eq!(exited, withdrawn);
+
+    assert_eq!(
+        vault_balance_of(&vault, &bob, &bob).await?,
+        vault_balance_of(&vault, &alice, &alice).await?
+    );
+    assert_eq!(
+        ft_balance_of(&usdt, &bob).await?,
+        ft_balance_of(&usdt, &alice).await?
+    );
+
+    Ok(())
+}
+
 /// Test preview_withdraw function
 #[tokio::test]
 async fn test_preview_withdraw() -> Result<(), Box<dyn std::error::Error>> {
@@ -269,8 +425,8 @@ async fn test_preview_withdraw() -> Result<(), Box<dyn std::error::Error>> {
     assert_eq!(preview_shares.0, 500);
 
     // Verify actual withdraw matches preview
-    let actual_shares_used = vault_withdraw(&vault, &alice, 500, None, None).await?;
-    assert_eq!(actual_shares_used.0, preview_shares.0);
+    let outcome = vault_withdraw(&vault, &alice, 500, None, None).await?;
+    assert_eq!(outcome.shares.0, preview_shares.0);
 
     Ok(())
 }
@@ -318,6 +474,44 @@ async fn test_deposit_with_receiver() -> Result<(), Box<dyn std::error::Error>>
     Ok(())
 }
 
+/// Test self_receive takes precedence over receiver_id
+#[tokio::test]
+async fn test_deposit_self_receive_overrides_receiver() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    vault_storage_deposit(&vault, &bob).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    let msg =
+        json!({ "action": "deposit", "receiver_id": bob.id(), "self_receive": true }).to_string();
+    ft_transfer_call_with_msg(&usdt, &vault, &alice, 1000, &msg)
+        .await?
+        .into_result()?;
+
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1000);
+    assert_eq!(vault_balance_of(&vault, &alice, &bob).await?.0, 0);
+
+    // Without the flag, receiver_id applies
+    let msg =
+        json!({ "action": "deposit", "receiver_id": bob.id(), "self_receive": false }).to_string();
+    ft_transfer_call_with_msg(&usdt, &vault, &alice, 1000, &msg)
+        .await?
+        .into_result()?;
+
+    assert_eq!(vault_balance_of(&vault, &alice, &bob).await?.0, 1000);
+
+    Ok(())
+}
+
 /// Test deposit with min_shares and max_shares parameters  
 #[tokio::test]
 async fn test_deposit_with_slippage_protection() -> Result<(), Box<dyn std::error::Error>> {
@@ -500,3 +694,2774 @@ async fn test_total_assets_never_reverts() -> Result<(), Box<dyn std::error::Err
 
     Ok(())
 }
+
+/// Test deposit with a referral emits a referral event
+#[tokio::test]
+async fn test_deposit_with_referral_event() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let referrer = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    let referral_events = |logs: Vec<&str>| -> Vec<serde_json::Value> {
+        logs.iter()
+            .filter_map(|log| log.strip_prefix("EVENT_JSON:"))
+            .map(|event| serde_json::from_str::<serde_json::Value>(event).unwrap())
+            .filter(|event| event["event"] == "vault_referral")
+            .collect()
+    };
+
+    // Deposit with a referrer
+    let msg = serde_json::json!({ "action": "deposit", "referral": referrer.id() }).to_string();
+    let result = ft_transfer_call_with_msg(&usdt, &vault, &alice, 1000, &msg).await?;
+    let events = referral_events(result.logs());
+    result.into_result()?;
+
+    assert_eq!(events.len(), 1);
+    let data = &events[0]["data"][0];
+    assert_eq!(data["referrer"], referrer.id().to_string());
+    assert_eq!(data["depositor"], alice.id().to_string());
+    assert_eq!(data["assets"], "1000");
+    assert_eq!(data["shares"], "1000");
+
+    // Deposit without a referrer
+    let result = ft_transfer_call_with_msg(&usdt, &vault, &alice, 1000, "{}").await?;
+    let events = referral_events(result.logs());
+    result.into_result()?;
+
+    assert!(
+        events.is_empty(),
+        "No referral event expected without referrer"
+    );
+
+    Ok(())
+}
+
+/// Test burning shares raises the value of the remaining holders' shares
+#[tokio::test]
+async fn test_burn_shares_boosts_other_holders() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_storage_deposit(&usdt, &bob).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    vault_storage_deposit(&vault, &bob).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer(&usdt, &owner, &bob, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &bob, 1000, None, None, None, None, None).await?;
+
+    let bob_assets_before = vault_assets_of(&vault, &bob, &bob).await?;
+    assert_eq!(bob_assets_before.0, 999); // 999 * 2001 / 1999
+
+    // Alice burns half of her shares
+    vault_burn_shares(&vault, &alice, 500).await?;
+
+    let alice_shares = vault_balance_of(&vault, &alice, &alice).await?;
+    assert_eq!(alice_shares.0, 500);
+
+    let total_supply = vault_total_supply(&vault, &alice).await?;
+    assert_eq!(total_supply.0, 1499);
+
+    // Assets stay in the vault
+    let total_assets = vault_total_assets(&vault, &alice).await?;
+    assert_eq!(total_assets.0, 2000);
+
+    let bob_assets_after = vault_assets_of(&vault, &bob, &bob).await?;
+    assert!(bob_assets_after.0 > bob_assets_before.0);
+    assert_eq!(bob_assets_after.0, 1333); // 999 * 2001 / 1499
+
+    // Burning the entire supply would orphan the vault's assets
+    let result = vault_burn_shares(&vault, &bob, 1499).await;
+    assert!(result.is_err(), "Burning the entire supply should fail");
+    let error_message = format!("{:?}", result.unwrap_err());
+    assert!(
+        error_message.contains("Cannot burn the entire share supply"),
+        "Unexpected error: {}",
+        error_message
+    );
+
+    Ok(())
+}
+
+/// Test per-account bookkeeping is kept in a single account state record
+#[tokio::test]
+async fn test_account_state_tracking() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    // No record before the first deposit
+    let state = vault_account_state_of(&vault, &alice, &alice).await?;
+    assert!(state.is_null());
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 500, None, None, None, None, None).await?;
+
+    // Every per-account feature reads from the same record
+    let state = vault_account_state_of(&vault, &alice, &alice).await?;
+    assert_eq!(state["deposited"], "1500");
+    assert!(state["entry_ns"].as_str().unwrap().parse::<u64>()? > 0);
+    assert_eq!(state["withdrawal_locked"], false);
+
+    // The withdrawal lock is released once the withdrawal settles
+    vault_redeem(&vault, &alice, 500, None, None).await?;
+    let state = vault_account_state_of(&vault, &alice, &alice).await?;
+    assert_eq!(state["withdrawal_locked"], false);
+
+    Ok(())
+}
+
+/// Test withdraw and redeem both resolve to the assets and shares actually moved
+#[tokio::test]
+async fn test_withdraw_outcome_after_yield() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    // Donation doubles the value of each share
+    ft_transfer_call_deposit(
+        &usdt,
+        &vault,
+        &alice,
+        1000,
+        None,
+        None,
+        None,
+        None,
+        Some(true),
+    )
+    .await?;
+
+    // Redeem: 100 shares * 2001 / 1000 = 200 assets
+    let outcome = vault_redeem(&vault, &alice, 100, None, None).await?;
+    assert_eq!(
+        outcome,
+        WithdrawOutcome {
+            assets: U128(200),
+            shares: U128(100),
+            rolled_back: false,
+        }
+    );
+
+    // Withdraw: 200 assets * 900 / 1801 rounded up = 100 shares
+    let outcome = vault_withdraw(&vault, &alice, 200, None, None).await?;
+    assert_eq!(
+        outcome,
+        WithdrawOutcome {
+            assets: U128(200),
+            shares: U128(100),
+            rolled_back: false,
+        }
+    );
+
+    Ok(())
+}
+
+/// Test that queued withdrawals are paid out in order as the strategy returns liquidity
+#[tokio::test]
+async fn test_withdrawal_queue_processed_in_order() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+    let strategy = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    for account in [&alice, &bob] {
+        ft_storage_deposit(&usdt, account).await?;
+        vault_storage_deposit(&vault, account).await?;
+        ft_transfer(&usdt, &owner, account, 10000).await?;
+    }
+    ft_storage_deposit(&usdt, &strategy).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &bob, 1000, None, None, None, None, None).await?;
+
+    // Leave only 200 idle
+    vault_set_strategy(&vault, &owner, Some(&strategy)).await?;
+    assert!(vault_invest(&vault, &owner, 1800).await?);
+    assert_eq!(vault_deployed_assets(&vault, &owner).await?.0, 1800);
+    assert_eq!(ft_balance_of(&usdt, &strategy).await?, 1800);
+
+    // Queue alice first, then bob
+    let alice_assets = vault_request_withdraw(&vault, &alice, 1000).await?;
+    let bob_assets = vault_request_withdraw(&vault, &bob, 500).await?;
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 0);
+    assert_eq!(
+        vault_claimable_withdrawal(&vault, &alice, &alice).await?,
+        alice_assets
+    );
+    assert_eq!(
+        vault_claimable_withdrawal(&vault, &bob, &bob).await?,
+        bob_assets
+    );
+
+    // Not enough idle liquidity for the head of the queue
+    assert_eq!(vault_process_withdrawals(&vault, &bob, 10).await?, 0);
+
+    // The strategy returns enough for alice only
+    ft_transfer_call_with_msg(&usdt, &vault, &strategy, 900, "")
+        .await?
+        .into_result()?;
+    assert_eq!(vault_deployed_assets(&vault, &owner).await?.0, 900);
+
+    assert_eq!(vault_process_withdrawals(&vault, &bob, 10).await?, 1);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 9000 + alice_assets.0);
+    assert_eq!(
+        vault_claimable_withdrawal(&vault, &alice, &alice).await?.0,
+        0
+    );
+    assert_eq!(
+        vault_claimable_withdrawal(&vault, &bob, &bob).await?,
+        bob_assets
+    );
+
+    // The rest comes back and bob gets paid
+    ft_transfer_call_with_msg(&usdt, &vault, &strategy, 900, "")
+        .await?
+        .into_result()?;
+    assert_eq!(vault_process_withdrawals(&vault, &bob, 10).await?, 1);
+    assert_eq!(ft_balance_of(&usdt, &bob).await?, 9000 + bob_assets.0);
+    assert_eq!(vault_claimable_withdrawal(&vault, &bob, &bob).await?.0, 0);
+
+    Ok(())
+}
+
+/// Test that the asset balance query reports what the vault actually holds
+#[tokio::test]
+async fn test_asset_balance_query() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    assert_eq!(vault_asset_balance(&vault, &alice).await?.0, 0);
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    assert_eq!(vault_asset_balance(&vault, &alice).await?.0, 1000);
+
+    // Plain transfers aren't tracked but are part of the balance
+    ft_transfer(&usdt, &owner, vault.as_account(), 50).await?;
+    assert_eq!(vault_asset_balance(&vault, &alice).await?.0, 1050);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1000);
+
+    Ok(())
+}
+
+/// Test that the performance fee is only charged on gains above the high-water mark
+#[tokio::test]
+async fn test_performance_fee_high_water_mark() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let strategy = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_storage_deposit(&usdt, &strategy).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    vault_storage_deposit(&vault, &owner).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    // 20% of gains go to the owner
+    vault_set_performance_fee(&vault, &owner, 2000, &owner).await?;
+    vault_set_strategy(&vault, &owner, Some(&strategy)).await?;
+    vault_invest(&vault, &owner, 1000).await?;
+
+    // Gain of 100 above the mark: 20 assets worth of shares are minted as fee
+    vault_report_yield(&vault, &owner, 1100).await?;
+    let fee_shares = vault_balance_of(&vault, &owner, &owner).await?;
+    assert_eq!(fee_shares.0, 18);
+    let fee_assets = vault_assets_of(&vault, &owner, &owner).await?;
+    assert!(fee_assets.0 >= 19 && fee_assets.0 <= 20);
+    let mark = vault_high_water_mark(&vault, &owner).await?;
+
+    // A loss takes no fee and leaves the mark in place
+    vault_report_yield(&vault, &owner, 1050).await?;
+    assert_eq!(vault_balance_of(&vault, &owner, &owner).await?.0, 18);
+    assert_eq!(vault_high_water_mark(&vault, &owner).await?, mark);
+
+    // The recovery is only charged on the 50 gained above the previous high
+    vault_report_yield(&vault, &owner, 1150).await?;
+    assert_eq!(vault_balance_of(&vault, &owner, &owner).await?.0, 26);
+    assert!(vault_high_water_mark(&vault, &owner).await?.0 > mark.0);
+
+    Ok(())
+}
+
+/// Test the performance fee is priced against the vault's whole value, not only the asset
+/// it tracks, while funds sit in the strategy and the basket
+#[tokio::test]
+async fn test_performance_fee_with_invested_and_basket_funds(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let dai_owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+    let strategy = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let dai = deploy_and_init_mock_ft(&dai_owner, "Dai", "DAI", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "Basket Vault", "vBSK", 0).await?;
+
+    // Setup accounts, the vault holds DAI too
+    ft_storage_deposit(&dai, vault.as_account()).await?;
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_storage_deposit(&usdt, &strategy).await?;
+    ft_storage_deposit(&dai, &bob).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    vault_storage_deposit(&vault, &bob).await?;
+    vault_storage_deposit(&vault, &owner).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer(&dai, &dai_owner, &bob, 10000).await?;
+
+    // 1 DAI is worth 2 USDT, half the vault's 2000 of value sits in DAI
+    vault_add_basket_asset(&vault, &owner, &dai, 2 * 10u128.pow(24)).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    ft_transfer_call_deposit(&dai, &vault, &bob, 500, None, None, None, None, None).await?;
+
+    // 20% of gains go to the owner
+    vault_set_performance_fee(&vault, &owner, 2000, &owner).await?;
+    vault_set_strategy(&vault, &owner, Some(&strategy)).await?;
+    vault_invest(&vault, &owner, 1000).await?;
+
+    // A gain of 100 charges 20 of the 2100 of value: 20 * 1999 / 2081 shares
+    vault_report_yield(&vault, &owner, 1100).await?;
+    assert_eq!(vault_total_assets(&vault, &owner).await?.0, 2100);
+    assert_eq!(vault_balance_of(&vault, &owner, &owner).await?.0, 19);
+    let fee_assets = vault_assets_of(&vault, &owner, &owner).await?;
+    assert!(fee_assets.0 >= 19 && fee_assets.0 <= 20);
+
+    Ok(())
+}
+
+/// Test that the performance fee waits for an unregistered fee recipient instead of panicking
+#[tokio::test]
+async fn test_performance_fee_unregistered_recipient() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+    let strategy = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_storage_deposit(&usdt, &strategy).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    vault_storage_deposit(&vault, &bob).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    // 20% of gains go to bob, who then leaves
+    vault_set_performance_fee(&vault, &owner, 2000, &bob).await?;
+    vault_set_strategy(&vault, &owner, Some(&strategy)).await?;
+    vault_invest(&vault, &owner, 1000).await?;
+    let mark = vault_high_water_mark(&vault, &owner).await?;
+    assert!(vault_storage_unregister(&vault, &bob, false).await?);
+
+    // The gain is booked without a fee and the mark stays where it was
+    vault_report_yield(&vault, &owner, 1100).await?;
+    assert_eq!(vault_total_assets(&vault, &owner).await?.0, 1100);
+    assert_eq!(vault_high_water_mark(&vault, &owner).await?, mark);
+
+    // Once bob is back the next gain charges the fee on everything above the old mark
+    vault_storage_deposit(&vault, &bob).await?;
+    vault_report_yield(&vault, &owner, 1150).await?;
+    assert_eq!(vault_balance_of(&vault, &owner, &bob).await?.0, 26);
+    assert!(vault_high_water_mark(&vault, &owner).await?.0 > mark.0);
+
+    Ok(())
+}
+
+/// Test that the TWAP stays within the range of observed prices
+#[tokio::test]
+async fn test_twap_within_observed_prices() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    let mut prices = Vec::new();
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    prices.push(vault_price_per_share(&vault, &alice).await?.0);
+    worker.fast_forward(10).await?;
+
+    // Donations raise the price step by step
+    for donation in [100, 300, 50] {
+        ft_transfer_call_deposit(
+            &usdt,
+            &vault,
+            &alice,
+            donation,
+            None,
+            None,
+            None,
+            None,
+            Some(true),
+        )
+        .await?;
+        prices.push(vault_price_per_share(&vault, &alice).await?.0);
+        worker.fast_forward(10).await?;
+    }
+
+    let min = *prices.iter().min().unwrap();
+    let max = *prices.iter().max().unwrap();
+    assert!(min < max);
+
+    let twap = vault_twap(&vault, &alice, u64::MAX).await?;
+    assert!(
+        twap.0 >= min && twap.0 <= max,
+        "TWAP {} outside [{}, {}]",
+        twap.0,
+        min,
+        max
+    );
+
+    // Lags behind the latest price after a jump
+    assert!(twap.0 < max);
+
+    Ok(())
+}
+
+/// Test that the estimated APY annualizes a known price gain over the elapsed window
+#[tokio::test]
+async fn test_estimated_apy_from_price_gain() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(10_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 2_000_000).await?;
+
+    assert_eq!(vault_estimated_apy_bps(&vault, &alice, u64::MAX).await?, 0);
+
+    ft_transfer_call_deposit(
+        &usdt, &vault, &alice, 1_000_000, None, None, None, None, None,
+    )
+    .await?;
+    let start_ns = worker.view_block().await?.timestamp();
+
+    // A single observation has no return yet
+    assert_eq!(vault_estimated_apy_bps(&vault, &alice, u64::MAX).await?, 0);
+
+    worker.fast_forward(200).await?;
+
+    // A 10% donation raises the price per share
+    ft_transfer_call_deposit(
+        &usdt,
+        &vault,
+        &alice,
+        100_000,
+        None,
+        None,
+        None,
+        None,
+        Some(true),
+    )
+    .await?;
+    let apy = vault_estimated_apy_bps(&vault, &alice, u64::MAX).await?;
+    let elapsed_ns = worker.view_block().await?.timestamp() - start_ns;
+
+    let year_ns = 365 * 24 * 60 * 60 * 1_000_000_000u128;
+    let expected = (1_000 * year_ns / elapsed_ns as u128) as i64;
+    assert!(
+        (apy - expected).abs() <= expected / 20,
+        "APY {} too far from {}",
+        apy,
+        expected
+    );
+
+    Ok(())
+}
+
+/// Test redeeming from a wNEAR vault straight to native NEAR
+#[tokio::test]
+async fn test_redeem_unwrap_to_native_near() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let wnear = deploy_and_init_mock_wnear(&owner).await?;
+    let vault = deploy_and_init_vault_with_config(
+        &owner,
+        &wnear,
+        "wNEAR Vault",
+        "vwNEAR",
+        0,
+        json!({ "is_wrap_near": true }),
+    )
+    .await?;
+
+    // Setup accounts
+    near_deposit(&wnear, &alice, NearToken::from_near(2)).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+
+    let one_near = NearToken::from_near(1).as_yoctonear();
+    ft_transfer_call_deposit(
+        &wnear, &vault, &alice, one_near, None, None, None, None, None,
+    )
+    .await?;
+
+    let bob_before = bob.view_account().await?.balance;
+
+    let outcome = vault_redeem_unwrap(&vault, &alice, one_near / 2, Some(&bob)).await?;
+    assert!(!outcome.rolled_back);
+    assert_eq!(outcome.assets.0, one_near / 2);
+
+    // Bob received native NEAR, the vault's wNEAR went down
+    let bob_after = bob.view_account().await?.balance;
+    assert_eq!(
+        bob_after.as_yoctonear() - bob_before.as_yoctonear(),
+        one_near / 2
+    );
+    assert_eq!(
+        ft_balance_of(&wnear, vault.as_account()).await?,
+        one_near / 2
+    );
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, one_near / 2);
+
+    Ok(())
+}
+
+/// Test unwrapped NEAR a receiver can't take goes to the owner instead of staying on the vault
+#[tokio::test]
+async fn test_redeem_unwrap_to_missing_receiver() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let wnear = deploy_and_init_mock_wnear(&owner).await?;
+    let vault = deploy_and_init_vault_with_config(
+        &owner,
+        &wnear,
+        "wNEAR Vault",
+        "vwNEAR",
+        0,
+        json!({ "is_wrap_near": true }),
+    )
+    .await?;
+
+    // Setup accounts
+    near_deposit(&wnear, &alice, NearToken::from_near(2)).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+
+    let one_near = NearToken::from_near(1).as_yoctonear();
+    ft_transfer_call_deposit(
+        &wnear, &vault, &alice, one_near, None, None, None, None, None,
+    )
+    .await?;
+
+    // Bob no longer exists, the NEAR transfer to him fails
+    bob.clone()
+        .delete_account(owner.id())
+        .await?
+        .into_result()?;
+
+    let alice_before = alice.view_account().await?.balance.as_yoctonear();
+    let outcome = vault_redeem_unwrap(&vault, &alice, one_near / 2, Some(&bob)).await?;
+    assert!(!outcome.rolled_back);
+
+    // Alice got the NEAR back, less the gas she paid
+    let alice_after = alice.view_account().await?.balance.as_yoctonear();
+    assert!(alice_after > alice_before + one_near / 2 - one_near / 10);
+
+    Ok(())
+}
+
+/// Test batch balance views keep the requested order and report zeros for non-holders
+#[tokio::test]
+async fn test_batch_balance_views() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+    let carol = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts, carol never deposits
+    for account in [&alice, &bob] {
+        ft_storage_deposit(&usdt, account).await?;
+        vault_storage_deposit(&vault, account).await?;
+        ft_transfer(&usdt, &owner, account, 10000).await?;
+    }
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &bob, 500, None, None, None, None, None).await?;
+
+    let accounts = [&bob, &carol, &alice];
+    let balances = vault_ft_balances_of(&vault, &owner, &accounts).await?;
+    assert_eq!(
+        balances,
+        vec![
+            vault_balance_of(&vault, &owner, &bob).await?,
+            U128(0),
+            vault_balance_of(&vault, &owner, &alice).await?,
+        ]
+    );
+
+    let assets = vault_assets_of_many(&vault, &owner, &accounts).await?;
+    assert_eq!(
+        assets,
+        vec![
+            vault_assets_of(&vault, &owner, &bob).await?,
+            U128(0),
+            vault_assets_of(&vault, &owner, &alice).await?,
+        ]
+    );
+
+    Ok(())
+}
+
+/// Test a redeem liquidating the strategy needs the gas of the whole chain, and fails before
+/// any share is burned without it
+#[tokio::test]
+async fn test_redeem_from_strategy_checks_gas() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+    let strategy = deploy_and_init_mock_strategy(&owner, &usdt, &vault, 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_storage_deposit(&usdt, strategy.as_account()).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    // Only 100 stay idle
+    vault_set_strategy(&vault, &owner, Some(strategy.as_account())).await?;
+    vault_invest(&vault, &owner, 900).await?;
+
+    // Enough for a plain transfer, not for the liquidation ahead of it
+    let err = vault_redeem(&vault, &alice, 500, None, None)
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Insufficient gas attached"));
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1000);
+    assert_eq!(vault_deployed_assets(&vault, &alice).await?.0, 900);
+
+    let outcome = vault_redeem_with_max_loss(&vault, &alice, 500, 0).await?;
+    assert!(!outcome.rolled_back);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 9500);
+    assert_eq!(vault_deployed_assets(&vault, &alice).await?.0, 500);
+
+    Ok(())
+}
+
+/// Test that a redeem liquidating the strategy is rolled back when slippage exceeds the
+/// tolerated loss
+#[tokio::test]
+async fn test_redeem_max_loss_against_strategy_slippage() -> Result<(), Box<dyn std::error::Error>>
+{
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+    // Unwinding the strategy loses 10%
+    let strategy = deploy_and_init_mock_strategy(&owner, &usdt, &vault, 1000).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_storage_deposit(&usdt, strategy.as_account()).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    // Only 100 stay idle
+    vault_set_strategy(&vault, &owner, Some(strategy.as_account())).await?;
+    vault_invest(&vault, &owner, 900).await?;
+
+    // 500 quoted, 400 pulled from the strategy which returns 360: an 8% loss
+    let outcome = vault_redeem_with_max_loss(&vault, &alice, 500, 500).await?;
+    assert!(outcome.rolled_back);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1000);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 9000);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1000);
+
+    // The 360 returned stay idle, so only 40 more are pulled next time: 36 come back
+    assert_eq!(vault_deployed_assets(&vault, &alice).await?.0, 540);
+    let outcome = vault_redeem_with_max_loss(&vault, &alice, 500, 1000).await?;
+    assert!(!outcome.rolled_back);
+    assert_eq!(outcome.assets.0, 496);
+    assert_eq!(outcome.shares.0, 500);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 9496);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 500);
+
+    Ok(())
+}
+
+/// Test a failed transfer after a lossy liquidation only gives back the shares the realized
+/// assets are worth, so the redeemer keeps the loss
+#[tokio::test]
+async fn test_strategy_withdrawal_rollback_keeps_loss_with_redeemer(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+    // Unwinding the strategy loses 10%
+    let strategy = deploy_and_init_mock_strategy(&owner, &usdt, &vault, 1000).await?;
+
+    // Setup accounts, bob isn't registered with the asset
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_storage_deposit(&usdt, strategy.as_account()).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    vault_set_strategy(&vault, &owner, Some(strategy.as_account())).await?;
+    vault_invest(&vault, &owner, 900).await?;
+
+    // 500 quoted, 460 realized, then the transfer to bob fails
+    let outcome: WithdrawOutcome = alice
+        .call(vault.id(), "redeem")
+        .args_json(json!({
+            "shares": "500",
+            "receiver_id": bob.id(),
+            "max_loss_bps": 1000,
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(300))
+        .transact()
+        .await?
+        .into_result()?
+        .json()?;
+    assert!(outcome.rolled_back);
+
+    // 460 of the 500 shares come back, worth what the vault still holds for them
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 960);
+    assert_eq!(vault_total_supply(&vault, &alice).await?.0, 960);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 960);
+
+    Ok(())
+}
+
+/// Test the early exit fee is taken in shares on redeem and withdraw, follows transferred shares
+/// and disappears once it has decayed
+#[tokio::test]
+async fn test_early_exit_fee() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts, the owner collects the fees
+    for account in [&owner, &alice, &bob] {
+        vault_storage_deposit(&vault, account).await?;
+    }
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    // A fee recipient is required
+    assert!(vault_set_exit_fee(&vault, &owner, 1000, 1).await.is_err());
+    vault_set_performance_fee(&vault, &owner, 0, &owner).await?;
+
+    // Decaying so slowly the fee stays just under its maximum
+    vault_set_exit_fee(&vault, &owner, 1000, 10u64.pow(18)).await?;
+    assert_eq!(vault_exit_fee_bps_of(&vault, &alice, &alice).await?, 999);
+
+    // 10 of the 100 shares redeemed go to the recipient
+    let outcome = vault_redeem(&vault, &alice, 100, None, None).await?;
+    assert!(!outcome.rolled_back);
+    assert_eq!(outcome.shares.0, 90);
+    assert_eq!(outcome.assets.0, 90);
+    assert_eq!(vault_balance_of(&vault, &alice, &owner).await?.0, 10);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 900);
+
+    // Withdrawing charges the fee on top of the shares burned
+    let outcome = vault_withdraw(&vault, &alice, 90, None, None).await?;
+    assert_eq!(outcome.shares.0, 90);
+    assert_eq!(vault_balance_of(&vault, &alice, &owner).await?.0, 20);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 800);
+
+    // Transferred shares keep paying the fee
+    vault_share_transfer(&vault, &alice, &bob, 100, NearToken::from_yoctonear(1)).await?;
+    assert_eq!(vault_exit_fee_bps_of(&vault, &bob, &bob).await?, 999);
+
+    // Half way through the decay window, the few blocks the calls take barely move the fee
+    worker.fast_forward(1000).await?;
+    let state = vault_account_state_of(&vault, &alice, &alice).await?;
+    let entry_ns: u64 = state["entry_ns"].as_str().unwrap().parse()?;
+    let held_ns = worker.view_block().await?.timestamp() - entry_ns;
+    vault_set_exit_fee(&vault, &owner, 1000, 2 * held_ns).await?;
+    let outcome = vault_redeem(&vault, &alice, 100, None, None).await?;
+    assert_eq!(outcome.shares.0, 95);
+    assert_eq!(vault_balance_of(&vault, &alice, &owner).await?.0, 25);
+
+    // Fully decayed
+    vault_set_exit_fee(&vault, &owner, 1000, 1).await?;
+    assert_eq!(vault_exit_fee_bps_of(&vault, &alice, &alice).await?, 0);
+    let outcome = vault_redeem(&vault, &alice, 100, None, None).await?;
+    assert_eq!(outcome.shares.0, 100);
+    assert_eq!(vault_balance_of(&vault, &alice, &owner).await?.0, 25);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 500);
+
+    Ok(())
+}
+
+/// Test a transfer blends the entry time into the receiver's by shares, so dust can't reset it
+#[tokio::test]
+async fn test_entry_time_weighted_by_shares() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    for account in [&alice, &bob] {
+        vault_storage_deposit(&vault, account).await?;
+        ft_storage_deposit(&usdt, account).await?;
+        ft_transfer(&usdt, &owner, account, 10000).await?;
+    }
+    ft_transfer_call_deposit(&usdt, &vault, &bob, 1000, None, None, None, None, None).await?;
+    worker.fast_forward(100).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    let state = vault_account_state_of(&vault, &bob, &bob).await?;
+    let bob_entry: u64 = state["entry_ns"].as_str().unwrap().parse()?;
+    let state = vault_account_state_of(&vault, &alice, &alice).await?;
+    let alice_entry: u64 = state["entry_ns"].as_str().unwrap().parse()?;
+    assert!(alice_entry > bob_entry);
+
+    // One fresh share moves bob's 1000 old ones a thousandth of the way
+    vault_share_transfer(&vault, &alice, &bob, 1, NearToken::from_yoctonear(1)).await?;
+    let state = vault_account_state_of(&vault, &bob, &bob).await?;
+    let blended: u64 = state["entry_ns"].as_str().unwrap().parse()?;
+    assert!(blended > bob_entry);
+    assert!(blended - bob_entry <= (alice_entry - bob_entry) / 1000 + 1);
+
+    Ok(())
+}
+
+/// Test a redeem or withdraw reverts before burning anything when the exit fee was raised above
+/// the caller's cap
+#[tokio::test]
+async fn test_max_fee_bps_cap() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts, the owner collects the fees
+    for account in [&owner, &alice] {
+        vault_storage_deposit(&vault, account).await?;
+    }
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    vault_set_performance_fee(&vault, &owner, 0, &owner).await?;
+
+    // Previewed at a 1% fee, then the operator raises it to 10%
+    vault_set_exit_fee(&vault, &owner, 100, 10u64.pow(18)).await?;
+    assert_eq!(vault_exit_fee_bps_of(&vault, &alice, &alice).await?, 99);
+    vault_set_exit_fee(&vault, &owner, 1000, 10u64.pow(18)).await?;
+
+    for method in ["redeem", "withdraw"] {
+        let result = vault_exit_with_max_fee(&vault, &alice, method, 100, 100).await?;
+        assert!(format!("{:?}", result.failures()).contains("Fee exceeds caller limit"));
+    }
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1000);
+    assert_eq!(vault_balance_of(&vault, &alice, &owner).await?.0, 0);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 9000);
+
+    // Within the cap the redeem goes through with the fee charged
+    vault_exit_with_max_fee(&vault, &alice, "redeem", 100, 1000)
+        .await?
+        .into_result()?;
+    assert_eq!(vault_balance_of(&vault, &alice, &owner).await?.0, 10);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 900);
+
+    Ok(())
+}
+
+/// Test a fee-exempt account redeems without paying the exit fee a normal account pays
+#[tokio::test]
+async fn test_fee_exempt_account() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts, the owner collects the fees
+    for account in [&owner, &alice, &bob] {
+        vault_storage_deposit(&vault, account).await?;
+    }
+    for account in [&alice, &bob] {
+        ft_storage_deposit(&usdt, account).await?;
+        ft_transfer(&usdt, &owner, account, 10000).await?;
+        ft_transfer_call_deposit(&usdt, &vault, account, 1000, None, None, None, None, None)
+            .await?;
+    }
+    vault_set_performance_fee(&vault, &owner, 0, &owner).await?;
+    vault_set_exit_fee(&vault, &owner, 1000, 10u64.pow(18)).await?;
+
+    // Only the owner manages the allowlist
+    assert!(vault_set_fee_exempt(&vault, &alice, &alice, true)
+        .await
+        .is_err());
+    vault_set_fee_exempt(&vault, &owner, &bob, true).await?;
+    assert_eq!(vault_exit_fee_bps_of(&vault, &bob, &bob).await?, 0);
+    assert_eq!(vault_exit_fee_bps_of(&vault, &alice, &alice).await?, 999);
+
+    // Alice pays 10 of her 100 shares, bob redeems all of his
+    let outcome = vault_redeem(&vault, &alice, 100, None, None).await?;
+    assert_eq!(outcome.shares.0, 90);
+    assert_eq!(vault_balance_of(&vault, &alice, &owner).await?.0, 10);
+
+    let outcome = vault_redeem(&vault, &bob, 100, None, None).await?;
+    assert!(!outcome.rolled_back);
+    assert_eq!(outcome.shares.0, 100);
+    let outcome = vault_withdraw(&vault, &bob, 50, None, None).await?;
+    assert!(!outcome.rolled_back);
+    assert_eq!(vault_balance_of(&vault, &alice, &owner).await?.0, 10);
+
+    // Removed from the allowlist, bob pays again
+    vault_set_fee_exempt(&vault, &owner, &bob, false).await?;
+    assert_eq!(vault_exit_fee_bps_of(&vault, &bob, &bob).await?, 999);
+    let outcome = vault_redeem(&vault, &bob, 100, None, None).await?;
+    assert_eq!(outcome.shares.0, 90);
+    assert_eq!(vault_balance_of(&vault, &alice, &owner).await?.0, 20);
+
+    Ok(())
+}
+
+/// Test a deposit logs exactly one vault deposit and one share mint for the same shares
+#[tokio::test]
+async fn test_deposit_events() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    let result = ft_transfer_call_with_msg(&usdt, &vault, &alice, 1000, "{}").await?;
+    let logs: Vec<String> = result.logs().into_iter().map(String::from).collect();
+    result.into_result()?;
+
+    let events = parse_vault_events(&logs);
+    let deposits: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            VaultEvent::VaultDeposit(deposit) => Some(deposit),
+            _ => None,
+        })
+        .collect();
+    let mints: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            VaultEvent::FtMint(mint) => Some(mint),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(deposits.len(), 1);
+    assert_eq!(mints.len(), 1);
+    assert_eq!(deposits[0].owner_id.as_str(), alice.id().as_str());
+    assert_eq!(deposits[0].assets.0, 1000);
+    assert_eq!(mints[0].owner_id.as_str(), alice.id().as_str());
+    assert_eq!(mints[0].amount, deposits[0].shares);
+    assert_eq!(
+        mints[0].amount,
+        vault_balance_of(&vault, &alice, &alice).await?
+    );
+
+    Ok(())
+}
+
+/// Test the owner registers the vault with its asset, after which deposits and withdrawals go
+/// through, and registering again only refunds the deposit
+#[tokio::test]
+async fn test_register_with_asset() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault =
+        deploy_and_init_vault_unregistered(&owner, &usdt, "USDT Vault", "vUSDT", 0, json!({}))
+            .await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    // The asset refuses transfers to the unregistered vault
+    let result = ft_transfer_call_with_msg(&usdt, &vault, &alice, 1000, "").await?;
+    assert!(result.is_failure());
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 10000);
+
+    // Only the owner registers it
+    assert!(
+        vault_register_with_asset(&vault, &alice, NearToken::from_near(1))
+            .await
+            .is_err()
+    );
+    // Only the asset's minimum is kept, the rest of the deposit goes back to the owner
+    let before = owner.view_account().await?.balance;
+    let storage_balance =
+        vault_register_with_asset(&vault, &owner, NearToken::from_near(1)).await?;
+    assert_ne!(storage_balance["total"], "0");
+    let spent = before.saturating_sub(owner.view_account().await?.balance);
+    assert!(spent < NearToken::from_millinear(100));
+
+    // Already registered, the deposit comes back and only gas is spent
+    let before = owner.view_account().await?.balance;
+    let again = vault_register_with_asset(&vault, &owner, NearToken::from_near(1)).await?;
+    assert_eq!(again, storage_balance);
+    let spent = before.saturating_sub(owner.view_account().await?.balance);
+    assert!(spent < NearToken::from_millinear(100));
+
+    // The vault now takes deposits and pays withdrawals out
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    let outcome = vault_redeem(&vault, &alice, 400, None, None).await?;
+    assert!(!outcome.rolled_back);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 9400);
+
+    Ok(())
+}
+
+/// Test a vault initialized without vault events still logs the NEP-141 share mint
+#[tokio::test]
+async fn test_deposit_without_vault_events() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault_with_config(
+        &owner,
+        &usdt,
+        "USDT Vault",
+        "vUSDT",
+        0,
+        json!({ "emit_vault_events": false }),
+    )
+    .await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    let result = ft_transfer_call_with_msg(&usdt, &vault, &alice, 1000, "{}").await?;
+    let logs: Vec<String> = result.logs().into_iter().map(String::from).collect();
+    result.into_result()?;
+
+    let events = parse_vault_events(&logs);
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, VaultEvent::FtMint(mint) if mint.amount.0 > 0)));
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, VaultEvent::VaultDeposit(_))));
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, VaultEvent::VaultTvlUpdated(_))));
+
+    Ok(())
+}
+
+/// Test deposits and redeems log the vault's total assets, supply and price after the operation
+#[tokio::test]
+async fn test_tvl_updated_events() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    let deposit = ft_transfer_call_with_msg(&usdt, &vault, &alice, 1000, "").await?;
+    let redeem = alice
+        .call(vault.id(), "redeem")
+        .args_json(json!({ "shares": "400" }))
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(100))
+        .transact()
+        .await?;
+
+    // One whole share is 10^6 units, worth (total_assets + 1) / total_supply of them
+    for (result, total_assets, total_supply, price_per_share) in [
+        (deposit, 1000, 1000, 1_001_000),
+        (redeem, 600, 600, 1_001_667),
+    ] {
+        let logs: Vec<String> = result.logs().into_iter().map(String::from).collect();
+        result.into_result()?;
+
+        // The last update carries the state the operation left the vault in
+        let update = parse_vault_events(&logs)
+            .into_iter()
+            .filter_map(|event| match event {
+                VaultEvent::VaultTvlUpdated(update) => Some(update),
+                _ => None,
+            })
+            .next_back()
+            .expect("No TVL update");
+        assert_eq!(update.total_assets.0, total_assets);
+        assert_eq!(update.total_supply.0, total_supply);
+        assert!(update.timestamp.0 > 0);
+        assert_eq!(update.price_per_share.0, price_per_share);
+    }
+    assert_eq!(vault_price_per_share(&vault, &alice).await?.0, 1_001_667);
+
+    Ok(())
+}
+
+/// Test a two token basket: deposits of either token are valued in the asset and redeeming in
+/// kind pays both tokens pro rata
+#[tokio::test]
+async fn test_basket_deposit_and_redeem_in_kind() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let dai_owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let dai = deploy_and_init_mock_ft(&dai_owner, "Dai", "DAI", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "Basket Vault", "vBSK", 0).await?;
+
+    // Setup accounts, the vault holds DAI too
+    ft_storage_deposit(&dai, vault.as_account()).await?;
+    for account in [&alice, &bob] {
+        ft_storage_deposit(&usdt, account).await?;
+        ft_storage_deposit(&dai, account).await?;
+        vault_storage_deposit(&vault, account).await?;
+    }
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer(&dai, &dai_owner, &bob, 10000).await?;
+
+    // 1 DAI is worth 2 USDT
+    vault_add_basket_asset(&vault, &owner, &dai, 2 * 10u128.pow(24)).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    let unused =
+        ft_transfer_call_deposit(&dai, &vault, &bob, 500, None, None, None, None, None).await?;
+    assert_eq!(unused.0, 0);
+    assert_eq!(vault_balance_of(&vault, &bob, &bob).await?.0, 999);
+    assert_eq!(vault_total_assets(&vault, &bob).await?.0, 2000);
+
+    // Half the value is in DAI, bob's 999 of 1999 shares get 999/1999 of each token: the 498
+    // worth of DAI paid with 498 of the shares
+    let outcome = vault_redeem_in_kind(&vault, &bob, 999).await?;
+    assert!(!outcome.rolled_back);
+    assert_eq!(outcome.assets.0, 499);
+    assert_eq!(outcome.shares.0, 501);
+    assert_eq!(ft_balance_of(&usdt, &bob).await?, 499);
+    assert_eq!(ft_balance_of(&dai, &bob).await?, 9500 + 249);
+    assert_eq!(vault_balance_of(&vault, &bob, &bob).await?.0, 0);
+    assert_eq!(vault_total_supply(&vault, &bob).await?.0, 1000);
+
+    Ok(())
+}
+
+/// Test deposits with an empty or `{}` message mint shares to the sender
+#[tokio::test]
+async fn test_deposit_with_empty_message() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    let result = ft_transfer_call_with_msg(&usdt, &vault, &alice, 1000, "").await?;
+    let used: U128 = result.into_result()?.json()?;
+    assert_eq!(used.0, 1000);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1000);
+
+    let result = ft_transfer_call_with_msg(&usdt, &vault, &alice, 500, "{}").await?;
+    let used: U128 = result.into_result()?.json()?;
+    assert_eq!(used.0, 500);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1499);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 8500);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1500);
+
+    Ok(())
+}
+
+/// Test a transfer whose message was meant for another contract is refunded with a rejection
+/// event instead of minting
+#[tokio::test]
+async fn test_foreign_message_refunded() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    // Valid JSON, none of it a deposit field
+    let msg = json!({ "force": 1, "actions": [{ "pool_id": 7 }] }).to_string();
+    let result = ft_transfer_call_with_msg(&usdt, &vault, &alice, 1000, &msg).await?;
+    assert!(result.is_success());
+
+    let logs: Vec<String> = result.logs().into_iter().map(String::from).collect();
+    let rejected: Vec<_> = parse_vault_events(&logs)
+        .into_iter()
+        .filter_map(|event| match event {
+            VaultEvent::VaultDepositRejected(rejected) => Some(rejected),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(rejected.len(), 1);
+    assert_eq!(rejected[0].sender_id.as_str(), alice.id().as_str());
+    assert_eq!(rejected[0].amount.0, 1000);
+    assert_eq!(rejected[0].reason, "invalid deposit message");
+
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 10000);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 0);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 0);
+
+    // The same with the discriminant deposits
+    let msg = json!({ "action": "deposit" }).to_string();
+    ft_transfer_call_with_msg(&usdt, &vault, &alice, 1000, &msg)
+        .await?
+        .into_result()?;
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1000);
+
+    Ok(())
+}
+
+/// Test a deposit whose message isn't valid JSON is refunded with the reason logged, rather than
+/// made without its options
+#[tokio::test]
+async fn test_malformed_message_refunded() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    // A typo in the slippage option: unquoted key and trailing comma
+    let msg = r#"{"action": "deposit", min_shares: "990",}"#;
+    let result = ft_transfer_call_with_msg(&usdt, &vault, &alice, 1000, msg).await?;
+    assert!(result.is_success());
+
+    let logs: Vec<String> = result.logs().into_iter().map(String::from).collect();
+    let rejected: Vec<_> = parse_vault_events(&logs)
+        .into_iter()
+        .filter_map(|event| match event {
+            VaultEvent::VaultDepositRejected(rejected) => Some(rejected),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(rejected.len(), 1);
+    assert_eq!(rejected[0].sender_id.as_str(), alice.id().as_str());
+    assert_eq!(rejected[0].amount.0, 1000);
+    assert_eq!(rejected[0].reason, "invalid deposit message");
+
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 10000);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 0);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 0);
+
+    // An empty message is still a plain deposit
+    ft_transfer_call_with_msg(&usdt, &vault, &alice, 1000, "")
+        .await?
+        .into_result()?;
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1000);
+
+    Ok(())
+}
+
+/// Test the price per share can be quoted at a requested precision
+#[tokio::test]
+async fn test_price_per_share_scaled() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    // Uneven price after a donation
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 3000, None, None, None, None, None).await?;
+    ft_transfer_call_deposit(
+        &usdt,
+        &vault,
+        &alice,
+        1000,
+        None,
+        None,
+        None,
+        None,
+        Some(true),
+    )
+    .await?;
+
+    let total_assets = vault_total_assets(&vault, &alice).await?.0;
+    let total_supply = vault_total_supply(&vault, &alice).await?.0;
+    let expected = total_assets * 10u128.pow(18) / total_supply;
+
+    // Only the virtual offset of one asset unit separates the two
+    let scaled = vault_price_per_share_scaled(&vault, &alice, 18).await?.0;
+    assert!(scaled >= expected);
+    assert!(scaled - expected <= 10u128.pow(18) / total_supply + 1);
+
+    // The default precision matches the share decimals
+    assert_eq!(
+        vault_price_per_share_scaled(&vault, &alice, 6).await?,
+        vault_price_per_share(&vault, &alice).await?
+    );
+    assert!(vault_price_per_share_scaled(&vault, &alice, 39)
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+/// Test the exchange rate reproduces the conversions exactly, before and after yield
+#[tokio::test]
+async fn test_exchange_rate_matches_conversions() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 3).await?;
+
+    // Empty, the shares carry the extra decimals
+    assert_eq!(vault_exchange_rate(&vault, &alice).await?, (1, 1000));
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    ft_transfer_call_deposit(
+        &usdt,
+        &vault,
+        &alice,
+        337,
+        None,
+        None,
+        None,
+        None,
+        Some(true),
+    )
+    .await?;
+
+    let (numerator, denominator) = vault_exchange_rate(&vault, &alice).await?;
+    assert_eq!((numerator, denominator), (1338, 1_000_000));
+    for shares in [1u128, 999, 1000, 123_457, 1_000_000] {
+        assert_eq!(
+            vault_convert_to_assets(&vault, &alice, shares).await?.0,
+            shares * numerator / denominator,
+            "shares: {}",
+            shares
+        );
+    }
+
+    Ok(())
+}
+
+/// Test the withdraw hook is told how each withdrawal settled and can't revert it
+#[tokio::test]
+async fn test_withdraw_hook_notified() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+    let hook = deploy_mock_hook(&owner).await?;
+
+    // Setup accounts, bob can't receive USDT
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    vault_set_withdraw_hook(&vault, &owner, Some(hook.as_account())).await?;
+
+    let outcome = vault_redeem(&vault, &alice, 100, None, None).await?;
+    assert!(!outcome.rolled_back);
+    let outcome = vault_redeem(&vault, &alice, 100, Some(&bob), None).await?;
+    assert!(outcome.rolled_back);
+
+    let calls = mock_hook_calls(&hook).await?;
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0]["owner"], alice.id().to_string());
+    assert_eq!(calls[0]["receiver"], alice.id().to_string());
+    assert_eq!(calls[0]["shares"], "100");
+    assert_eq!(calls[0]["assets"], "100");
+    assert_eq!(calls[0]["success"], true);
+    assert_eq!(calls[1]["receiver"], bob.id().to_string());
+    assert_eq!(calls[1]["shares"], "100");
+    assert_eq!(calls[1]["success"], false);
+
+    // A hook that fails doesn't affect the withdrawal
+    vault_set_withdraw_hook(&vault, &owner, Some(&bob)).await?;
+    let outcome = vault_redeem(&vault, &alice, 100, None, None).await?;
+    assert!(!outcome.rolled_back);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 800);
+
+    Ok(())
+}
+
+/// Test the asset decimals are read off the asset and a mismatch with the share decimals is logged
+#[tokio::test]
+async fn test_sync_asset_decimals() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+
+    let wnear =
+        deploy_and_init_mock_ft_with_decimals(&owner, "Wrapped NEAR", "wNEAR", None, 24).await?;
+    // Shares have 6 decimals
+    let vault = deploy_and_init_vault(&owner, &wnear, "wNEAR Vault", "vwNEAR", 0).await?;
+
+    assert_eq!(vault_asset_decimals(&vault, &owner).await?, None);
+
+    let result = vault_sync_asset_decimals(&vault, &owner).await?;
+    let logs: Vec<String> = result.logs().into_iter().map(String::from).collect();
+    result.into_result()?;
+
+    assert_eq!(vault_asset_decimals(&vault, &owner).await?, Some(24));
+    let mismatches: Vec<_> = parse_vault_events(&logs)
+        .into_iter()
+        .filter_map(|event| match event {
+            VaultEvent::VaultDecimalsMismatch(mismatch) => Some(mismatch),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].share_decimals, 6);
+    assert_eq!(mismatches[0].asset_decimals, 24);
+
+    Ok(())
+}
+
+/// Test a vault with 12 decimal shares over a 6 decimal asset scales deposits by 10^6
+#[tokio::test]
+async fn test_share_decimals_offset() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    // Shares have 6 + 6 decimals
+    let vault = deploy_and_init_vault_with_config(
+        &owner,
+        &usdt,
+        "USDT Vault",
+        "vUSDT",
+        6,
+        json!({ "asset_decimals": 6 }),
+    )
+    .await?;
+
+    assert_eq!(vault_asset_decimals(&vault, &owner).await?, Some(6));
+    assert_eq!(vault_share_decimals(&vault, &owner).await?, 12);
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1, None, None, None, None, None).await?;
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1_000_000);
+
+    // An offset that doesn't match the decimals is rejected
+    let err = deploy_and_init_vault_with_config(
+        &owner,
+        &usdt,
+        "USDT Vault",
+        "vUSDT",
+        6,
+        json!({ "asset_decimals": 8 }),
+    )
+    .await
+    .unwrap_err();
+    assert!(format!("{:?}", err).contains("extra_decimals must be the share decimals"));
+
+    Ok(())
+}
+
+/// Test the deposited assets follow deposits and shrink in proportion to the shares withdrawn
+#[tokio::test]
+async fn test_deposited_assets_basis() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts, bob can't receive USDT
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    assert_eq!(
+        vault_deposited_assets_of(&vault, &alice, &alice).await?.0,
+        0
+    );
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    assert_eq!(
+        vault_deposited_assets_of(&vault, &alice, &alice).await?.0,
+        1000
+    );
+
+    // A rolled back withdrawal leaves the basis untouched
+    let outcome = vault_redeem(&vault, &alice, 500, Some(&bob), None).await?;
+    assert!(outcome.rolled_back);
+    assert_eq!(
+        vault_deposited_assets_of(&vault, &alice, &alice).await?.0,
+        1000
+    );
+
+    vault_redeem(&vault, &alice, 500, None, None).await?;
+    assert_eq!(
+        vault_deposited_assets_of(&vault, &alice, &alice).await?.0,
+        500
+    );
+
+    Ok(())
+}
+
+/// Test a single transfer only deposits up to the per-transaction cap and gets the rest refunded
+#[tokio::test]
+async fn test_max_deposit_per_tx() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    vault_set_max_deposit_per_tx(&vault, &owner, Some(500)).await?;
+
+    let used =
+        ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    assert_eq!(used.0, 500);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 500);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 500);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 9500);
+
+    // The cap is per transfer, not per account
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 400, None, None, None, None, None).await?;
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 900);
+
+    Ok(())
+}
+
+/// Test the deposit quote matches the shares and assets a deposit actually credits
+#[tokio::test]
+async fn test_quote_deposit_matches_deposit() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    // A donation moves the rate off 1:1 so conversions round
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    ft_transfer_call_deposit(
+        &usdt,
+        &vault,
+        &alice,
+        37,
+        None,
+        None,
+        None,
+        None,
+        Some(true),
+    )
+    .await?;
+
+    let quote = vault_quote_deposit(&vault, &alice, 777).await?;
+    let shares_before = vault_balance_of(&vault, &alice, &alice).await?.0;
+    let used =
+        ft_transfer_call_deposit(&usdt, &vault, &alice, 777, None, None, None, None, None).await?;
+    let minted = vault_balance_of(&vault, &alice, &alice).await?.0 - shares_before;
+
+    assert_eq!(quote["shares"], minted.to_string());
+    assert_eq!(quote["used_assets"], used.0.to_string());
+    assert_eq!(quote["fee_assets"], "0");
+
+    Ok(())
+}
+
+/// Test the capped quote predicts the partial fill of a deposit running into the TVL cap and
+/// the per-transaction cap
+#[tokio::test]
+async fn test_quote_deposit_partial_fill() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    // Only 500 fit under the TVL cap
+    vault_set_total_assets_cap(&vault, &owner, Some(1500)).await?;
+    vault_set_max_deposit_per_tx(&vault, &owner, Some(800)).await?;
+
+    let quote = vault_quote_deposit(&vault, &alice, 2000).await?;
+    let shares_before = vault_balance_of(&vault, &alice, &alice).await?.0;
+    let used =
+        ft_transfer_call_deposit(&usdt, &vault, &alice, 2000, None, None, None, None, None).await?;
+    let minted = vault_balance_of(&vault, &alice, &alice).await?.0 - shares_before;
+
+    assert_eq!(quote["used_assets"], "500");
+    assert_eq!(quote["used_assets"], used.0.to_string());
+    assert_eq!(quote["shares"], minted.to_string());
+    assert_eq!(quote["refund"], "1500");
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 8500);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1500);
+
+    // A full vault refunds everything
+    let quote = vault_quote_deposit(&vault, &alice, 100).await?;
+    assert_eq!(quote["used_assets"], "0");
+    assert_eq!(quote["refund"], "100");
+
+    // Without the TVL cap the per-transaction cap cuts the deposit down
+    vault_set_total_assets_cap(&vault, &owner, None).await?;
+    let quote = vault_quote_deposit(&vault, &alice, 1000).await?;
+    let used =
+        ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    assert_eq!(quote["used_assets"], "800");
+    assert_eq!(quote["used_assets"], used.0.to_string());
+    assert_eq!(quote["refund"], "200");
+
+    // A paused vault refunds everything, whatever room the caps leave
+    vault_pause(&vault, &owner).await?;
+    let quote = vault_quote_deposit(&vault, &alice, 100).await?;
+    assert_eq!(quote["used_assets"], "0");
+    assert_eq!(quote["refund"], "100");
+    let used =
+        ft_transfer_call_deposit(&usdt, &vault, &alice, 100, None, None, None, None, None).await?;
+    assert_eq!(used.0, 0);
+
+    Ok(())
+}
+
+/// Test a deposit cut down by the TVL cap beyond the vault's slippage bound is refunded, even
+/// though the depositor set no min_shares
+#[tokio::test]
+async fn test_max_deposit_slippage_refunds() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    for account in [&alice, &bob] {
+        ft_storage_deposit(&usdt, account).await?;
+        vault_storage_deposit(&vault, account).await?;
+        ft_transfer(&usdt, &owner, account, 10000).await?;
+    }
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    // Only 500 of the next 1000 fit, minting 499 shares instead of 999
+    vault_set_total_assets_cap(&vault, &owner, Some(1500)).await?;
+    vault_set_max_deposit_slippage_bps(&vault, &owner, Some(1000)).await?;
+
+    let used =
+        ft_transfer_call_deposit(&usdt, &vault, &bob, 1000, None, None, None, None, None).await?;
+    assert_eq!(used.0, 0);
+    assert_eq!(ft_balance_of(&usdt, &bob).await?, 10000);
+    assert_eq!(vault_balance_of(&vault, &bob, &bob).await?.0, 0);
+
+    // A looser bound lets the partial fill through
+    vault_set_max_deposit_slippage_bps(&vault, &owner, Some(6000)).await?;
+    let used =
+        ft_transfer_call_deposit(&usdt, &vault, &bob, 1000, None, None, None, None, None).await?;
+    assert_eq!(used.0, 500);
+    assert_eq!(vault_balance_of(&vault, &bob, &bob).await?.0, 499);
+
+    Ok(())
+}
+
+/// Test the asset can only be migrated while the vault holds nothing
+#[tokio::test]
+async fn test_migrate_asset() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let new_owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let new_usdt =
+        deploy_and_init_mock_ft(&new_owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    // An empty vault can be repointed
+    let result = vault_migrate_asset(&vault, &owner, &new_usdt).await?;
+    let logs: Vec<String> = result.logs().into_iter().map(String::from).collect();
+    result.into_result()?;
+    assert_eq!(
+        vault_asset(&vault, &owner).await?,
+        new_usdt.id().to_string()
+    );
+    assert!(logs
+        .iter()
+        .any(|log| log.contains("\"event\":\"vault_asset_migrated\"")));
+
+    // Once it holds assets it can't
+    vault_migrate_asset(&vault, &owner, &usdt)
+        .await?
+        .into_result()?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    let result = vault_migrate_asset(&vault, &owner, &new_usdt).await?;
+    assert!(result.is_failure());
+    assert!(format!("{:?}", result.failures()).contains("Vault still holds assets"));
+    assert_eq!(vault_asset(&vault, &owner).await?, usdt.id().to_string());
+
+    Ok(())
+}
+
+/// Test the shares redeemable right now are bounded by the idle liquidity
+#[tokio::test]
+async fn test_max_redeemable_now() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let strategy = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_storage_deposit(&usdt, &strategy).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    assert_eq!(
+        vault_max_redeemable_now(&vault, &alice, &alice).await?.0,
+        1000
+    );
+
+    // Leave only 400 idle
+    vault_set_strategy(&vault, &owner, Some(&strategy)).await?;
+    assert!(vault_invest(&vault, &owner, 600).await?);
+
+    let redeemable = vault_max_redeemable_now(&vault, &alice, &alice).await?.0;
+    assert!(redeemable < vault_balance_of(&vault, &alice, &alice).await?.0);
+    assert!(vault_convert_to_assets(&vault, &alice, redeemable).await?.0 <= 400);
+
+    // Exactly that much is paid out of idle liquidity
+    let outcome = vault_redeem(&vault, &alice, redeemable, None, None).await?;
+    assert!(!outcome.rolled_back);
+    assert_eq!(vault_deployed_assets(&vault, &owner).await?.0, 600);
+
+    Ok(())
+}
+
+/// Test share holders can be enumerated page by page with their balances
+#[tokio::test]
+async fn test_ft_accounts_pagination() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+    let carol = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts, each holding a different balance
+    for (account, amount) in [(&alice, 100), (&bob, 200), (&carol, 300)] {
+        ft_storage_deposit(&usdt, account).await?;
+        vault_storage_deposit(&vault, account).await?;
+        ft_transfer(&usdt, &owner, account, 10000).await?;
+        ft_transfer_call_deposit(&usdt, &vault, account, amount, None, None, None, None, None)
+            .await?;
+    }
+
+    let first_page = vault_ft_accounts(&vault, &alice, 0, 2).await?;
+    let second_page = vault_ft_accounts(&vault, &alice, 2, 2).await?;
+    assert_eq!(first_page.len(), 2);
+    assert_eq!(second_page.len(), 1);
+    assert!(vault_ft_accounts(&vault, &alice, 3, 2).await?.is_empty());
+
+    let holders: Vec<_> = first_page.into_iter().chain(second_page).collect();
+    for account in [&alice, &bob, &carol] {
+        let balance = vault_balance_of(&vault, account, account).await?;
+        assert!(holders.contains(&(account.id().to_string(), balance)));
+    }
+
+    Ok(())
+}
+
+/// Test a reported loss can't push the price per share below the owner's floor
+#[tokio::test]
+async fn test_price_floor_circuit_breaker() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let strategy = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_storage_deposit(&usdt, &strategy).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    vault_set_strategy(&vault, &owner, Some(&strategy)).await?;
+    vault_invest(&vault, &owner, 1000).await?;
+
+    // One whole share is worth 1.001 assets, the floor allows a loss down to 0.9
+    vault_set_min_price_per_share(&vault, &owner, Some(900_000)).await?;
+
+    // Losing 102 of the 1000 deployed prices a share at 0.899, below the floor
+    let result = owner
+        .call(vault.id(), "report_yield")
+        .args_json(json!({ "strategy_balance": "898" }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?;
+    assert!(result.is_failure());
+    assert!(format!("{:?}", result.failures()).contains("Price floor breached"));
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1000);
+    assert_eq!(vault_deployed_assets(&vault, &alice).await?.0, 1000);
+
+    // Losing 100 leaves a share at 0.901, just above the floor
+    vault_report_yield(&vault, &owner, 900).await?;
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 900);
+    assert_eq!(vault_price_per_share(&vault, &alice).await?.0, 901_000);
+
+    Ok(())
+}
+
+/// Test a strategy loss lowers the share price while the vault stays healthy, until shares are
+/// left without any value
+#[tokio::test]
+async fn test_is_healthy_after_strategy_loss() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let strategy = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    assert!(vault_is_healthy(&vault, &alice).await?);
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_storage_deposit(&usdt, &strategy).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    vault_set_strategy(&vault, &owner, Some(&strategy)).await?;
+    vault_invest(&vault, &owner, 800).await?;
+    assert!(vault_is_healthy(&vault, &alice).await?);
+
+    // The loss is borne by the share price, every share stays backed
+    vault_report_yield(&vault, &owner, 500).await?;
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 700);
+    assert_eq!(vault_convert_to_assets(&vault, &alice, 1000).await?.0, 701);
+    assert!(vault_is_healthy(&vault, &alice).await?);
+
+    // Even a total loss of the deployed assets
+    vault_report_yield(&vault, &owner, 0).await?;
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 200);
+    assert!(vault_is_healthy(&vault, &alice).await?);
+
+    // Losing the rest too leaves the shares unbacked
+    vault_invest(&vault, &owner, 200).await?;
+    vault_report_yield(&vault, &owner, 0).await?;
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 0);
+    assert!(!vault_is_healthy(&vault, &alice).await?);
+
+    Ok(())
+}
+
+/// Test redeeming to the bridge forwards the assets with the caller's message, and rolls back
+/// when the bridge refunds them
+#[tokio::test]
+async fn test_redeem_to_bridge() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+    let bridge = deploy_mock_bridge(&owner).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_storage_deposit(&usdt, bridge.as_account()).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    vault_set_bridge_account(&vault, &owner, Some(&bridge)).await?;
+
+    let bridge_msg = r#"{"destination":"0x000000000000000000000000000000000000dEaD"}"#;
+    let outcome = vault_redeem_to_bridge(&vault, &alice, 400, bridge_msg).await?;
+    assert!(!outcome.rolled_back);
+    assert_eq!(outcome.assets.0, 400);
+    assert_eq!(ft_balance_of(&usdt, bridge.as_account()).await?, 400);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 600);
+
+    let transfers = mock_bridge_transfers(&bridge).await?;
+    assert_eq!(transfers.len(), 1);
+    assert_eq!(transfers[0]["sender_id"], vault.id().to_string());
+    assert_eq!(transfers[0]["amount"], "400");
+    assert_eq!(transfers[0]["msg"], bridge_msg);
+
+    // The bridge refunds everything, the redeem is rolled back
+    let outcome = vault_redeem_to_bridge(&vault, &alice, 100, "reject").await?;
+    assert!(outcome.rolled_back);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 600);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 600);
+    assert_eq!(ft_balance_of(&usdt, bridge.as_account()).await?, 400);
+
+    Ok(())
+}
+
+/// Test a bridge refunding part of a redeem restores that part's shares and exit fee to the owner
+#[tokio::test]
+async fn test_redeem_to_bridge_partial_refund() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+    let bridge = deploy_mock_bridge(&owner).await?;
+
+    // Setup accounts, the owner collects the fees
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_storage_deposit(&usdt, bridge.as_account()).await?;
+    for account in [&owner, &alice] {
+        vault_storage_deposit(&vault, account).await?;
+    }
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    vault_set_bridge_account(&vault, &owner, Some(&bridge)).await?;
+    vault_set_performance_fee(&vault, &owner, 0, &owner).await?;
+    vault_set_exit_fee(&vault, &owner, 1000, 10u64.pow(18)).await?;
+
+    // 90 of the 100 shares are redeemed after the fee, the bridge only takes 40 of the 90 assets
+    let outcome = vault_redeem_to_bridge(&vault, &alice, 100, "refund:50").await?;
+    assert!(!outcome.rolled_back);
+    assert_eq!(outcome.assets.0, 40);
+    assert_eq!(outcome.shares.0, 40);
+    assert_eq!(ft_balance_of(&usdt, bridge.as_account()).await?, 40);
+
+    // The fee is only charged on the part that went through, 5 of the 10 escrowed shares
+    assert_eq!(vault_balance_of(&vault, &alice, &owner).await?.0, 5);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 955);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 960);
+    assert_eq!(vault_total_supply(&vault, &alice).await?.0, 960);
+
+    Ok(())
+}
+
+/// Test deposits with `stake_to` stake the minted shares, and get them back if the farm refuses
+#[tokio::test]
+async fn test_deposit_stake_to_farm() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+    let farm = deploy_mock_farm(&owner).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    vault_storage_deposit(&vault, farm.as_account()).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    // Only the configured farm can be staked into, the deposit is refunded otherwise
+    let msg = json!({ "action": "deposit", "stake_to": farm.id() }).to_string();
+    let result = ft_transfer_call_with_msg(&usdt, &vault, &alice, 1000, &msg).await?;
+    assert!(format!("{:?}", result.failures()).contains("Not the configured farm"));
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 10000);
+
+    vault_set_farm(&vault, &owner, Some(&farm)).await?;
+    ft_transfer_call_with_msg(&usdt, &vault, &alice, 1000, &msg)
+        .await?
+        .into_result()?;
+    assert_eq!(mock_farm_staked_of(&farm, &alice).await?.0, 1000);
+    assert_eq!(
+        vault_balance_of(&vault, &alice, farm.as_account()).await?.0,
+        1000
+    );
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 0);
+
+    // The farm refuses the stake, the shares (not the assets) go back to the depositor
+    let msg =
+        json!({ "action": "deposit", "stake_to": farm.id(), "stake_msg": "reject" }).to_string();
+    ft_transfer_call_with_msg(&usdt, &vault, &alice, 500, &msg)
+        .await?
+        .into_result()?;
+    assert_eq!(mock_farm_staked_of(&farm, &alice).await?.0, 1000);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 499);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 8500);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1500);
+
+    Ok(())
+}
+
+/// Test deposits can stake into the farm under a lock period, the shares the farm refuses come
+/// back locked
+#[tokio::test]
+async fn test_deposit_stake_to_farm_under_share_lock() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+    let farm = deploy_mock_farm(&owner).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    vault_storage_deposit(&vault, farm.as_account()).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    vault_set_farm(&vault, &owner, Some(&farm)).await?;
+    vault_set_share_lock_period(&vault, &owner, 60_000_000_000).await?;
+
+    let msg = json!({ "action": "deposit", "stake_to": farm.id() }).to_string();
+    ft_transfer_call_with_msg(&usdt, &vault, &alice, 1000, &msg)
+        .await?
+        .into_result()?;
+    assert_eq!(mock_farm_staked_of(&farm, &alice).await?.0, 1000);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 0);
+
+    // Refused shares don't slip past the lock
+    let msg =
+        json!({ "action": "deposit", "stake_to": farm.id(), "stake_msg": "reject" }).to_string();
+    ft_transfer_call_with_msg(&usdt, &vault, &alice, 500, &msg)
+        .await?
+        .into_result()?;
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 499);
+    assert_eq!(vault_unlocked_shares_of(&vault, &alice, &alice).await?.0, 0);
+    let err = vault_redeem(&vault, &alice, 499, None, None)
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Shares are locked"));
+
+    Ok(())
+}
+
+/// Test shares minted under a lock period can't leave the account until it expires
+#[tokio::test]
+async fn test_share_lock_period() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    vault_storage_deposit(&vault, &bob).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    // Shares minted before the lock period stay transferable
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    vault_set_share_lock_period(&vault, &owner, 60_000_000_000).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1001, None, None, None, None, None).await?;
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 2000);
+    assert_eq!(
+        vault_unlocked_shares_of(&vault, &alice, &alice).await?.0,
+        1000
+    );
+
+    let err = vault_redeem(&vault, &alice, 1001, None, None)
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Shares are locked"));
+    let err = vault_share_transfer(&vault, &alice, &bob, 1001, NearToken::from_yoctonear(1))
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Shares are locked"));
+    let err = vault_burn_shares(&vault, &alice, 1001).await.unwrap_err();
+    assert!(format!("{:?}", err).contains("Shares are locked"));
+    let err = vault_storage_unregister(&vault, &alice, true)
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Shares are locked"));
+    vault_redeem(&vault, &alice, 500, None, None).await?;
+    assert_eq!(
+        vault_unlocked_shares_of(&vault, &alice, &alice).await?.0,
+        500
+    );
+
+    let state = vault_account_state_of(&vault, &alice, &alice).await?;
+    let unlocks_ns: u64 = state["share_lots"][0]["unlocks_ns"]
+        .as_str()
+        .unwrap()
+        .parse()?;
+    while worker.view_block().await?.timestamp() <= unlocks_ns {
+        worker.fast_forward(100).await?;
+    }
+
+    assert_eq!(
+        vault_unlocked_shares_of(&vault, &alice, &alice).await?.0,
+        1500
+    );
+    let outcome = vault_redeem(&vault, &alice, 1001, None, None).await?;
+    assert!(!outcome.rolled_back);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 499);
+
+    Ok(())
+}
+
+/// Test the entry price stays at what the deposit paid while a redeem realizes the gain
+#[tokio::test]
+async fn test_entry_price_and_realized_pnl() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    let initial_price = vault_price_per_share(&vault, &alice).await?.0;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    assert_eq!(
+        vault_entry_price_of(&vault, &alice, &alice).await?.0,
+        initial_price
+    );
+
+    // A donation raises the rate, not the price alice entered at
+    ft_transfer_call_deposit(
+        &usdt,
+        &vault,
+        &owner,
+        1000,
+        None,
+        None,
+        None,
+        None,
+        Some(true),
+    )
+    .await?;
+    assert!(vault_price_per_share(&vault, &alice).await?.0 > initial_price);
+    assert_eq!(
+        vault_entry_price_of(&vault, &alice, &alice).await?.0,
+        initial_price
+    );
+
+    // Half the shares bring 1000 assets back for the 500 deposited
+    let outcome = vault_redeem(&vault, &alice, 500, None, None).await?;
+    assert_eq!(outcome.assets.0, 1000);
+    assert_eq!(vault_realized_pnl_of(&vault, &alice, &alice).await?.0, 500);
+    assert_eq!(
+        vault_deposited_assets_of(&vault, &alice, &alice).await?.0,
+        500
+    );
+
+    Ok(())
+}
+
+/// Test a balance-based vault books a direct transfer as yield once refreshed
+#[tokio::test]
+async fn test_balance_based_total_assets() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault_with_config(
+        &owner,
+        &usdt,
+        "USDT Vault",
+        "vUSDT",
+        0,
+        json!({ "total_assets_mode": "BalanceBased" }),
+    )
+    .await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    // A plain transfer isn't seen until the vault refreshes from its balance
+    ft_transfer(&usdt, &owner, vault.as_account(), 100).await?;
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1000);
+
+    let total = vault_refresh_total_assets(&vault, &owner).await?;
+    assert_eq!(total.0, 1100);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1100);
+
+    // Nothing new to pick up
+    vault_refresh_total_assets(&vault, &owner).await?;
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1100);
+
+    Ok(())
+}
+
+/// Test investing into, harvesting from and divesting out of a strategy through its adapter ABI
+#[tokio::test]
+async fn test_strategy_adapter_lifecycle() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+    let strategy = deploy_and_init_mock_strategy(&owner, &usdt, &vault, 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_storage_deposit(&usdt, strategy.as_account()).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    vault_set_strategy(&vault, &owner, Some(strategy.as_account())).await?;
+
+    // The strategy is told to put the transferred assets to work
+    assert!(vault_invest(&vault, &owner, 600).await?);
+    assert_eq!(mock_strategy_deposited(&strategy).await?.0, 600);
+    assert_eq!(vault_deployed_assets(&vault, &alice).await?.0, 600);
+
+    // The strategy earned 60, harvesting books it from the strategy's balance
+    ft_transfer(&usdt, &owner, strategy.as_account(), 60).await?;
+    let total = vault_harvest(&vault, &owner).await?;
+    assert_eq!(total.0, 1060);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1060);
+    assert_eq!(vault_deployed_assets(&vault, &alice).await?.0, 660);
+
+    // Divesting moves assets back to idle liquidity without changing the total
+    let returned = vault_divest(&vault, &owner, 300).await?;
+    assert_eq!(returned.0, 300);
+    assert_eq!(vault_deployed_assets(&vault, &alice).await?.0, 360);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1060);
+    assert_eq!(ft_balance_of(&usdt, vault.as_account()).await?, 700);
+
+    Ok(())
+}
+
+/// Test compounding claims the strategy's rewards as yield and redeploys the idle assets
+#[tokio::test]
+async fn test_compound_from_strategy() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+    let strategy = deploy_and_init_mock_strategy(&owner, &usdt, &vault, 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_storage_deposit(&usdt, strategy.as_account()).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    vault_set_strategy(&vault, &owner, Some(strategy.as_account())).await?;
+    assert!(vault_invest(&vault, &owner, 600).await?);
+
+    // The strategy earned 100 in rewards, which its balance leaves out
+    ft_transfer(&usdt, &owner, strategy.as_account(), 100).await?;
+    mock_strategy_accrue_rewards(&strategy, 100).await?;
+    assert_eq!(vault_harvest(&vault, &owner).await?.0, 1000);
+
+    let price_before = vault_price_per_share(&vault, &alice).await?.0;
+    let claimed = vault_compound(&vault, &owner, true).await?;
+    assert_eq!(claimed.0, 100);
+    assert!(vault_price_per_share(&vault, &alice).await?.0 > price_before);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1100);
+
+    // Everything idle went back to work
+    assert_eq!(vault_deployed_assets(&vault, &alice).await?.0, 1100);
+    assert_eq!(mock_strategy_deposited(&strategy).await?.0, 1100);
+    assert_eq!(ft_balance_of(&usdt, vault.as_account()).await?, 0);
+
+    Ok(())
+}
+
+/// Test a structured withdraw message carries the memo and referral into the withdraw events,
+/// and its deadline is enforced
+#[tokio::test]
+async fn test_redeem_with_referral_message() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let referrer = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    let msg = json!({ "referral": referrer.id(), "memo": "Rebalancing" }).to_string();
+    let result = vault_redeem_with_msg(&vault, &alice, 400, &msg).await?;
+    let logs: Vec<String> = result.logs().into_iter().map(String::from).collect();
+    result.into_result()?;
+
+    let events = parse_vault_events(&logs);
+    let withdraw = events
+        .iter()
+        .find_map(|event| match event {
+            VaultEvent::VaultWithdraw(withdraw) => Some(withdraw),
+            _ => None,
+        })
+        .expect("No withdraw event");
+    assert_eq!(withdraw.memo, Some("Rebalancing"));
+    let referral = events
+        .iter()
+        .find_map(|event| match event {
+            VaultEvent::VaultWithdrawReferral(referral) => Some(referral),
+            _ => None,
+        })
+        .expect("No withdraw referral event");
+    assert_eq!(referral.referrer.as_str(), referrer.id().as_str());
+    assert_eq!(referral.owner_id.as_str(), alice.id().as_str());
+    assert_eq!(referral.assets.0, 400);
+    assert_eq!(referral.shares.0, 400);
+
+    // A deadline in the past refuses the withdrawal
+    let msg = json!({ "deadline_ns": "1" }).to_string();
+    let result = vault_redeem_with_msg(&vault, &alice, 100, &msg).await?;
+    assert!(result.is_failure());
+    assert!(format!("{:?}", result.failures()).contains("Withdrawal deadline passed"));
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 600);
+
+    Ok(())
+}
+
+/// Test total_assets_usd values total_assets at the oracle's price, scaled by its decimals
+#[tokio::test]
+async fn test_total_assets_usd() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+    let oracle = deploy_mock_oracle(&owner).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    let result = vault_total_assets_usd(&vault, &alice).await;
+    assert!(format!("{:?}", result.unwrap_err()).contains("No price oracle set"));
+
+    let oracle_decimals = 8;
+    let price = 99_950_000u128;
+    mock_oracle_set_price(&oracle, &usdt, price).await?;
+    vault_set_price_oracle(&vault, &owner, Some(&oracle), oracle_decimals).await?;
+
+    let total_assets = vault_total_assets(&vault, &alice).await?.0;
+    assert_eq!(
+        vault_total_assets_usd(&vault, &alice).await?.0,
+        total_assets * price / 10u128.pow(oracle_decimals as u32)
+    );
+    assert_eq!(vault_total_assets_usd(&vault, &alice).await?.0, 999);
+
+    Ok(())
+}
+
+/// Test a position moves from an old vault into a new one of the same asset in one transfer,
+/// and is refunded while the new vault doesn't accept the old one
+#[tokio::test]
+async fn test_migrate_between_vaults() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let new_owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let old_vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+    let new_vault = deploy_and_init_vault(&new_owner, &usdt, "USDT Vault v2", "vUSDT2", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&old_vault, &alice).await?;
+    vault_storage_deposit(&new_vault, &alice).await?;
+    vault_storage_deposit(&old_vault, new_vault.as_account()).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer_call_deposit(
+        &usdt, &old_vault, &alice, 1000, None, None, None, None, None,
+    )
+    .await?;
+    let old_shares = vault_balance_of(&old_vault, &alice, &alice).await?.0;
+
+    // Not the configured source, the shares are refunded
+    let result = vault_migrate(&old_vault, &new_vault, &alice, 400).await?;
+    assert!(format!("{:?}", result.failures()).contains("Not the configured migration source"));
+    assert_eq!(
+        vault_balance_of(&old_vault, &alice, &alice).await?.0,
+        old_shares
+    );
+
+    // A vault of another asset can't be the source
+    let dai_owner = worker.dev_create_account().await?;
+    let dai = deploy_and_init_mock_ft(&dai_owner, "Dai", "DAI", Some(1_000_000u128)).await?;
+    let dai_vault = deploy_and_init_vault(&dai_owner, &dai, "DAI Vault", "vDAI", 0).await?;
+    let err = vault_set_migration_source(&new_vault, &new_owner, Some(&dai_vault))
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Migration source holds another asset"));
+
+    vault_set_migration_source(&new_vault, &new_owner, Some(&old_vault)).await?;
+    let assets = vault_convert_to_assets(&old_vault, &alice, 400).await?.0;
+    vault_migrate(&old_vault, &new_vault, &alice, 400)
+        .await?
+        .into_result()?;
+
+    assert_eq!(
+        vault_balance_of(&old_vault, &alice, &alice).await?.0,
+        old_shares - 400
+    );
+    assert_eq!(
+        vault_balance_of(&old_vault, &alice, new_vault.as_account())
+            .await?
+            .0,
+        0
+    );
+    assert_eq!(vault_total_assets(&new_vault, &alice).await?.0, assets);
+    assert_eq!(ft_balance_of(&usdt, new_vault.as_account()).await?, assets);
+    assert!(vault_balance_of(&new_vault, &alice, &alice).await?.0 > 0);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 9000);
+
+    Ok(())
+}
+
+/// Test a position migrates out of a vault of the first release, whose `redeem` resolves to the
+/// bare assets, and the shares are refunded when that redeem rolls back
+#[tokio::test]
+async fn test_migrate_from_legacy_vault() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let new_owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let old_vault = deploy_and_init_mock_legacy_vault(&owner, &usdt).await?;
+    let new_vault = deploy_and_init_vault(&new_owner, &usdt, "USDT Vault v2", "vUSDT2", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, old_vault.as_account()).await?;
+    ft_transfer(&usdt, &owner, old_vault.as_account(), 1000).await?;
+    mock_legacy_vault_mint(&old_vault, &alice, 1000).await?;
+    vault_storage_deposit(&new_vault, &alice).await?;
+    vault_set_migration_source(&new_vault, &new_owner, Some(&old_vault)).await?;
+
+    // Resolved to zero assets, the old vault kept the shares and they go back to Alice
+    mock_legacy_vault_set_roll_back(&old_vault, true).await?;
+    vault_migrate(&old_vault, &new_vault, &alice, 400)
+        .await?
+        .into_result()?;
+    assert_eq!(vault_balance_of(&old_vault, &alice, &alice).await?.0, 1000);
+    assert_eq!(vault_balance_of(&new_vault, &alice, &alice).await?.0, 0);
+    assert_eq!(vault_total_assets(&new_vault, &alice).await?.0, 0);
+
+    mock_legacy_vault_set_roll_back(&old_vault, false).await?;
+    vault_migrate(&old_vault, &new_vault, &alice, 400)
+        .await?
+        .into_result()?;
+
+    assert_eq!(vault_balance_of(&old_vault, &alice, &alice).await?.0, 600);
+    assert_eq!(
+        vault_balance_of(&old_vault, &alice, new_vault.as_account())
+            .await?
+            .0,
+        0
+    );
+    assert_eq!(vault_total_assets(&new_vault, &alice).await?.0, 400);
+    assert_eq!(ft_balance_of(&usdt, new_vault.as_account()).await?, 400);
+    assert_eq!(vault_balance_of(&new_vault, &alice, &alice).await?.0, 400);
+
+    Ok(())
+}
+
+/// Test withdrawals stop at the limit of the sliding window, resume once it has moved past them
+/// and get back the allowance of a rollback
+#[tokio::test]
+async fn test_withdraw_limit_per_window() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    let err = vault_set_withdraw_limit(&vault, &owner, Some(300), 0)
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Window must not be empty"));
+
+    let window_ns = 60_000_000_000;
+    vault_set_withdraw_limit(&vault, &owner, Some(300), window_ns).await?;
+    assert_eq!(
+        vault_withdrawable_in_window(&vault, &alice).await?,
+        Some(U128(300))
+    );
+
+    vault_withdraw(&vault, &alice, 200, None, None).await?;
+    vault_withdraw(&vault, &alice, 100, None, None).await?;
+    assert_eq!(
+        vault_withdrawable_in_window(&vault, &alice).await?,
+        Some(U128(0))
+    );
+
+    let err = vault_withdraw(&vault, &alice, 1, None, None)
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Withdrawal limit reached, try later"));
+    let err = vault_redeem(&vault, &alice, 10, None, None)
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Withdrawal limit reached, try later"));
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 9300);
+
+    // The window slides, what was withdrawn only stops counting once two windows have passed
+    let window_end = worker.view_block().await?.timestamp() + 2 * window_ns;
+    while worker.view_block().await?.timestamp() <= window_end {
+        worker.fast_forward(100).await?;
+    }
+
+    assert_eq!(
+        vault_withdrawable_in_window(&vault, &alice).await?,
+        Some(U128(300))
+    );
+    // A rolled back withdrawal gives its allowance back
+    let outcome = vault_withdraw(&vault, &alice, 300, Some(&bob), None).await?;
+    assert!(outcome.rolled_back);
+    assert_eq!(
+        vault_withdrawable_in_window(&vault, &alice).await?,
+        Some(U128(300))
+    );
+
+    vault_withdraw(&vault, &alice, 300, None, None).await?;
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 9600);
+
+    Ok(())
+}
+
+/// Test assets plain-transferred to the vault stay out of total_assets until absorbed, then
+/// accrue to the existing holders
+#[tokio::test]
+async fn test_absorb_plain_transferred_assets() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    for account in [&alice, &bob] {
+        ft_storage_deposit(&usdt, account).await?;
+        vault_storage_deposit(&vault, account).await?;
+        ft_transfer(&usdt, &owner, account, 10000).await?;
+    }
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    let shares = vault_balance_of(&vault, &alice, &alice).await?.0;
+
+    // Without a message the vault never sees the transfer
+    ft_transfer(&usdt, &bob, vault.as_account(), 500).await?;
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1000);
+    assert_eq!(ft_balance_of(&usdt, vault.as_account()).await?, 1500);
+
+    let err = vault_absorb_untracked_assets(&vault, &alice)
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Not owner"));
+
+    assert_eq!(vault_absorb_untracked_assets(&vault, &owner).await?.0, 500);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1500);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, shares);
+    assert!(vault_assets_of(&vault, &alice, &alice).await?.0 >= 1499);
+
+    // Nothing left to absorb
+    assert_eq!(vault_absorb_untracked_assets(&vault, &owner).await?.0, 0);
+
+    // The unused part of a capped deposit is refunded to the sender, not absorbed
+    ft_transfer_call_deposit(&usdt, &vault, &bob, 1000, None, None, Some(100), None, None).await?;
+    assert!(ft_balance_of(&usdt, &bob).await? > 9000);
+    assert_eq!(vault_absorb_untracked_assets(&vault, &owner).await?.0, 0);
+
+    Ok(())
+}
@@ -1,9 +1,20 @@
+use near_sdk::{json_types::U128, NearToken};
+use near_workspaces::operations::Function;
+use serde_json::json;
+
 use crate::helper::{
     mock_ft::{deploy_and_init_mock_ft, ft_balance_of, ft_storage_deposit, ft_transfer},
     vault::{
-        deploy_and_init_vault, ft_transfer_call_deposit, vault_balance_of, vault_convert_to_assets,
-        vault_convert_to_shares, vault_redeem, vault_storage_deposit, vault_total_assets,
-        vault_total_supply, vault_withdraw,
+        deploy_and_init_vault, deploy_and_init_vault_with_config, ft_transfer_call_deposit,
+        ft_transfer_call_with_msg, vault_account_state_of, vault_assets_of, vault_available_assets,
+        vault_balance_of, vault_check_invariants, vault_claimable_withdrawal,
+        vault_convert_to_assets, vault_convert_to_shares, vault_mt_on_transfer,
+        vault_preview_deposit, vault_preview_mint, vault_process_withdrawals, vault_redeem,
+        vault_reserve_assets, vault_set_check_receiver_storage, vault_set_min_shares_per_receiver,
+        vault_set_reject_zero_share_deposits, vault_share_transfer,
+        vault_storage_balance_bounds_min, vault_storage_deposit, vault_storage_deposit_exact,
+        vault_storage_deposit_many, vault_storage_unregister, vault_storage_withdraw,
+        vault_total_assets, vault_total_supply, vault_withdraw, vault_withdraw_split,
     },
 };
 
@@ -311,6 +322,7 @@ async fn test_max_shares_capping() -> Result<(), Box<dyn std::error::Error>> {
     // Deposit with max_shares limit
     let deposit_amount = 1000u128;
     let max_shares = 700u128; // Less than what would normally be minted (1000 shares for 1000 assets)
+    let preview_used = vault_preview_mint(&vault, &alice, max_shares).await?.0;
 
     let used_amount = ft_transfer_call_deposit(
         &usdt,
@@ -339,17 +351,15 @@ async fn test_max_shares_capping() -> Result<(), Box<dyn std::error::Error>> {
         "Total vault assets should equal the amount actually used"
     );
 
-    // The used amount should match the assets equivalent of max_shares
-    // We allow ±1 rounding difference because:
-    // - Deposit uses Rounding::Down for convert_to_assets (conservative)
-    // - Preview uses different calculation path
-    // This prevents inflation attacks while allowing minimal acceptable rounding
+    // The used amount should match the assets equivalent of max_shares, within ±1 of the
+    // conversion rounded down and exactly the preview, which rounds up like the deposit
     let expected_used = vault_convert_to_assets(&vault, &alice, max_shares).await?.0;
     assert!(
         used_amount.0 >= expected_used.saturating_sub(1) && used_amount.0 <= expected_used + 1,
         "Used amount should be within ±1 of assets equivalent of max_shares due to rounding modes (got {}, expected {})",
         used_amount.0, expected_used
     );
+    assert_eq!(used_amount.0, preview_used);
 
     // Verify Alice received refund for unused portion
     let alice_balance = ft_balance_of(&usdt, &alice).await?;
@@ -362,6 +372,76 @@ async fn test_max_shares_capping() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Test `preview_deposit` quotes exactly the shares a real deposit mints, across vault states
+/// from empty with dead shares to a share price above one
+#[tokio::test]
+async fn test_preview_deposit_matches_mint() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(10_000_000u128)).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 5_000_000).await?;
+
+    for (extra_decimals, config) in [
+        (0, json!({})),
+        (0, json!({ "dead_shares": "1000" })),
+        (3, json!({})),
+    ] {
+        let vault = deploy_and_init_vault_with_config(
+            &owner,
+            &usdt,
+            "USDT Vault",
+            "vUSDT",
+            extra_decimals,
+            config,
+        )
+        .await?;
+        vault_storage_deposit(&vault, &alice).await?;
+
+        // Uneven amounts and donations move total_assets and total_supply apart
+        for (amount, donation) in [
+            (10_007, 0),
+            (1, 0),
+            (333, 4_567),
+            (99_999, 0),
+            (7, 13),
+            (123_457, 0),
+        ] {
+            if donation > 0 {
+                ft_transfer_call_deposit(
+                    &usdt,
+                    &vault,
+                    &alice,
+                    donation,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(true),
+                )
+                .await?;
+            }
+
+            let preview = vault_preview_deposit(&vault, &alice, amount).await?.0;
+            let before = vault_balance_of(&vault, &alice, &alice).await?.0;
+            ft_transfer_call_deposit(&usdt, &vault, &alice, amount, None, None, None, None, None)
+                .await?;
+            let minted = vault_balance_of(&vault, &alice, &alice).await?.0 - before;
+            assert_eq!(
+                preview, minted,
+                "Preview off for {} assets with {} extra decimals",
+                amount, extra_decimals
+            );
+        }
+    }
+
+    Ok(())
+}
+
 /// Test edge case with very small deposits and withdrawals
 #[tokio::test]
 async fn test_dust_amounts() -> Result<(), Box<dyn std::error::Error>> {
@@ -620,11 +700,16 @@ async fn test_withdrawal_rollback_mechanism() -> Result<(), Box<dyn std::error::
     // This should complete with rollback due to transfer failure to unregistered account
     let result = vault_redeem(&vault, &alice, 500, Some(&non_existent), None).await?;
 
-    // Rollback should occur, returning 0 assets and restoring all state
+    // Rollback should occur, reporting nothing moved and restoring all state
+    assert!(result.rolled_back, "Outcome should report the rollback");
     assert_eq!(
-        result.0, 0,
+        result.assets.0, 0,
         "Rollback should return 0 assets when transfer fails"
     );
+    assert_eq!(
+        result.shares.0, 0,
+        "Rollback should report no shares burned"
+    );
 
     let final_shares = vault_balance_of(&vault, &alice, &alice).await?.0;
     let final_total_assets = vault_total_assets(&vault, &alice).await?.0;
@@ -647,6 +732,65 @@ async fn test_withdrawal_rollback_mechanism() -> Result<(), Box<dyn std::error::
     Ok(())
 }
 
+/// Test a rollback after the owner redeemed everything and unregistered before the callback
+/// gives the shares back instead of stranding them
+#[tokio::test]
+async fn test_rollback_after_owner_unregistered() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+    let unregistered = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    for account in [&bob, &alice] {
+        ft_storage_deposit(&usdt, account).await?;
+        vault_storage_deposit(&vault, account).await?;
+        ft_transfer(&usdt, &owner, account, 10000).await?;
+        ft_transfer_call_deposit(&usdt, &vault, account, 1000, None, None, None, None, None)
+            .await?;
+    }
+    let shares = vault_balance_of(&vault, &alice, &alice).await?.0;
+    let total_supply = vault_total_supply(&vault, &alice).await?.0;
+
+    // Alice redeems everything and unregisters in one receipt, ahead of the failing transfer
+    // and its callback
+    let result = alice
+        .batch(vault.id())
+        .call(
+            Function::new("redeem")
+                .args_json(json!({
+                    "shares": shares.to_string(),
+                    "receiver_id": unregistered.id(),
+                }))
+                .deposit(NearToken::from_yoctonear(1))
+                .gas(near_workspaces::types::Gas::from_tgas(100)),
+        )
+        .call(
+            Function::new("storage_unregister")
+                .args_json(json!({}))
+                .deposit(NearToken::from_yoctonear(1))
+                .gas(near_workspaces::types::Gas::from_tgas(30)),
+        )
+        .transact()
+        .await?;
+    assert!(format!("{:?}", result.receipt_failures()).contains("not registered"));
+
+    // The rollback registered Alice again with her shares
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, shares);
+    assert_eq!(vault_total_supply(&vault, &alice).await?.0, total_supply);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 2000);
+
+    let outcome = vault_redeem(&vault, &alice, shares, None, None).await?;
+    assert!(!outcome.rolled_back);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 9000 + outcome.assets.0);
+
+    Ok(())
+}
+
 /// # Inflation (Donation) Attack on ERC-4626 Vaults
 #[tokio::test]
 async fn test_inflation_attack() -> Result<(), Box<dyn std::error::Error>> {
@@ -719,3 +863,1187 @@ async fn test_inflation_attack() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// Test a donation front-running a small depositor can't take their assets: a deposit the
+/// inflated rate would round to zero shares is refunded, and a larger one only pays for the
+/// whole shares it gets
+#[tokio::test]
+async fn test_donation_attack_on_pending_deposit() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let mallory = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(10u128.pow(12))).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    for account in [&alice, &mallory] {
+        ft_storage_deposit(&usdt, account).await?;
+        vault_storage_deposit(&vault, account).await?;
+    }
+    ft_transfer(&usdt, &owner, &alice, 2_000_000).await?;
+    ft_transfer(&usdt, &owner, &mallory, 2_000_000).await?;
+
+    // Mallory holds the only share and donates to push its price up before Alice lands
+    ft_transfer_call_deposit(&usdt, &vault, &mallory, 1, None, None, None, None, None).await?;
+    ft_transfer_call_deposit(
+        &usdt,
+        &vault,
+        &mallory,
+        1_000_000,
+        None,
+        None,
+        None,
+        None,
+        Some(true),
+    )
+    .await?;
+    assert_eq!(vault_total_supply(&vault, &alice).await?.0, 1);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1_000_001);
+
+    // Rounded to zero shares, the deposit is refunded rather than absorbed by Mallory's share
+    let used =
+        ft_transfer_call_deposit(&usdt, &vault, &alice, 10_000, None, None, None, None, None)
+            .await?;
+    assert_eq!(used.0, 0);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 2_000_000);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1_000_001);
+
+    // One whole share is bought at its price, the remainder goes back
+    let used = ft_transfer_call_deposit(
+        &usdt, &vault, &alice, 1_500_000, None, None, None, None, None,
+    )
+    .await?;
+    assert_eq!(used.0, 1_000_002);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 2_000_000 - used.0);
+    assert_eq!(vault_assets_of(&vault, &alice, &alice).await?.0, used.0);
+
+    // Mallory gained no more than the one unit of the virtual offset
+    let mallory_assets = vault_assets_of(&vault, &mallory, &mallory).await?.0;
+    assert!(mallory_assets <= 1_000_001 + 1);
+
+    Ok(())
+}
+
+/// Test that deposits crediting shares to the vault itself are refunded
+#[tokio::test]
+async fn test_deposit_to_vault_account_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    // Alice tries to mint shares to the vault's own account
+    let used_amount = ft_transfer_call_deposit(
+        &usdt,
+        &vault,
+        &alice,
+        1000,
+        Some(vault.as_account()),
+        None,
+        None,
+        None,
+        None,
+    )
+    .await?;
+
+    assert_eq!(
+        used_amount.0, 0,
+        "Deposit to the vault's own account should be fully refunded"
+    );
+
+    let vault_shares = vault_balance_of(&vault, &alice, vault.as_account()).await?;
+    assert_eq!(vault_shares.0, 0);
+
+    let total_supply = vault_total_supply(&vault, &alice).await?;
+    assert_eq!(total_supply.0, 0);
+
+    let total_assets = vault_total_assets(&vault, &alice).await?;
+    assert_eq!(total_assets.0, 0);
+
+    let alice_balance = ft_balance_of(&usdt, &alice).await?;
+    assert_eq!(alice_balance, 10000);
+
+    Ok(())
+}
+
+/// Test that withdrawals to the asset contract itself are rejected
+#[tokio::test]
+async fn test_withdraw_to_asset_contract_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    // Redeem with the asset contract as receiver
+    let result = vault_redeem(&vault, &alice, 500, Some(usdt.as_account()), None).await;
+    assert!(result.is_err(), "Redeem to the asset contract should fail");
+    let error_message = format!("{:?}", result.unwrap_err());
+    assert!(
+        error_message.contains("Cannot withdraw to the asset contract"),
+        "Unexpected error: {}",
+        error_message
+    );
+
+    // Withdraw with the asset contract as receiver
+    let result = vault_withdraw(&vault, &alice, 500, Some(usdt.as_account()), None).await;
+    assert!(
+        result.is_err(),
+        "Withdraw to the asset contract should fail"
+    );
+
+    // Nothing was burned
+    let alice_shares = vault_balance_of(&vault, &alice, &alice).await?;
+    assert_eq!(alice_shares.0, 1000);
+
+    let total_assets = vault_total_assets(&vault, &alice).await?;
+    assert_eq!(total_assets.0, 1000);
+
+    Ok(())
+}
+
+/// Test that deposits crediting an unregistered receiver are fully refunded
+#[tokio::test]
+async fn test_deposit_to_unregistered_receiver_refunded() -> Result<(), Box<dyn std::error::Error>>
+{
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts, bob never registers on the vault
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    let used_amount = ft_transfer_call_deposit(
+        &usdt,
+        &vault,
+        &alice,
+        1000,
+        Some(&bob),
+        None,
+        None,
+        None,
+        None,
+    )
+    .await?;
+
+    assert_eq!(
+        used_amount.0, 0,
+        "Deposit to an unregistered receiver should be fully refunded"
+    );
+
+    let alice_balance = ft_balance_of(&usdt, &alice).await?;
+    assert_eq!(alice_balance, 10000);
+
+    let total_supply = vault_total_supply(&vault, &alice).await?;
+    assert_eq!(total_supply.0, 0);
+
+    let total_assets = vault_total_assets(&vault, &alice).await?;
+    assert_eq!(total_assets.0, 0);
+
+    // The refund says why
+    let msg = json!({ "action": "deposit", "receiver_id": bob.id() }).to_string();
+    let result = ft_transfer_call_with_msg(&usdt, &vault, &alice, 1000, &msg).await?;
+    assert!(result
+        .logs()
+        .iter()
+        .any(|log| log.contains("Share receiver not registered")));
+    result.into_result()?;
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 10000);
+
+    Ok(())
+}
+
+/// Test that reserved assets still back conversions but can't be withdrawn
+#[tokio::test]
+async fn test_reserved_assets_block_withdrawals_not_conversions(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    for account in [&alice, &bob] {
+        ft_storage_deposit(&usdt, account).await?;
+        vault_storage_deposit(&vault, account).await?;
+        ft_transfer(&usdt, &owner, account, 10000).await?;
+    }
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    // Earmark assets for a pending withdrawal
+    vault_reserve_assets(&vault, &owner, 600).await?;
+    let available = vault_available_assets(&vault, &alice).await?;
+    assert_eq!(available.0, 400);
+
+    // A deposit made meanwhile converts against the full total assets
+    let expected_shares = vault_convert_to_shares(&vault, &bob, 500).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &bob, 500, None, None, None, None, None).await?;
+    let bob_shares = vault_balance_of(&vault, &bob, &bob).await?;
+    assert_eq!(bob_shares.0, expected_shares.0);
+
+    let total_assets = vault_total_assets(&vault, &alice).await?;
+    assert_eq!(total_assets.0, 1500);
+    let available = vault_available_assets(&vault, &alice).await?;
+    assert_eq!(available.0, 900);
+
+    // Redeeming everything would dip into the reserve
+    let result = vault_redeem(&vault, &alice, 1000, None, None).await;
+    assert!(result.is_err(), "Should not withdraw reserved assets");
+    let error_message = result.unwrap_err().to_string();
+    assert!(
+        error_message.contains("Insufficient vault assets"),
+        "Expected insufficient assets error, got: {}",
+        error_message
+    );
+
+    let alice_shares = vault_balance_of(&vault, &alice, &alice).await?;
+    assert_eq!(alice_shares.0, 1000);
+
+    // Only the owner can reserve
+    let result = vault_reserve_assets(&vault, &alice, 100).await;
+    assert!(result.is_err(), "Only the owner should reserve assets");
+
+    Ok(())
+}
+
+/// Test that oversized memos are rejected on deposits and withdrawals
+#[tokio::test]
+async fn test_oversized_memo_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    let long_memo = "x".repeat(257);
+
+    // The deposit is refunded
+    let used = ft_transfer_call_deposit(
+        &usdt,
+        &vault,
+        &alice,
+        1000,
+        None,
+        None,
+        None,
+        Some(&long_memo),
+        None,
+    )
+    .await?;
+    assert_eq!(used.0, 0);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 10000);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 0);
+
+    // A memo at the limit is fine
+    let max_memo = "x".repeat(256);
+    ft_transfer_call_deposit(
+        &usdt,
+        &vault,
+        &alice,
+        1000,
+        None,
+        None,
+        None,
+        Some(&max_memo),
+        None,
+    )
+    .await?;
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1000);
+
+    let result = vault_withdraw(&vault, &alice, 100, None, Some(&long_memo)).await;
+    assert!(result.is_err(), "Should reject oversized withdraw memo");
+    assert!(result.unwrap_err().to_string().contains("Memo too long"));
+
+    let result = vault_redeem(&vault, &alice, 100, None, Some(&long_memo)).await;
+    assert!(result.is_err(), "Should reject oversized redeem memo");
+    assert!(result.unwrap_err().to_string().contains("Memo too long"));
+
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1000);
+
+    Ok(())
+}
+
+/// Test that memos with quotes, backslashes and non-ASCII characters survive the withdraw
+/// callback round-trip
+#[tokio::test]
+async fn test_withdraw_memo_with_special_characters() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    let memo = r#"quote " backslash \ "}, "injected": "x" ünïcödé ✓"#;
+    let outcome = vault_withdraw(&vault, &alice, 500, None, Some(memo)).await?;
+    assert!(!outcome.rolled_back);
+    assert_eq!(outcome.assets.0, 500);
+
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 9500);
+
+    Ok(())
+}
+
+/// Test that share transfers to unregistered accounts fail clearly unless storage is attached
+#[tokio::test]
+async fn test_share_transfer_to_unregistered_receiver() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts, bob isn't registered on the vault
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    let result =
+        vault_share_transfer(&vault, &alice, &bob, 100, NearToken::from_yoctonear(1)).await;
+    assert!(result.is_err(), "Should reject unregistered receiver");
+    let error_message = result.unwrap_err().to_string();
+    assert!(
+        error_message.contains("Receiver not registered on vault, call storage_deposit first"),
+        "Expected registration error, got: {}",
+        error_message
+    );
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1000);
+
+    // Attaching storage registers bob on the fly
+    vault_share_transfer(&vault, &alice, &bob, 100, NearToken::from_millinear(10)).await?;
+    assert_eq!(vault_balance_of(&vault, &bob, &bob).await?.0, 100);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 900);
+
+    Ok(())
+}
+
+/// Test that the minimum storage balance covers the storage an account's deposit takes
+#[tokio::test]
+async fn test_storage_minimum_covers_account_state() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    // Every state change also records a price observation, measure it through a donation
+    let usage_before_donation = vault.view_account().await?.storage_usage;
+    ft_transfer_call_deposit(
+        &usdt,
+        &vault,
+        &owner,
+        100,
+        None,
+        None,
+        None,
+        None,
+        Some(true),
+    )
+    .await?;
+    let usage_before = vault.view_account().await?.storage_usage;
+    let observation_usage = usage_before - usage_before_donation;
+
+    // Register with exactly the minimum, then deposit
+    let min = vault_storage_balance_bounds_min(&vault, &alice).await?;
+    vault_storage_deposit_exact(&vault, &alice, min).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    assert!(vault_balance_of(&vault, &alice, &alice).await?.0 > 0);
+
+    let account_usage =
+        vault.view_account().await?.storage_usage - usage_before - observation_usage;
+    let byte_cost = NearToken::from_yoctonear(10u128.pow(19));
+    assert!(
+        byte_cost.as_yoctonear() * account_usage as u128 <= min.as_yoctonear(),
+        "Account takes {} bytes, more than the minimum {} covers",
+        account_usage,
+        min
+    );
+
+    // Less than the minimum is rejected
+    let bob = worker.dev_create_account().await?;
+    let result = vault_storage_deposit_exact(
+        &vault,
+        &bob,
+        NearToken::from_yoctonear(min.as_yoctonear() - 1),
+    )
+    .await;
+    assert!(result.is_err(), "Should reject less than the minimum");
+
+    Ok(())
+}
+
+/// Test that converting assets to shares and back never yields more assets than it started with
+#[tokio::test]
+async fn test_conversion_round_trip_never_gains() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 3).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 100_000).await?;
+
+    let amounts = [0u128, 1, 2, 3, 7, 10, 99, 333, 1000, 1001, 12_345, 99_999];
+    let assert_round_trips = |vault, alice| async move {
+        for assets in amounts {
+            let shares = vault_convert_to_shares(vault, alice, assets).await?;
+            let back = vault_convert_to_assets(vault, alice, shares.0).await?;
+            assert!(
+                back.0 <= assets,
+                "{} assets round trip to {} through {} shares",
+                assets,
+                back.0,
+                shares.0
+            );
+        }
+        Ok::<(), Box<dyn std::error::Error>>(())
+    };
+
+    // Empty vault
+    assert_round_trips(&vault, &alice).await?;
+
+    // Uneven share price after a donation
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    ft_transfer_call_deposit(
+        &usdt,
+        &vault,
+        &alice,
+        337,
+        None,
+        None,
+        None,
+        None,
+        Some(true),
+    )
+    .await?;
+    assert_round_trips(&vault, &alice).await?;
+
+    // And after more deposits at that price
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 4321, None, None, None, None, None).await?;
+    assert_round_trips(&vault, &alice).await?;
+
+    Ok(())
+}
+
+/// Test the invariant self-check holds through deposits, withdrawals and a donation
+#[tokio::test]
+async fn test_invariants_hold() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    for account in [&alice, &bob] {
+        ft_storage_deposit(&usdt, account).await?;
+        vault_storage_deposit(&vault, account).await?;
+        ft_transfer(&usdt, &owner, account, 10000).await?;
+    }
+
+    let assert_invariants = |vault, account| async move {
+        let report = vault_check_invariants(vault, account).await?;
+        assert_eq!(report["all_hold"], true, "Invariants broken: {}", report);
+        Ok::<(), Box<dyn std::error::Error>>(())
+    };
+
+    assert_invariants(&vault, &alice).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &bob, 777, None, None, None, None, None).await?;
+    assert_invariants(&vault, &alice).await?;
+
+    vault_withdraw(&vault, &alice, 300, None, None).await?;
+    assert_invariants(&vault, &alice).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &bob, 123, None, None, None, None, Some(true)).await?;
+    assert_invariants(&vault, &alice).await?;
+
+    vault_reserve_assets(&vault, &owner, 200).await?;
+    vault_redeem(&vault, &bob, 400, None, None).await?;
+    assert_invariants(&vault, &alice).await?;
+
+    Ok(())
+}
+
+/// Test force-unregistering an account holding shares queues its assets rather than leaving
+/// them to the other holders
+#[tokio::test]
+async fn test_force_unregister_queues_assets() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    for account in [&alice, &bob] {
+        ft_storage_deposit(&usdt, account).await?;
+        vault_storage_deposit(&vault, account).await?;
+        ft_transfer(&usdt, &owner, account, 10000).await?;
+        ft_transfer_call_deposit(&usdt, &vault, account, 1000, None, None, None, None, None)
+            .await?;
+    }
+
+    // Without force the standard refuses to close an account holding shares
+    assert!(vault_storage_unregister(&vault, &alice, false)
+        .await
+        .is_err());
+
+    let bob_assets = vault_assets_of(&vault, &bob, &bob).await?.0;
+    assert!(vault_storage_unregister(&vault, &alice, true).await?);
+    assert_eq!(vault_balance_of(&vault, &bob, &alice).await?.0, 0);
+
+    // Alice's assets are queued for her, Bob's shares didn't absorb them
+    let claimable = vault_claimable_withdrawal(&vault, &bob, &alice).await?.0;
+    assert!(claimable >= 1000);
+    assert_eq!(vault_total_assets(&vault, &bob).await?.0 + claimable, 2000);
+    assert!(vault_assets_of(&vault, &bob, &bob).await?.0 <= bob_assets + 1);
+
+    assert_eq!(vault_process_withdrawals(&vault, &bob, 10).await?, 1);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 9000 + claimable);
+
+    // Once paid out nothing of Alice's record is left behind
+    assert!(vault_account_state_of(&vault, &bob, &alice)
+        .await?
+        .is_null());
+
+    Ok(())
+}
+
+/// Test registering several accounts in one call, each able to receive shares afterwards
+#[tokio::test]
+async fn test_storage_deposit_many() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+    let carol = worker.dev_create_account().await?;
+    let dave = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+    let min = vault_storage_balance_bounds_min(&vault, &owner).await?;
+
+    // Too little for three accounts
+    let err = vault_storage_deposit_many(
+        &vault,
+        &owner,
+        &[&bob, &carol, &dave],
+        min.saturating_mul(3)
+            .saturating_sub(NearToken::from_yoctonear(1)),
+    )
+    .await
+    .unwrap_err();
+    assert!(format!("{:?}", err).contains("less than the minimum storage balance"));
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    // Alice is already registered and left out, the surplus is refunded
+    vault_storage_deposit_many(
+        &vault,
+        &owner,
+        &[&alice, &bob, &carol, &dave],
+        NearToken::from_near(1),
+    )
+    .await?;
+
+    for receiver in [&bob, &carol, &dave] {
+        ft_transfer_call_deposit(
+            &usdt,
+            &vault,
+            &alice,
+            100,
+            Some(receiver),
+            None,
+            None,
+            None,
+            None,
+        )
+        .await?;
+        assert!(vault_balance_of(&vault, &alice, receiver).await?.0 > 0);
+    }
+
+    Ok(())
+}
+
+/// Test a holder can't withdraw the storage its shares need, registration is held at the minimum
+#[tokio::test]
+async fn test_storage_withdraw_keeps_holder_minimum() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    let min = vault_storage_balance_bounds_min(&vault, &alice).await?;
+    let result = vault_storage_withdraw(&vault, &alice, Some(min)).await;
+    assert!(format!("{:?}", result.unwrap_err())
+        .contains("The amount is greater than the available storage balance"));
+
+    // Withdrawing whatever is available leaves exactly the minimum
+    let (total, available) = vault_storage_withdraw(&vault, &alice, None).await?;
+    assert_eq!(total, min);
+    assert_eq!(available, NearToken::from_yoctonear(0));
+    assert!(vault_balance_of(&vault, &alice, &alice).await?.0 > 0);
+
+    Ok(())
+}
+
+/// Test that multi-token transfers are refused by a vault holding a fungible token
+#[tokio::test]
+async fn test_multi_token_deposit_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+
+    let result = vault_mt_on_transfer(&vault, &alice, 1000).await?;
+    assert!(result.is_failure());
+    assert!(format!("{:?}", result.failures()).contains("Vault expects fungible-token deposits"));
+    assert_eq!(vault_total_supply(&vault, &alice).await?.0, 0);
+
+    Ok(())
+}
+
+/// Test that a rollback racing another holder's redeem restores exactly what it took
+#[tokio::test]
+async fn test_concurrent_redeems_with_rollback() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+    let carol = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts, carol can't receive USDT
+    for account in [&alice, &bob] {
+        ft_storage_deposit(&usdt, account).await?;
+        vault_storage_deposit(&vault, account).await?;
+        ft_transfer(&usdt, &owner, account, 10000).await?;
+        ft_transfer_call_deposit(&usdt, &vault, account, 1000, None, None, None, None, None)
+            .await?;
+    }
+
+    // Both burn and debit total_assets before either transfer settles, alice's is rolled back
+    let (alice_outcome, bob_outcome) = tokio::join!(
+        vault_redeem(&vault, &alice, 1000, Some(&carol), None),
+        vault_redeem(&vault, &bob, 999, None, None),
+    );
+    assert!(alice_outcome?.rolled_back);
+    let bob_outcome = bob_outcome?;
+    assert!(!bob_outcome.rolled_back);
+
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1000);
+    assert_eq!(
+        vault_total_assets(&vault, &alice).await?.0,
+        2000 - bob_outcome.assets.0
+    );
+    assert_eq!(
+        ft_balance_of(&usdt, vault.as_account()).await?,
+        2000 - bob_outcome.assets.0
+    );
+    assert_eq!(
+        vault_check_invariants(&vault, &alice).await?["all_hold"],
+        true
+    );
+
+    Ok(())
+}
+
+/// Test the first deposit locks the dead shares in the vault so supply never returns to zero
+#[tokio::test]
+async fn test_dead_shares_on_first_deposit() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault_with_config(
+        &owner,
+        &usdt,
+        "USDT Vault",
+        "vUSDT",
+        0,
+        json!({ "dead_shares": "1000" }),
+    )
+    .await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 20000).await?;
+
+    let used = ft_transfer_call_deposit(&usdt, &vault, &alice, 10000, None, None, None, None, None)
+        .await?;
+    assert_eq!(used.0, 10000);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 9000);
+    assert_eq!(
+        vault_balance_of(&vault, &alice, vault.as_account())
+            .await?
+            .0,
+        1000
+    );
+
+    // Later deposits don't pay for dead shares
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    assert_eq!(vault_total_supply(&vault, &alice).await?.0, 10999);
+
+    // Redeeming everything alice holds leaves the dead shares
+    let shares = vault_balance_of(&vault, &alice, &alice).await?.0;
+    vault_redeem(&vault, &alice, shares, None, None).await?;
+    assert_eq!(vault_total_supply(&vault, &alice).await?.0, 1000);
+
+    Ok(())
+}
+
+/// Test that the vault's own shares sent to it through `ft_transfer_call` are refused and refunded
+#[tokio::test]
+async fn test_share_transfer_call_to_vault_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    let result = ft_transfer_call_with_msg(&vault, &vault, &alice, 500, "").await?;
+    assert!(format!("{:?}", result.receipt_failures())
+        .contains("Vault shares can't be deposited into the vault"));
+
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1000);
+    assert_eq!(
+        vault_balance_of(&vault, &alice, vault.as_account())
+            .await?
+            .0,
+        0
+    );
+    assert_eq!(vault_total_supply(&vault, &alice).await?.0, 1000);
+
+    Ok(())
+}
+
+/// Test the vault can't be initialized with non-compliant share metadata
+#[tokio::test]
+async fn test_invalid_metadata_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+
+    let result = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "", 0).await;
+    assert!(result.is_err());
+    assert!(format!("{:?}", result.err().unwrap()).contains("Name and symbol are required"));
+
+    Ok(())
+}
+
+/// Test a zero-amount ft_on_transfer from the asset is a clean no-op
+#[tokio::test]
+async fn test_zero_amount_ft_on_transfer() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    // The asset contract itself notifies the vault of an empty transfer
+    let result = usdt
+        .as_account()
+        .call(vault.id(), "ft_on_transfer")
+        .args_json(json!({
+            "sender_id": alice.id(),
+            "amount": "0",
+            "msg": "",
+        }))
+        .transact()
+        .await?
+        .into_result()?;
+    let unused: U128 = result.json()?;
+    assert_eq!(unused.0, 0);
+    assert!(result.logs().is_empty());
+
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1000);
+    assert_eq!(vault_total_supply(&vault, &alice).await?.0, 1000);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1000);
+
+    Ok(())
+}
+
+/// Test a dust deposit that would mint zero shares is refunded in full by default, and kept
+/// for the holders in the legacy mode
+#[tokio::test]
+async fn test_zero_share_deposit_refunded() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+
+    // A share is worth 2 assets, a single unit can't mint one
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    ft_transfer_call_deposit(
+        &usdt,
+        &vault,
+        &alice,
+        1000,
+        None,
+        None,
+        None,
+        None,
+        Some(true),
+    )
+    .await?;
+    assert_eq!(vault_convert_to_shares(&vault, &alice, 1).await?.0, 0);
+
+    let used =
+        ft_transfer_call_deposit(&usdt, &vault, &alice, 1, None, None, None, None, None).await?;
+    assert_eq!(used.0, 0);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 8000);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 2000);
+    assert_eq!(vault_total_supply(&vault, &alice).await?.0, 1000);
+
+    vault_set_reject_zero_share_deposits(&vault, &owner, false).await?;
+    let used =
+        ft_transfer_call_deposit(&usdt, &vault, &alice, 1, None, None, None, None, None).await?;
+    assert_eq!(used.0, 1);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 2001);
+    assert_eq!(vault_total_supply(&vault, &alice).await?.0, 1000);
+
+    Ok(())
+}
+
+/// Test that a failed leg of a split withdrawal only rolls back its own part
+#[tokio::test]
+async fn test_withdraw_split_leg_rollback() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+    let unregistered = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_storage_deposit(&usdt, &bob).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    // Weights have to add up to the assets withdrawn
+    let err = vault_withdraw_split(&vault, &alice, 600, &[(&bob, 400), (&unregistered, 100)])
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Weights must sum to assets"));
+
+    let burned =
+        vault_withdraw_split(&vault, &alice, 600, &[(&bob, 400), (&unregistered, 200)]).await?;
+    assert_eq!(burned.0, 600);
+
+    // Bob's leg went through, the leg to the unregistered account was rolled back
+    assert_eq!(ft_balance_of(&usdt, &bob).await?, 400);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 600);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 600);
+    assert_eq!(vault_total_supply(&vault, &alice).await?.0, 600);
+    assert_eq!(vault_available_assets(&vault, &alice).await?.0, 600);
+
+    Ok(())
+}
+
+/// Test a dust deposit for another receiver is refunded while the same deposit for oneself goes
+/// through
+#[tokio::test]
+async fn test_min_shares_per_receiver() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    for account in [&alice, &bob] {
+        ft_storage_deposit(&usdt, account).await?;
+        vault_storage_deposit(&vault, account).await?;
+        ft_transfer(&usdt, &owner, account, 10000).await?;
+    }
+    ft_transfer_call_deposit(&usdt, &vault, &bob, 1000, None, None, None, None, None).await?;
+    vault_set_min_shares_per_receiver(&vault, &owner, 100).await?;
+
+    let bob_shares = vault_balance_of(&vault, &bob, &bob).await?.0;
+    let used = ft_transfer_call_deposit(
+        &usdt,
+        &vault,
+        &alice,
+        10,
+        Some(&bob),
+        None,
+        None,
+        None,
+        None,
+    )
+    .await?;
+    assert_eq!(used.0, 0);
+    assert_eq!(vault_balance_of(&vault, &bob, &bob).await?.0, bob_shares);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 10000);
+
+    let used =
+        ft_transfer_call_deposit(&usdt, &vault, &alice, 10, None, None, None, None, None).await?;
+    assert_eq!(used.0, 10);
+    assert!(vault_balance_of(&vault, &alice, &alice).await?.0 > 0);
+
+    // At the threshold another receiver can be credited
+    let used = ft_transfer_call_deposit(
+        &usdt,
+        &vault,
+        &alice,
+        200,
+        Some(&bob),
+        None,
+        None,
+        None,
+        None,
+    )
+    .await?;
+    assert_eq!(used.0, 200);
+    assert!(vault_balance_of(&vault, &bob, &bob).await?.0 >= bob_shares + 100);
+
+    Ok(())
+}
+
+/// Test redeeming to a receiver without asset storage fails before any share is burned
+#[tokio::test]
+async fn test_redeem_checks_receiver_storage() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+    let unregistered = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    ft_storage_deposit(&usdt, &bob).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    vault_set_check_receiver_storage(&vault, &owner, true).await?;
+
+    let shares_before = vault_balance_of(&vault, &alice, &alice).await?.0;
+    let total_assets_before = vault_total_assets(&vault, &alice).await?.0;
+
+    // The unregistered receiver is caught by the pre-flight check
+    let err = vault_redeem(&vault, &alice, 500, Some(&unregistered), None)
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Receiver not registered with the asset"));
+    assert_eq!(
+        vault_balance_of(&vault, &alice, &alice).await?.0,
+        shares_before,
+        "No share should be burned"
+    );
+    assert_eq!(
+        vault_total_assets(&vault, &alice).await?.0,
+        total_assets_before
+    );
+
+    // A registered receiver still gets the assets
+    let outcome = vault_redeem(&vault, &alice, 500, Some(&bob), None).await?;
+    assert!(!outcome.rolled_back);
+    assert_eq!(ft_balance_of(&usdt, &bob).await?, outcome.assets.0);
+
+    Ok(())
+}
+
+/// Test redeem with too little gas reverts before any share is burned
+#[tokio::test]
+async fn test_redeem_with_insufficient_gas() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    let result = alice
+        .call(vault.id(), "redeem")
+        .args_json(json!({ "shares": "500" }))
+        .deposit(NearToken::from_yoctonear(1))
+        .gas(near_workspaces::types::Gas::from_tgas(40))
+        .transact()
+        .await?
+        .into_result();
+    assert!(format!("{:?}", result.unwrap_err()).contains("Insufficient gas attached"));
+
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1000);
+    assert_eq!(vault_total_supply(&vault, &alice).await?.0, 1000);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1000);
+
+    Ok(())
+}
+
+/// Test total_assets tracks the vault's asset balance to the unit through deposits with
+/// refunds, donations, withdrawals and rollbacks at a rate that forces rounding
+#[tokio::test]
+async fn test_total_assets_accounting_sequence() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+    let unregistered = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    for account in [&alice, &bob] {
+        ft_storage_deposit(&usdt, account).await?;
+        vault_storage_deposit(&vault, account).await?;
+        ft_transfer(&usdt, &owner, account, 10000).await?;
+    }
+
+    let vault_account = vault.as_account();
+    let assert_accounted = |step: &'static str| {
+        let (usdt, vault, vault_account, alice) = (&usdt, &vault, vault_account, &alice);
+        async move {
+            let report = vault_check_invariants(vault, alice).await?;
+            assert_eq!(report["all_hold"], true, "{}: {}", step, report);
+            assert_eq!(
+                vault_total_assets(vault, alice).await?.0,
+                ft_balance_of(usdt, vault_account).await?,
+                "{}: total_assets off the vault's balance",
+                step
+            );
+            Ok::<(), Box<dyn std::error::Error>>(())
+        }
+    };
+
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+    assert_accounted("first deposit").await?;
+
+    // A donation moves the rate off 1:1 so later conversions round
+    ft_transfer_call_deposit(&usdt, &vault, &bob, 337, None, None, None, None, Some(true)).await?;
+    assert_accounted("donation").await?;
+
+    ft_transfer_call_deposit(&usdt, &vault, &bob, 777, None, None, None, None, None).await?;
+    assert_accounted("deposit at a rounding rate").await?;
+
+    // Capped by max_shares, the rest is refunded
+    ft_transfer_call_deposit(&usdt, &vault, &bob, 500, None, None, Some(101), None, None).await?;
+    assert_accounted("partially used deposit").await?;
+
+    vault_withdraw(&vault, &alice, 333, None, None).await?;
+    assert_accounted("withdraw").await?;
+
+    vault_redeem(&vault, &bob, 199, None, None).await?;
+    assert_accounted("redeem").await?;
+
+    let outcome = vault_redeem(&vault, &alice, 100, Some(&unregistered), None).await?;
+    assert!(outcome.rolled_back);
+    assert_accounted("rolled back redeem").await?;
+
+    Ok(())
+}
@@ -0,0 +1,618 @@
+use near_sdk::NearToken;
+use serde_json::json;
+
+use crate::helper::{
+    events::parse_admin_actions,
+    fee_ft::deploy_and_init_fee_ft,
+    malicious_ft::{deploy_and_init_malicious_ft, malicious_ft_attack},
+    mock_ft::{deploy_and_init_mock_ft, ft_balance_of, ft_storage_deposit, ft_transfer},
+    vault::{
+        deploy_and_init_vault, deploy_and_init_vault_with_config, ft_transfer_call_deposit,
+        ft_transfer_call_with_msg, vault_balance_of, vault_check_invariants, vault_is_paused,
+        vault_pause, vault_redeem, vault_set_guardian, vault_set_performance_fee,
+        vault_set_strategy, vault_storage_deposit, vault_storage_unregister, vault_total_assets,
+        vault_total_supply, vault_unpause, vault_withdraw_stranded_near,
+    },
+};
+
+mod helper;
+
+/// Test that an asset re-entering `redeem` from its `ft_transfer` cannot be paid twice
+#[tokio::test]
+async fn test_reentrant_redeem_from_malicious_asset() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let evil = deploy_and_init_malicious_ft(&owner, 1_000_000u128).await?;
+    let vault = deploy_and_init_vault(&owner, &evil, "EVIL Vault", "vEVIL", 0).await?;
+
+    // Setup accounts, the malicious token contract holds shares on its own account
+    ft_storage_deposit(&evil, &alice).await?;
+    ft_storage_deposit(&evil, evil.as_account()).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    vault_storage_deposit(&vault, evil.as_account()).await?;
+    ft_transfer(&evil, &owner, &alice, 10000).await?;
+
+    // Alice provides the backing the attacker wants to steal
+    ft_transfer_call_deposit(&evil, &vault, &alice, 1000, None, None, None, None, None).await?;
+    ft_transfer_call_deposit(
+        &evil,
+        &vault,
+        &owner,
+        1000,
+        Some(evil.as_account()),
+        None,
+        None,
+        None,
+        None,
+    )
+    .await?;
+
+    let attacker_shares = vault_balance_of(&vault, &alice, evil.as_account()).await?.0;
+    assert_eq!(attacker_shares, 999);
+
+    let result = malicious_ft_attack(&evil, &owner, &vault, attacker_shares).await?;
+
+    // The re-entrant redeem must fail, either on the lock or on the already burned shares
+    let failures = format!("{:?}", result.receipt_failures());
+    assert!(
+        failures.contains("Withdrawal already in progress")
+            || failures.contains("Exceeds max redeem"),
+        "Re-entrant redeem should be rejected, got: {}",
+        failures
+    );
+
+    // Only one redemption was paid out
+    let attacker_assets = ft_balance_of(&evil, evil.as_account()).await?;
+    assert_eq!(attacker_assets, 999);
+
+    let vault_assets = ft_balance_of(&evil, vault.as_account()).await?;
+    assert_eq!(vault_assets, 2000 - attacker_assets);
+
+    let attacker_shares_after = vault_balance_of(&vault, &alice, evil.as_account()).await?;
+    assert_eq!(attacker_shares_after.0, 0);
+
+    let total_supply = vault_total_supply(&vault, &alice).await?;
+    assert_eq!(total_supply.0, 1000);
+
+    let total_assets = vault_total_assets(&vault, &alice).await?;
+    assert_eq!(total_assets.0, vault_assets);
+
+    Ok(())
+}
+
+/// Test that a re-entrant redeem the attacker's shares could still cover is stopped by the
+/// withdrawal lock alone
+#[tokio::test]
+async fn test_reentrant_redeem_blocked_by_withdrawal_lock() -> Result<(), Box<dyn std::error::Error>>
+{
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let evil = deploy_and_init_malicious_ft(&owner, 1_000_000u128).await?;
+    let vault = deploy_and_init_vault(&owner, &evil, "EVIL Vault", "vEVIL", 0).await?;
+
+    // Setup accounts, the malicious token contract holds shares on its own account
+    ft_storage_deposit(&evil, &alice).await?;
+    ft_storage_deposit(&evil, evil.as_account()).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    vault_storage_deposit(&vault, evil.as_account()).await?;
+    ft_transfer(&evil, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(&evil, &vault, &alice, 1000, None, None, None, None, None).await?;
+    ft_transfer_call_deposit(
+        &evil,
+        &vault,
+        &owner,
+        1000,
+        Some(evil.as_account()),
+        None,
+        None,
+        None,
+        None,
+    )
+    .await?;
+    assert_eq!(
+        vault_balance_of(&vault, &alice, evil.as_account()).await?.0,
+        999
+    );
+
+    // Few enough shares that the balance left would cover the re-entrant redeem
+    let result = malicious_ft_attack(&evil, &owner, &vault, 400).await?;
+    let failures = format!("{:?}", result.receipt_failures());
+    assert!(
+        failures.contains("Withdrawal already in progress"),
+        "Re-entrant redeem should hit the lock, got: {}",
+        failures
+    );
+
+    // Exactly one redemption went through
+    assert_eq!(ft_balance_of(&evil, evil.as_account()).await?, 400);
+    assert_eq!(
+        vault_balance_of(&vault, &alice, evil.as_account()).await?.0,
+        599
+    );
+    assert_eq!(vault_total_supply(&vault, &alice).await?.0, 1599);
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1600);
+    assert_eq!(ft_balance_of(&evil, vault.as_account()).await?, 1600);
+
+    Ok(())
+}
+
+/// Test that a fee-on-transfer asset only credits what the vault actually received
+#[tokio::test]
+async fn test_verify_received_with_fee_on_transfer_asset() -> Result<(), Box<dyn std::error::Error>>
+{
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    // 1% of every ft_transfer_call is burned from the receiver
+    let fee_token = deploy_and_init_fee_ft(&owner, 1_000_000u128, 100).await?;
+    let vault = deploy_and_init_vault_with_config(
+        &owner,
+        &fee_token,
+        "FEE Vault",
+        "vFEE",
+        0,
+        json!({ "verify_received": true }),
+    )
+    .await?;
+
+    // Setup accounts
+    ft_storage_deposit(&fee_token, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&fee_token, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(
+        &fee_token, &vault, &alice, 1000, None, None, None, None, None,
+    )
+    .await?;
+
+    // Only the 990 that arrived are credited
+    let total_assets = vault_total_assets(&vault, &alice).await?;
+    assert_eq!(total_assets.0, 990);
+
+    let alice_shares = vault_balance_of(&vault, &alice, &alice).await?;
+    assert_eq!(alice_shares.0, 990);
+
+    let vault_balance = ft_balance_of(&fee_token, vault.as_account()).await?;
+    assert_eq!(total_assets.0, vault_balance);
+
+    // A second deposit is measured against the already tracked assets
+    ft_transfer_call_deposit(
+        &fee_token, &vault, &alice, 1000, None, None, None, None, None,
+    )
+    .await?;
+
+    let total_assets = vault_total_assets(&vault, &alice).await?;
+    assert_eq!(total_assets.0, 1980);
+
+    let vault_balance = ft_balance_of(&fee_token, vault.as_account()).await?;
+    assert_eq!(total_assets.0, vault_balance);
+
+    Ok(())
+}
+
+/// Test that assets sent with a plain transfer aren't credited to the next verified deposits
+#[tokio::test]
+async fn test_verify_received_ignores_untracked_balance() -> Result<(), Box<dyn std::error::Error>>
+{
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    // 1% of every ft_transfer_call is burned from the receiver
+    let fee_token = deploy_and_init_fee_ft(&owner, 1_000_000u128, 100).await?;
+    let vault = deploy_and_init_vault_with_config(
+        &owner,
+        &fee_token,
+        "FEE Vault",
+        "vFEE",
+        0,
+        json!({ "verify_received": true }),
+    )
+    .await?;
+
+    // Setup accounts
+    ft_storage_deposit(&fee_token, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&fee_token, &owner, &alice, 10000).await?;
+
+    ft_transfer_call_deposit(
+        &fee_token, &vault, &alice, 1000, None, None, None, None, None,
+    )
+    .await?;
+
+    // Plain transfers are fee-free and never reach ft_on_transfer
+    ft_transfer(&fee_token, &owner, vault.as_account(), 500).await?;
+
+    // The deposit can't tell the untracked 500 from its own, but takes no more than it was sent
+    ft_transfer_call_deposit(
+        &fee_token, &vault, &alice, 1000, None, None, None, None, None,
+    )
+    .await?;
+    let total_assets = vault_total_assets(&vault, &alice).await?;
+    assert_eq!(total_assets.0, 1990);
+
+    // The 490 left untracked were seen by the last query and stay out of the next deposit
+    ft_transfer_call_deposit(
+        &fee_token, &vault, &alice, 1000, None, None, None, None, None,
+    )
+    .await?;
+    let total_assets = vault_total_assets(&vault, &alice).await?;
+    assert_eq!(total_assets.0, 2980);
+
+    let vault_balance = ft_balance_of(&fee_token, vault.as_account()).await?;
+    assert_eq!(vault_balance, 2980 + 490);
+
+    Ok(())
+}
+
+/// Test the invariant report catches a vault crediting more than it holds, when a fee-on-transfer
+/// asset is deposited without verifying what was received
+#[tokio::test]
+async fn test_check_invariants_against_asset_balance() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    // 1% of every ft_transfer_call is burned from the receiver
+    let fee_token = deploy_and_init_fee_ft(&owner, 1_000_000u128, 100).await?;
+    let vault = deploy_and_init_vault(&owner, &fee_token, "FEE Vault", "vFEE", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&fee_token, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&fee_token, &owner, &alice, 10000).await?;
+
+    let report = vault_check_invariants(&vault, &alice).await?;
+    assert_eq!(report["all_hold"], true, "{}", report);
+
+    // 1000 credited, 990 held
+    ft_transfer_call_deposit(
+        &fee_token, &vault, &alice, 1000, None, None, None, None, None,
+    )
+    .await?;
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1000);
+    let report = vault_check_invariants(&vault, &alice).await?;
+    assert_eq!(report["assets_held"], false, "{}", report);
+    assert_eq!(report["supply_backed"], true, "{}", report);
+    assert_eq!(report["all_hold"], false, "{}", report);
+
+    Ok(())
+}
+
+/// Test that a verified deposit staking into the wrong farm is refused before its balance query
+#[tokio::test]
+async fn test_verify_received_rejects_stake_to_upfront() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    // 1% of every ft_transfer_call is burned from the receiver
+    let fee_token = deploy_and_init_fee_ft(&owner, 1_000_000u128, 100).await?;
+    let vault = deploy_and_init_vault_with_config(
+        &owner,
+        &fee_token,
+        "FEE Vault",
+        "vFEE",
+        0,
+        json!({ "verify_received": true }),
+    )
+    .await?;
+
+    // Setup accounts
+    ft_storage_deposit(&fee_token, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&fee_token, &owner, &alice, 10000).await?;
+
+    let msg = json!({ "action": "deposit", "stake_to": alice.id() }).to_string();
+    let result = ft_transfer_call_with_msg(&fee_token, &vault, &alice, 1000, &msg).await?;
+    assert!(format!("{:?}", result.failures()).contains("Not the configured farm"));
+    assert_eq!(ft_balance_of(&fee_token, &alice).await?, 9990);
+
+    // No verification was started, the next deposit isn't locked out
+    ft_transfer_call_deposit(
+        &fee_token, &vault, &alice, 1000, None, None, None, None, None,
+    )
+    .await?;
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 990);
+    assert_eq!(ft_balance_of(&fee_token, vault.as_account()).await?, 990);
+
+    Ok(())
+}
+
+/// Test that calling `new` again cannot reset an initialized vault
+#[tokio::test]
+async fn test_new_cannot_reinitialize() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    // Alice tries to take the vault over by initializing it again
+    let result = alice
+        .call(vault.id(), "new")
+        .args_json(json!({
+            "asset": usdt.id(),
+            "metadata": {
+                "spec": "ft-1.0.0",
+                "name": "Hijacked Vault",
+                "symbol": "vHJK",
+                "decimals": 6,
+            },
+            "extra_decimals": 0,
+        }))
+        .transact()
+        .await?;
+    assert!(result.is_failure());
+    assert!(format!("{:?}", result.failures()).contains("initialized"));
+
+    // Nothing was reset
+    assert_eq!(vault_total_assets(&vault, &alice).await?.0, 1000);
+    assert_eq!(vault_balance_of(&vault, &alice, &alice).await?.0, 1000);
+
+    Ok(())
+}
+
+/// Test owner-only methods reject any other caller
+#[tokio::test]
+async fn test_owner_only_method_rejects_non_owner() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    let err = vault_set_strategy(&vault, &alice, Some(&alice))
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Not owner"));
+
+    vault_set_strategy(&vault, &owner, Some(&alice)).await?;
+
+    Ok(())
+}
+
+/// Test only the owner can pause, and pausing halts deposits and withdrawals
+#[tokio::test]
+async fn test_owner_and_pause_guards() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    let err = vault_pause(&vault, &alice).await.unwrap_err();
+    assert!(format!("{:?}", err).contains("Not owner"));
+
+    vault_pause(&vault, &owner).await?;
+
+    // Deposits are refunded and withdrawals rejected while paused
+    let used =
+        ft_transfer_call_deposit(&usdt, &vault, &alice, 500, None, None, None, None, None).await?;
+    assert_eq!(used.0, 0);
+    assert_eq!(ft_balance_of(&usdt, &alice).await?, 9000);
+    let err = vault_redeem(&vault, &alice, 100, None, None)
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Vault is paused"));
+    let err = vault_storage_unregister(&vault, &alice, true)
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Vault is paused"));
+
+    let err = vault_unpause(&vault, &alice).await.unwrap_err();
+    assert!(format!("{:?}", err).contains("Not owner"));
+    vault_unpause(&vault, &owner).await?;
+
+    let outcome = vault_redeem(&vault, &alice, 100, None, None).await?;
+    assert_eq!(outcome.shares.0, 100);
+
+    Ok(())
+}
+
+/// Test owner methods require the one yocto deposit, even when called by the owner
+#[tokio::test]
+async fn test_admin_methods_require_one_yocto() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    let calls = [
+        ("pause", json!({})),
+        (
+            "set_total_assets_cap",
+            json!({ "total_assets_cap": "1000" }),
+        ),
+        (
+            "set_performance_fee",
+            json!({ "performance_fee_bps": 1000, "fee_recipient": owner.id() }),
+        ),
+    ];
+    for (method, args) in calls {
+        let err = owner
+            .call(vault.id(), method)
+            .args_json(args)
+            .transact()
+            .await?
+            .into_result()
+            .unwrap_err();
+        assert!(
+            format!("{:?}", err).contains("Requires attached deposit of exactly 1 yoctoNEAR"),
+            "{} should require one yocto",
+            method
+        );
+    }
+
+    assert!(!vault_is_paused(&vault, &owner).await?);
+
+    Ok(())
+}
+
+/// Test the guardian can pause the vault but can't unpause it or change its parameters
+#[tokio::test]
+async fn test_guardian_can_only_pause() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let guardian = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    vault_storage_deposit(&vault, &guardian).await?;
+
+    let err = vault_pause(&vault, &guardian).await.unwrap_err();
+    assert!(format!("{:?}", err).contains("Not owner or guardian"));
+
+    vault_set_guardian(&vault, &owner, Some(&guardian)).await?;
+    vault_pause(&vault, &guardian).await?;
+    assert!(vault_is_paused(&vault, &guardian).await?);
+
+    let err = vault_unpause(&vault, &guardian).await.unwrap_err();
+    assert!(format!("{:?}", err).contains("Not owner"));
+    let err = vault_set_performance_fee(&vault, &guardian, 1000, &guardian)
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Not owner"));
+    let err = vault_set_guardian(&vault, &guardian, None)
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Not owner"));
+    assert!(vault_is_paused(&vault, &guardian).await?);
+
+    vault_unpause(&vault, &owner).await?;
+    assert!(!vault_is_paused(&vault, &guardian).await?);
+
+    Ok(())
+}
+
+/// Test owner-only actions log an audit event with their caller and arguments
+#[tokio::test]
+async fn test_admin_actions_audit_log() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let treasury = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    let calls = [
+        ("pause", json!({})),
+        (
+            "set_performance_fee",
+            json!({ "performance_fee_bps": 1000, "fee_recipient": treasury.id() }),
+        ),
+        (
+            "set_max_deposit_per_tx",
+            json!({ "max_deposit_per_tx": "5000" }),
+        ),
+        ("unpause", json!({})),
+    ];
+    let mut logs = Vec::new();
+    for (method, args) in &calls {
+        let result = owner
+            .call(vault.id(), method)
+            .args_json(args)
+            .deposit(NearToken::from_yoctonear(1))
+            .transact()
+            .await?;
+        logs.extend(result.logs().into_iter().map(String::from));
+        result.into_result()?;
+    }
+
+    let actions = parse_admin_actions(&logs);
+    assert_eq!(actions.len(), calls.len());
+    for (action, (method, args)) in actions.iter().zip(&calls) {
+        assert_eq!(action.action, *method);
+        assert_eq!(action.caller.as_str(), owner.id().as_str());
+        assert!(action.timestamp.0 > 0);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&action.args_json)?,
+            *args
+        );
+    }
+
+    // Rejected calls leave no trace
+    let result = treasury
+        .call(vault.id(), "pause")
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?;
+    let logs: Vec<String> = result.logs().into_iter().map(String::from).collect();
+    assert!(result.into_result().is_err());
+    assert!(parse_admin_actions(&logs).is_empty());
+
+    Ok(())
+}
+
+/// Test NEAR sent to the vault by mistake can be reclaimed by the owner, down to the storage
+/// the vault needs plus its buffer
+#[tokio::test]
+async fn test_withdraw_stranded_near() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let treasury = worker.dev_create_account().await?;
+
+    let usdt = deploy_and_init_mock_ft(&owner, "Tether USD", "USDT", Some(1_000_000u128)).await?;
+    let vault = deploy_and_init_vault(&owner, &usdt, "USDT Vault", "vUSDT", 0).await?;
+
+    // Setup accounts
+    ft_storage_deposit(&usdt, &alice).await?;
+    vault_storage_deposit(&vault, &alice).await?;
+    ft_transfer(&usdt, &owner, &alice, 10000).await?;
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 1000, None, None, None, None, None).await?;
+
+    alice
+        .transfer_near(vault.id(), NearToken::from_near(5))
+        .await?
+        .into_result()?;
+
+    let err = vault_withdraw_stranded_near(&vault, &alice, &alice)
+        .await
+        .unwrap_err();
+    assert!(format!("{:?}", err).contains("Not owner"));
+
+    let treasury_before = treasury.view_account().await?.balance;
+    let stranded = vault_withdraw_stranded_near(&vault, &owner, &treasury)
+        .await?
+        .0;
+    assert!(stranded >= NearToken::from_near(5).as_yoctonear());
+    assert_eq!(
+        treasury.view_account().await?.balance.as_yoctonear() - treasury_before.as_yoctonear(),
+        stranded
+    );
+
+    // The storage stake and buffer stay, deposits and withdrawals keep working
+    let vault_account = vault.view_account().await?;
+    let storage_stake = vault_account.storage_usage as u128 * 10u128.pow(19);
+    assert!(
+        vault_account.balance.as_yoctonear()
+            >= storage_stake + NearToken::from_near(1).as_yoctonear()
+    );
+    ft_transfer_call_deposit(&usdt, &vault, &alice, 500, None, None, None, None, None).await?;
+    vault_redeem(&vault, &alice, 100, None, None).await?;
+
+    Ok(())
+}